@@ -0,0 +1,354 @@
+//!
+//! Animated tiles at runtime.
+//!
+//! Tile::get_anim() finds the frame of an animation from scratch on every call.
+//! AnimationClock instead builds the timeline of every animated tile of a map
+//! once, then advances with the time elapsed since the last frame and tells
+//! which animations changed frame during that tick.
+//!
+//! Animations can play in two ways, chosen per layer with AnimationSync:
+//! - Global: every cell showing the same animated tile shows the same frame,
+//!   like Tiled does.
+//! - PerCell: every cell plays its animation with its own phase offset, so a
+//!   field of animated grass or water does not move in lockstep.
+//!
+//! LayerAnimation keeps the frame shown in every cell of a tile layer and
+//! reports the cells whose frame changed on each update.
+//!
+//! Gids given to and returned by these functions keep their flip flags: the
+//! frame of a flipped animated tile is flipped the same way.
+//!
+
+use std::collections::HashMap;
+
+use crate::layer::Layer;
+use crate::map::Map;
+
+const FLAGS: u32 = crate::HORZ_FLIP_FLAG | crate::VERT_FLIP_FLAG | crate::DIAG_FLIP_FLAG;
+
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// AnimationSync selects how the cells of a layer showing the same animated
+/// tile are timed:
+/// - Global: all of them show the same frame.
+/// - PerCell: each cell starts its animation at its own offset.
+pub enum AnimationSync {
+    Global,
+    PerCell,
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The frames of one animated tile.  gid is the gid of the animated tile, and
+/// each frame is (gid of the frame, start time in milliseconds within the
+/// loop).  duration is the length of the loop in milliseconds.
+pub struct AnimationTimeline {
+    pub gid: u32,
+    pub frames: Vec<(u32, u64)>,
+    pub duration: u64,
+}
+
+impl AnimationTimeline {
+    /// The gid of the animated tile.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Borrow the frames: (gid of the frame, start time within the loop).
+    pub fn frames(&self) -> &Vec<(u32, u64)> {
+        &self.frames
+    }
+
+    /// The length of the loop in milliseconds.
+    pub fn duration(&self) -> u64 {
+        self.duration
+    }
+
+    /// The gid of the frame shown at time milliseconds, without flags.
+    pub fn frame_at(&self, milliseconds: u64) -> u32 {
+        let t = milliseconds % self.duration;
+        let next = self.frames.partition_point(|f| f.1 <= t);
+        self.frames[next - 1].0
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A clock driving every animated tile of a map.  time counts the milliseconds
+/// elapsed since the clock was created.
+pub struct AnimationClock {
+    pub time: u64,
+    pub timelines: HashMap<u32, AnimationTimeline>,
+}
+
+impl AnimationClock {
+    /// Build the timelines of every animated tile of the map, starting at time 0.
+    ///
+    /// Animations whose frames all last 0 milliseconds are ignored.
+    pub fn new(map: &Map) -> AnimationClock {
+        let mut timelines = HashMap::new();
+        for ts in map.tilesets.iter() {
+            for tile in ts.tiles.iter() {
+                let mut frames = Vec::with_capacity(tile.animation.len());
+                let mut duration: u64 = 0;
+                for f in tile.animation.iter().filter(|f| f.duration > 0) {
+                    frames.push((ts.firstgid + f.tileid as u32, duration));
+                    duration += f.duration as u64;
+                }
+                if duration > 0 {
+                    let gid = ts.firstgid + tile.id as u32;
+                    timelines.insert(
+                        gid,
+                        AnimationTimeline {
+                            gid,
+                            frames,
+                            duration,
+                        },
+                    );
+                }
+            }
+        }
+        AnimationClock { time: 0, timelines }
+    }
+
+    /// Milliseconds elapsed since the clock was created.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Get the timeline of an animated tile.  Flags on the gid are ignored.
+    pub fn timeline(&self, gid: u32) -> Option<&AnimationTimeline> {
+        self.timelines.get(&crate::gid_without_flags(gid))
+    }
+
+    /// Is the tile of this gid animated?
+    pub fn is_animated(&self, gid: u32) -> bool {
+        self.timeline(gid).is_some()
+    }
+
+    /// Advance the clock by milliseconds, returning the gids of the animated
+    /// tiles (without flags) whose globally synchronized frame changed, sorted.
+    pub fn advance(&mut self, milliseconds: u32) -> Vec<u32> {
+        let before = self.time;
+        self.time += milliseconds as u64;
+        let mut changed: Vec<u32> = self
+            .timelines
+            .values()
+            .filter(|t| t.frame_at(before) != t.frame_at(self.time))
+            .map(|t| t.gid)
+            .collect();
+        changed.sort_unstable();
+        changed
+    }
+
+    /// The gid to draw for a gid right now, flags kept.  Gids of tiles that are
+    /// not animated are returned unchanged.
+    pub fn frame(&self, gid: u32) -> u32 {
+        self.frame_with_offset(gid, 0)
+    }
+
+    /// The gid to draw for a gid with its animation started offset milliseconds
+    /// early, flags kept.
+    pub fn frame_with_offset(&self, gid: u32, offset: u64) -> u32 {
+        match self.timeline(gid) {
+            Option::Some(t) => t.frame_at(self.time + offset) | (gid & FLAGS),
+            Option::None => gid,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The animation state of a tile layer: the phase offset of every cell in
+/// milliseconds and the gid currently shown in it, indexed like tile layer data
+/// (y * width + x).
+pub struct LayerAnimation {
+    pub width: u32,
+    pub height: u32,
+    pub sync: AnimationSync,
+    pub offsets: Vec<u64>,
+    pub frames: Vec<u32>,
+}
+
+impl LayerAnimation {
+    /// Create the animation state of a tile layer at the current time of clock.
+    ///
+    /// With AnimationSync::PerCell, every cell receives an offset derived from
+    /// its position, so the same map always animates the same way.  Offsets
+    /// can be changed with set_offset().
+    pub fn new(
+        map: &Map,
+        layer: &Layer,
+        clock: &AnimationClock,
+        sync: AnimationSync,
+    ) -> LayerAnimation {
+        let width = map.width as u32;
+        let height = map.height as u32;
+        let offsets = (0..width * height)
+            .map(|i| match sync {
+                AnimationSync::Global => 0,
+                AnimationSync::PerCell => cell_offset(i % width, i / width),
+            })
+            .collect();
+        let mut anim = LayerAnimation {
+            width,
+            height,
+            sync,
+            offsets,
+            frames: vec![0; (width * height) as usize],
+        };
+        anim.update(layer, clock);
+        anim
+    }
+
+    /// The way cells are timed.
+    pub fn sync(&self) -> AnimationSync {
+        self.sync
+    }
+
+    /// The gid shown in a cell, flags kept; 0 for empty cells and cells outside
+    /// the layer.
+    pub fn frame(&self, x: u32, y: u32) -> u32 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        self.frames[(y * self.width + x) as usize]
+    }
+
+    /// Borrow the gids shown in every cell.
+    pub fn frames(&self) -> &Vec<u32> {
+        &self.frames
+    }
+
+    /// Change the phase offset of a cell.  Cells outside the layer are ignored.
+    pub fn set_offset(&mut self, x: u32, y: u32, milliseconds: u64) {
+        if x < self.width && y < self.height {
+            self.offsets[(y * self.width + x) as usize] = milliseconds;
+        }
+    }
+
+    /// Bring every cell up to date with the data of the layer and the time of
+    /// clock, returning the cells whose shown gid changed, row by row.
+    ///
+    /// Since the layer is read again, cells edited since the last update are
+    /// reported too.
+    pub fn update(&mut self, layer: &Layer, clock: &AnimationClock) -> Vec<(u32, u32)> {
+        let data = match layer.get_data() {
+            Option::Some(d) => d,
+            Option::None => return Vec::new(),
+        };
+        let mut changed = Vec::new();
+        for (i, shown) in self.frames.iter_mut().enumerate() {
+            let gid = data.get(i).copied().unwrap_or(0);
+            let frame = clock.frame_with_offset(gid, self.offsets[i]);
+            if *shown != frame {
+                *shown = frame;
+                changed.push((i as u32 % self.width, i as u32 / self.width));
+            }
+        }
+        changed
+    }
+}
+
+// A phase offset in milliseconds for a cell, scattered over about a minute so
+// neighbouring cells rarely share a phase.
+fn cell_offset(x: u32, y: u32) -> u64 {
+    let mut h = x.wrapping_mul(0x9E37_79B9) ^ y.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    (h % 60_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnimationClock;
+    use super::AnimationSync;
+    use super::LayerAnimation;
+    use crate::map::Map;
+
+    // Gid 1 plays gids 1 then 2 for 100 and 200 ms.  Gid 3 plays gids 3 then 4
+    // for 40 s each, 80 s in all, more than a u16 of milliseconds holds.
+    fn map() -> Map {
+        serde_json::from_str(
+            r#"{
+                "orientation": "orthogonal", "width": 3, "height": 1,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 2,
+                "tilesets": [{
+                    "firstgid": 1, "name": "water", "image": "water.png",
+                    "imagewidth": 64, "imageheight": 16, "tilewidth": 16, "tileheight": 16,
+                    "tilecount": 4, "columns": 4,
+                    "tiles": [
+                        { "id": 0, "animation": [
+                            { "tileid": 0, "duration": 100 },
+                            { "tileid": 1, "duration": 200 } ] },
+                        { "id": 2, "animation": [
+                            { "tileid": 2, "duration": 40000 },
+                            { "tileid": 3, "duration": 40000 } ] }
+                    ]
+                }],
+                "layers": [{ "type": "tilelayer", "id": 1, "name": "water",
+                             "width": 3, "height": 1, "data": [1, 2, 2147483649] }]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn frames_wrap_around_at_the_total_duration() {
+        let clock = AnimationClock::new(&map());
+        let timeline = clock.timeline(1).unwrap();
+        assert_eq!(timeline.duration(), 300);
+        let frames: Vec<u32> = [0, 99, 100, 299, 300, 400, 600]
+            .iter()
+            .map(|t| timeline.frame_at(*t))
+            .collect();
+        assert_eq!(frames, vec![1, 1, 2, 2, 1, 2, 1]);
+        assert!(!clock.is_animated(2));
+    }
+
+    #[test]
+    fn animations_longer_than_a_u16_of_milliseconds() {
+        let map = map();
+        let clock = AnimationClock::new(&map);
+        let timeline = clock.timeline(3).unwrap();
+        assert_eq!(timeline.duration(), 80_000);
+        assert_eq!(timeline.frame_at(79_999), 4);
+        assert_eq!(timeline.frame_at(80_000), 3);
+
+        let tile = &map.tilesets[0].tiles[1];
+        assert_eq!(tile.get_anim(70_000), (true, 3));
+        assert_eq!(tile.get_anim(120_000), (true, 3));
+        assert_eq!(tile.get_anim(160_000), (true, 2));
+    }
+
+    #[test]
+    fn advance_reports_changed_animations_and_keeps_flags() {
+        let map = map();
+        let mut clock = AnimationClock::new(&map);
+        let flipped = crate::HORZ_FLIP_FLAG | 1;
+        assert_eq!(clock.advance(50), Vec::<u32>::new());
+        assert_eq!(clock.advance(50), vec![1]);
+        assert_eq!(clock.frame(flipped), crate::HORZ_FLIP_FLAG | 2);
+        assert_eq!(clock.frame(2), 2);
+        assert_eq!(clock.advance(40_100), vec![1, 3]);
+    }
+
+    #[test]
+    fn layer_animation_reports_changed_cells() {
+        let map = map();
+        let mut clock = AnimationClock::new(&map);
+        let mut anim = LayerAnimation::new(&map, &map.layers[0], &clock, AnimationSync::Global);
+        assert_eq!(anim.frames(), &vec![1, 2, crate::HORZ_FLIP_FLAG | 1]);
+
+        clock.advance(100);
+        let changed = anim.update(&map.layers[0], &clock);
+        assert_eq!(changed, vec![(0, 0), (2, 0)]);
+        assert_eq!(anim.frame(2, 0), crate::HORZ_FLIP_FLAG | 2);
+
+        // A cell played 200 ms ahead is back on its first frame.
+        anim.set_offset(0, 0, 200);
+        assert_eq!(anim.update(&map.layers[0], &clock), vec![(0, 0)]);
+        assert_eq!(anim.frame(0, 0), 1);
+    }
+}
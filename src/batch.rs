@@ -0,0 +1,260 @@
+//!
+//! Sprite-batch quads for tile layers.
+//!
+//! Engines draw tile layers as one textured quad per tile.  layer_quads() turns
+//! a tile layer into those quads, in the order the map says tiles must be drawn,
+//! with everything an engine would otherwise compute per tile:
+//! - The destination rectangle in pixels, for every MapOrientation, with tiles
+//!   taller than the map grid sticking out of the top of their cell, and the
+//!   TileOffset of the tileset and the offset of the layer applied.
+//! - The index of the tileset in Map::tilesets(), which selects the texture.
+//! - The texture coordinates of each corner, normalized to the tileset image
+//!   (or to the tile image for tilesets made of separate images), with the
+//!   horizontal, vertical and diagonal flips of the gid already applied.
+//!
+//! Quad::vertices() lays a quad out as two triangles of x, y, u, v floats,
+//! ready for a vertex buffer.
+//!
+//! Offsets of the groups holding the layer are not applied.
+//!
+
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::object::Point;
+
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// One textured quad drawing the tile of a cell.
+///
+/// x, y, width and height give the destination rectangle in pixels.  uvs are
+/// the texture coordinates of its top-left, top-right, bottom-right and
+/// bottom-left corners, in that order.  gid is the gid of the cell, flags
+/// included.
+pub struct Quad {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub uvs: [Point; 4],
+    pub tileset: usize,
+    pub gid: u32,
+    pub cell: (u32, u32),
+}
+
+impl Quad {
+    /// The corners of the destination rectangle: top-left, top-right,
+    /// bottom-right and bottom-left, matching uvs.
+    pub fn positions(&self) -> [Point; 4] {
+        [
+            Point::new(self.x, self.y),
+            Point::new(self.x + self.width, self.y),
+            Point::new(self.x + self.width, self.y + self.height),
+            Point::new(self.x, self.y + self.height),
+        ]
+    }
+
+    /// Borrow the texture coordinates of the corners.
+    pub fn uvs(&self) -> &[Point; 4] {
+        &self.uvs
+    }
+
+    /// The texture rectangle of the quad, flips ignored: (left, top, right,
+    /// bottom) in normalized coordinates.
+    pub fn uv_rect(&self) -> (f64, f64, f64, f64) {
+        let us = self.uvs.iter().map(|p| p.x);
+        let vs = self.uvs.iter().map(|p| p.y);
+        (
+            us.clone().fold(f64::INFINITY, f64::min),
+            vs.clone().fold(f64::INFINITY, f64::min),
+            us.fold(f64::NEG_INFINITY, f64::max),
+            vs.fold(f64::NEG_INFINITY, f64::max),
+        )
+    }
+
+    /// The index of the tileset of the tile in Map::tilesets().
+    pub fn tileset(&self) -> usize {
+        self.tileset
+    }
+
+    /// The gid of the cell, flags included.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// The cell drawn by the quad.
+    pub fn cell(&self) -> (u32, u32) {
+        self.cell
+    }
+
+    /// The quad as two clockwise triangles (top-left, top-right, bottom-right,
+    /// then top-left, bottom-right, bottom-left) of [x, y, u, v] vertices.
+    pub fn vertices(&self) -> [[f32; 4]; 6] {
+        let p = self.positions();
+        let vertex = |i: usize| {
+            [
+                p[i].x as f32,
+                p[i].y as f32,
+                self.uvs[i].x as f32,
+                self.uvs[i].y as f32,
+            ]
+        };
+        [
+            vertex(0),
+            vertex(1),
+            vertex(2),
+            vertex(0),
+            vertex(2),
+            vertex(3),
+        ]
+    }
+}
+
+/// Build the quads of every non-empty cell of a tile layer, in the render order
+/// of the map.
+///
+/// Cells whose tile cannot be found (no tileset, or a tileset without an image
+/// for it) are skipped.  The vector is empty for layers that are not tile layers.
+pub fn layer_quads(map: &Map, layer: &Layer) -> Vec<Quad> {
+    if layer.get_data().is_none() {
+        return Vec::new();
+    }
+    map.cells_in_render_order()
+        .into_iter()
+        .filter_map(|(x, y)| tile_quad(map, layer, x, y))
+        .collect()
+}
+
+/// Build the quad of a single cell of a tile layer, if the cell holds a tile.
+pub fn tile_quad(map: &Map, layer: &Layer, x: u32, y: u32) -> Option<Quad> {
+    if x >= map.width as u32 || y >= map.height as u32 {
+        return Option::None;
+    }
+    let gid = *layer.get_data()?.get((y * map.width as u32 + x) as usize)?;
+    if crate::gid_without_flags(gid) == 0 {
+        return Option::None;
+    }
+    let tileset = map
+        .tilesets
+        .iter()
+        .rposition(|ts| ts.firstgid <= crate::gid_without_flags(gid))?;
+    let ts = &map.tilesets[tileset];
+
+    // Source rectangle, in pixels, and the size of the image it lies in.
+    let (src, image_width, image_height) = match ts.tile_by_gid(gid).filter(|t| t.image.is_some()) {
+        Option::Some(tile) => {
+            let (w, h) = (tile.imagewidth as f64, tile.imageheight as f64);
+            ((0.0, 0.0, w, h), w, h)
+        }
+        Option::None => {
+            if ts.columns == 0 || ts.image.is_empty() {
+                return Option::None;
+            }
+            let (sx, sy) = ts.coord_by_gid(gid);
+            let src = (
+                sx as f64,
+                sy as f64,
+                ts.tilewidth as f64,
+                ts.tileheight as f64,
+            );
+            (src, ts.imagewidth as f64, ts.imageheight as f64)
+        }
+    };
+    if src.2 <= 0.0 || src.3 <= 0.0 || image_width <= 0.0 || image_height <= 0.0 {
+        return Option::None;
+    }
+
+    let (px, py) = map.tile_to_pixel(x, y);
+    let (off_x, off_y) = match ts.tileoffset {
+        Option::Some(o) => (o.x as f64, o.y as f64),
+        Option::None => (0.0, 0.0),
+    };
+
+    let (flip_h, flip_v, flip_d) = crate::gid_flipped_hvd(gid);
+    let corner = |u: f64, v: f64| {
+        // Tiled flips diagonally, then horizontally, then vertically; undo those
+        // in reverse to find which corner of the source lands here.
+        let v = if flip_v { 1.0 - v } else { v };
+        let u = if flip_h { 1.0 - u } else { u };
+        let (u, v) = if flip_d { (v, u) } else { (u, v) };
+        Point::new(
+            (src.0 + u * src.2) / image_width,
+            (src.1 + v * src.3) / image_height,
+        )
+    };
+
+    // A diagonal flip turns the tile by a quarter, swapping its width and
+    // height; the quad still rests on the bottom of the cell.
+    let (width, height) = if flip_d {
+        (src.3, src.2)
+    } else {
+        (src.2, src.3)
+    };
+
+    Option::Some(Quad {
+        x: px + off_x + layer.offsetx,
+        y: py + map.tileheight as f64 - height + off_y + layer.offsety,
+        width,
+        height,
+        uvs: [
+            corner(0.0, 0.0),
+            corner(1.0, 0.0),
+            corner(1.0, 1.0),
+            corner(0.0, 1.0),
+        ],
+        tileset,
+        gid,
+        cell: (x, y),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tile_quad;
+    use crate::builder::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+    use crate::object::Point;
+    use crate::tileset::Tileset;
+
+    // A 2x2 map of 16x16 cells with tiles of 16x32 pixels, two of them side by
+    // side in a 32x32 image, and cell (1, 1) holding tile 0 with flags.
+    fn tall_tiles(flags: u32) -> Map {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 2, 2, 16, 16);
+        builder.add_tileset(Tileset::new("tall", "tall.png", 32, 32, 16, 32));
+        let id = builder.add_layer(Layer::new_tile_layer("walls")).unwrap();
+        builder.layer_mut(id).unwrap().set_gid(1, 1, 1 | flags);
+        builder.build()
+    }
+
+    #[test]
+    fn tall_tile_sticks_out_of_the_top() {
+        let map = tall_tiles(0);
+        let quad = tile_quad(&map, &map.layers[0], 1, 1).unwrap();
+        assert_eq!(
+            (quad.x, quad.y, quad.width, quad.height),
+            (16.0, 0.0, 16.0, 32.0)
+        );
+        assert_eq!(quad.uvs[2], Point::new(0.5, 1.0));
+    }
+
+    #[test]
+    fn diagonal_flip_swaps_the_size() {
+        let map = tall_tiles(crate::DIAG_FLIP_FLAG);
+        let quad = tile_quad(&map, &map.layers[0], 1, 1).unwrap();
+        assert_eq!(
+            (quad.x, quad.y, quad.width, quad.height),
+            (16.0, 16.0, 32.0, 16.0)
+        );
+        // Top-right of the quad shows the bottom-left of the tile.
+        assert_eq!(
+            quad.uvs,
+            [
+                Point::new(0.0, 0.0),
+                Point::new(0.0, 1.0),
+                Point::new(0.5, 1.0),
+                Point::new(0.5, 0.0),
+            ]
+        );
+    }
+}
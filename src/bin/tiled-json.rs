@@ -0,0 +1,248 @@
+//!
+//! The tiled-json command, for looking into map files without writing code.
+//!
+//!         tiled-json info <map.json>...
+//!
+//! info prints a summary of each map: orientation and size, tilesets with
+//! their gid ranges, the layer tree with types and counts, objects counted by
+//! type, every property, and roughly how much memory the decoded map takes.
+//!
+
+use std::collections::BTreeMap;
+use std::mem::size_of;
+use std::mem::size_of_val;
+use std::process::exit;
+
+use rs_tiled_json::load_map;
+use rs_tiled_json::Frame;
+use rs_tiled_json::Layer;
+use rs_tiled_json::LayerDataContainer;
+use rs_tiled_json::Map;
+use rs_tiled_json::Object;
+use rs_tiled_json::Point;
+use rs_tiled_json::Property;
+use rs_tiled_json::PropertyValue;
+
+const USAGE: &str = "usage: tiled-json info <map.json>...";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let files = match args.split_first() {
+        Option::Some((command, files)) if command == "info" && !files.is_empty() => files,
+        Option::Some((command, _)) if command == "-h" || command == "--help" => {
+            println!("{}", USAGE);
+            return;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    let mut failed = false;
+    for (i, file) in files.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        match load_map(file) {
+            Ok(map) => info(file, &map),
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
+
+fn info(file: &str, map: &Map) {
+    let (pw, ph) = map.pixel_size();
+    println!("{}", file);
+    println!(
+        "  {} map, {}x{} tiles of {}x{} pixels, {}x{} pixels",
+        map.orientation(),
+        map.width(),
+        map.height(),
+        map.tile_width(),
+        map.tile_height(),
+        pw,
+        ph
+    );
+    println!(
+        "  render order {}, tiled {}, next layer id {}, next object id {}",
+        map.render_order(),
+        map.tiled_version(),
+        map.nextlayerid,
+        map.nextobjectid
+    );
+
+    println!("tilesets:");
+    for ts in map.tilesets().iter() {
+        let end = ts.first_gid() + ts.gid_span();
+        let image = if ts.image().is_empty() {
+            "image collection".to_string()
+        } else {
+            format!("image {}", ts.image())
+        };
+        println!(
+            "  {:?}: gids {}..{}, {} tiles of {}x{}, {}",
+            ts.name(),
+            ts.first_gid(),
+            end,
+            ts.tile_count(),
+            ts.tile_width(),
+            ts.tile_height(),
+            image
+        );
+    }
+
+    println!("layers:");
+    print_layers(map.layers(), 1);
+
+    let mut types: BTreeMap<&str, usize> = BTreeMap::new();
+    for_each_layer(map.layers(), &mut |layer| {
+        for obj in layer.get_objects_vector().into_iter().flatten() {
+            *types.entry(obj.obj_type().as_str()).or_insert(0) += 1;
+        }
+    });
+    println!("objects:");
+    for (otype, count) in types.iter() {
+        let otype = if otype.is_empty() { "(no type)" } else { otype };
+        println!("  {}: {}", otype, count);
+    }
+
+    println!("properties:");
+    print_properties("map", &map.properties);
+    for ts in map.tilesets().iter() {
+        print_properties(&format!("tileset {:?}", ts.name()), &ts.properties);
+        for tile in ts.tiles().iter() {
+            let owner = format!("tile {} of tileset {:?}", tile.id, ts.name());
+            print_properties(&owner, &tile.properties);
+        }
+    }
+    for_each_layer(map.layers(), &mut |layer| {
+        print_properties(&format!("layer {:?}", layer.name), &layer.properties);
+        for obj in layer.get_objects_vector().into_iter().flatten() {
+            print_properties(&format!("object {}", obj.id()), &obj.properties);
+        }
+    });
+
+    let memory = memory_size(map);
+    println!(
+        "memory: about {} bytes, {} of them tile data",
+        memory.0, memory.1
+    );
+}
+
+// Print layers as a tree, indented by depth.
+fn print_layers(layers: &[Layer], depth: usize) {
+    for layer in layers.iter() {
+        let id = layer.id.map(|id| id.to_string()).unwrap_or_default();
+        let content = match layer.layerdata {
+            LayerDataContainer::TileLayer { ref data } => format!(
+                "{}x{}, {} of {} cells set",
+                layer.width,
+                layer.height,
+                data.iter()
+                    .filter(|g| rs_tiled_json::gid_without_flags(**g) != 0)
+                    .count(),
+                data.len()
+            ),
+            LayerDataContainer::ObjectGroup { ref objects, .. } => {
+                format!("{} objects", objects.len())
+            }
+            LayerDataContainer::ImageLayer { ref image, .. } => format!("image {}", image),
+            LayerDataContainer::Group { ref layers } => format!("{} layers", layers.len()),
+        };
+        let hidden = if layer.visible { "" } else { ", hidden" };
+        println!(
+            "{}{} {} {:?}: {}{}",
+            "  ".repeat(depth),
+            layer.ltype,
+            id,
+            layer.name,
+            content,
+            hidden
+        );
+        if let Option::Some(children) = layer.get_layers() {
+            print_layers(children, depth + 1);
+        }
+    }
+}
+
+fn print_properties(owner: &str, properties: &[Property]) {
+    for property in properties.iter() {
+        println!(
+            "  {}: {} ({}) = {}",
+            owner,
+            property.name(),
+            property.type_as_string(),
+            property.get_pvalue()
+        );
+    }
+}
+
+fn for_each_layer<'a, F>(layers: &'a [Layer], f: &mut F)
+where
+    F: FnMut(&'a Layer),
+{
+    for layer in layers.iter() {
+        f(layer);
+        if let Option::Some(children) = layer.get_layers() {
+            for_each_layer(children, f);
+        }
+    }
+}
+
+// The approximate size of the decoded map in memory, and the part of it taken
+// by tile data: structs, vectors and strings, without allocator overhead.
+fn memory_size(map: &Map) -> (usize, usize) {
+    let mut total = size_of::<Map>() + properties_size(&map.properties);
+    for ts in map.tilesets().iter() {
+        total += size_of_val(ts) + ts.name().len() + ts.image().len();
+        total += properties_size(&ts.properties);
+        for tile in ts.tiles().iter() {
+            total += size_of_val(tile) + properties_size(&tile.properties);
+            total += tile.animation.len() * size_of::<Frame>();
+            total += tile.image.as_ref().map(|s| s.len()).unwrap_or(0);
+        }
+    }
+
+    let mut tiles = 0;
+    for_each_layer(map.layers(), &mut |layer| {
+        total += size_of::<Layer>() + layer.name.len() + properties_size(&layer.properties);
+        if let Option::Some(data) = layer.get_data() {
+            tiles += data.len() * size_of::<u32>();
+        }
+        for obj in layer.get_objects_vector().into_iter().flatten() {
+            total += object_size(obj);
+        }
+    });
+    (total + tiles, tiles)
+}
+
+fn object_size(obj: &Object) -> usize {
+    let points =
+        obj.polygon().map(|p| p.len()).unwrap_or(0) + obj.polyline().map(|p| p.len()).unwrap_or(0);
+    size_of::<Object>()
+        + obj.name().len()
+        + obj.obj_type().len()
+        + points * size_of::<Point>()
+        + obj.text().map(|t| t.text.len()).unwrap_or(0)
+        + properties_size(&obj.properties)
+}
+
+fn properties_size(properties: &[Property]) -> usize {
+    properties
+        .iter()
+        .map(|p| {
+            let text = match p.get_pvalue() {
+                PropertyValue::StringV(s) | PropertyValue::File(s) => s.len(),
+                _ => 0,
+            };
+            size_of::<Property>() + p.name().len() + text
+        })
+        .sum()
+}
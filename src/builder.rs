@@ -0,0 +1,450 @@
+//!
+//! Building maps in code.
+//!
+//! Maps usually come from load_map(), but procedural generators need to emit
+//! them too.  MapBuilder creates an empty map and takes tilesets and layers one
+//! at a time, doing the bookkeeping Tiled would do:
+//! - every tileset receives the next free firstgid,
+//! - every layer, including the layers of groups, receives the next layer id,
+//!   and every object the next object id, keeping nextlayerid and nextobjectid
+//!   up to date,
+//! - tile layers are sized to the map and filled with empty cells.
+//!
+//! The map returned by MapBuilder::build() has the same shape as a loaded one,
+//! so everything else in this library works on it.
+//!
+//! Constructors for the pieces live here as well: Tileset::new(), the
+//! Layer::new_* functions and the Object::new_* functions.  Their results are
+//! plain structs with public fields, which can be adjusted before being added.
+//!
+
+use std::convert::TryFrom;
+
+use crate::color::Color;
+use crate::layer::DrawOrder;
+use crate::layer::Layer;
+use crate::layer::LayerDataContainer;
+use crate::layer::LayerType;
+use crate::map::Map;
+use crate::map::MapOrientation;
+use crate::map::RenderOrder;
+use crate::map::StaggerAxis;
+use crate::map::StaggerIndex;
+use crate::object::Object;
+use crate::object::Point;
+use crate::property::Property;
+use crate::tileset::Tileset;
+
+/// MapBuilder assembles a Map piece by piece.  See the module documentation.
+pub struct MapBuilder {
+    map: Map,
+}
+
+impl MapBuilder {
+    /// Start an empty map of width by height cells of tilewidth by tileheight
+    /// pixels.
+    pub fn new(
+        orientation: MapOrientation,
+        width: u16,
+        height: u16,
+        tilewidth: u16,
+        tileheight: u16,
+    ) -> MapBuilder {
+        MapBuilder {
+            map: Map {
+                orientation,
+                height,
+                width,
+                nextobjectid: 1,
+                nextlayerid: 1,
+                tileheight,
+                tilewidth,
+                tiledversion: String::new(),
+                backgroundcolor: Option::None,
+                renderorder: RenderOrder::RightDown,
+                hexsidelength: 0,
+                staggeraxis: Option::None,
+                staggerindex: Option::None,
+                parallaxoriginx: 0.0,
+                parallaxoriginy: 0.0,
+                tilesets: Vec::new(),
+                layers: Vec::new(),
+                properties: Vec::new(),
+            },
+        }
+    }
+
+    /// Set the background color of the map.
+    pub fn background_color(&mut self, color: Color) -> &mut MapBuilder {
+        self.map.backgroundcolor = Option::Some(color);
+        self
+    }
+
+    /// Set the render order of the map.
+    pub fn render_order(&mut self, order: RenderOrder) -> &mut MapBuilder {
+        self.map.renderorder = order;
+        self
+    }
+
+    /// Set the stagger axis and index of a staggered or hexagonal map.
+    pub fn stagger(&mut self, axis: StaggerAxis, index: StaggerIndex) -> &mut MapBuilder {
+        self.map.staggeraxis = Option::Some(axis);
+        self.map.staggerindex = Option::Some(index);
+        self
+    }
+
+    /// Set the side length of the tiles of a hexagonal map.
+    pub fn hex_side_length(&mut self, length: u16) -> &mut MapBuilder {
+        self.map.hexsidelength = length;
+        self
+    }
+
+    /// Add a custom property to the map.
+    pub fn property(&mut self, property: Property) -> &mut MapBuilder {
+        self.map.properties.push(property);
+        self
+    }
+
+    /// Add a tileset after the ones already added, returning the firstgid it
+    /// was given.
+    pub fn add_tileset(&mut self, mut tileset: Tileset) -> u32 {
+        tileset.firstgid = self
+            .map
+            .tilesets
+            .last()
+            .map(|ts| ts.firstgid + ts.gid_span())
+            .unwrap_or(1);
+        let firstgid = tileset.firstgid;
+        self.map.tilesets.push(tileset);
+        firstgid
+    }
+
+    /// Add a layer on top of the others, returning its id.
+    ///
+    /// The layer, and every layer and object it holds, is given a fresh id.
+    /// Tile layers are resized to the map: missing cells are empty and extra
+    /// ones are dropped.  An error is returned, and nothing added, when the
+    /// layer ids would run past nextlayerid's maximum of 65535.
+    pub fn add_layer(&mut self, mut layer: Layer) -> Result<u32, String> {
+        let id = self.prepare(&mut layer)?;
+        self.map.layers.push(layer);
+        Ok(id)
+    }
+
+    /// Add a layer on top of the layers of the group with id group, returning
+    /// the id of the new layer.  An error is returned, and nothing added, when
+    /// no group has that id or the layer ids would run out.
+    pub fn add_layer_to(&mut self, group: u32, mut layer: Layer) -> Result<u32, String> {
+        if !self.layer_mut(group).is_some_and(|g| g.is_group()) {
+            return Err(format!("The map has no group with id {}.", group));
+        }
+        let id = self.prepare(&mut layer)?;
+        if let Option::Some(LayerDataContainer::Group { layers }) =
+            self.layer_mut(group).map(|g| &mut g.layerdata)
+        {
+            layers.push(layer);
+        }
+        Ok(id)
+    }
+
+    /// Add an object to the object group with id layer, returning the id the
+    /// object was given.  None is returned, and nothing added, when no object
+    /// group has that id.
+    pub fn add_object(&mut self, layer: u32, mut object: Object) -> Option<u32> {
+        let id = self.map.nextobjectid;
+        match self.layer_mut(layer).map(|l| &mut l.layerdata) {
+            Option::Some(LayerDataContainer::ObjectGroup { objects, .. }) => {
+                object.id = id;
+                objects.push(object);
+            }
+            _ => return Option::None,
+        }
+        self.map.nextobjectid += 1;
+        Option::Some(id)
+    }
+
+    /// Borrow the map built so far.
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// Mutably borrow a layer added earlier by id, wherever it is nested, for
+    /// instance to paint a tile layer with Layer::set_gid().
+    pub fn layer_mut(&mut self, id: u32) -> Option<&mut Layer> {
+        self.map.layer_by_id_mut(id)
+    }
+
+    /// Finish the map.
+    pub fn build(self) -> Map {
+        self.map
+    }
+
+    // Give a layer and everything it holds fresh ids, and size tile layers to
+    // the map.  Returns the id of the layer, or an error, before changing
+    // anything, when there are not enough layer ids left.
+    fn prepare(&mut self, layer: &mut Layer) -> Result<u32, String> {
+        let count = count_layers(layer);
+        let next = u16::try_from(count)
+            .ok()
+            .and_then(|count| self.map.nextlayerid.checked_add(count));
+        if next.is_none() {
+            return Err(format!(
+                "Cannot add {} layers after layer id {}: layer ids stop at {}.",
+                count,
+                self.map.nextlayerid,
+                u16::MAX
+            ));
+        }
+        Ok(self.assign(layer))
+    }
+
+    // The work of prepare(), once the ids are known to fit.
+    fn assign(&mut self, layer: &mut Layer) -> u32 {
+        let id = self.map.nextlayerid as u32;
+        self.map.nextlayerid += 1;
+        layer.id = Option::Some(id);
+        match layer.layerdata {
+            LayerDataContainer::TileLayer { ref mut data } => {
+                layer.width = self.map.width as u32;
+                layer.height = self.map.height as u32;
+                data.resize((layer.width * layer.height) as usize, 0);
+            }
+            LayerDataContainer::ObjectGroup {
+                ref mut objects, ..
+            } => {
+                for object in objects.iter_mut() {
+                    object.id = self.map.nextobjectid;
+                    self.map.nextobjectid += 1;
+                }
+            }
+            LayerDataContainer::Group { ref mut layers } => {
+                for child in layers.iter_mut() {
+                    self.assign(child);
+                }
+            }
+            LayerDataContainer::ImageLayer { .. } => {}
+        }
+        id
+    }
+}
+
+// The number of layers in a layer: itself and those of its groups.
+fn count_layers(layer: &Layer) -> usize {
+    1 + layer
+        .get_layers()
+        .map(|layers| layers.iter().map(count_layers).sum())
+        .unwrap_or(0)
+}
+
+impl Tileset {
+    /// Create a tileset cutting an image of imagewidth by imageheight pixels
+    /// into tiles of tilewidth by tileheight pixels, without margin or spacing.
+    /// The firstgid is given when the tileset is added to a MapBuilder.
+    pub fn new(
+        name: &str,
+        image: &str,
+        imagewidth: u16,
+        imageheight: u16,
+        tilewidth: u16,
+        tileheight: u16,
+    ) -> Tileset {
+        let columns = imagewidth.checked_div(tilewidth).unwrap_or(0);
+        let rows = imageheight.checked_div(tileheight).unwrap_or(0);
+        Tileset {
+            tiledversion: String::new(),
+            image: image.to_string(),
+            firstgid: 1,
+            imageheight,
+            imagewidth,
+            tileheight,
+            tilewidth,
+            tilecount: columns as u32 * rows as u32,
+            columns,
+            margin: 0,
+            spacing: 0,
+            name: name.to_string(),
+            backgroundcolor: Option::None,
+            transparentcolor: Option::None,
+            grid: Option::None,
+            tiles: Vec::new(),
+            tileoffset: Option::None,
+            properties: Vec::new(),
+        }
+    }
+}
+
+impl Layer {
+    /// Create an empty tile layer.  It is sized to the map when added to a
+    /// MapBuilder.
+    pub fn new_tile_layer(name: &str) -> Layer {
+        Layer::new_layer(
+            name,
+            LayerType::TileLayer,
+            LayerDataContainer::TileLayer { data: Vec::new() },
+        )
+    }
+
+    /// Create an empty object group drawn in DrawOrder::TopDown order.
+    pub fn new_object_group(name: &str) -> Layer {
+        Layer::new_layer(
+            name,
+            LayerType::ObjectGroup,
+            LayerDataContainer::ObjectGroup {
+                draworder: DrawOrder::TopDown,
+                objects: Vec::new(),
+            },
+        )
+    }
+
+    /// Create an image layer showing image.
+    pub fn new_image_layer(name: &str, image: &str) -> Layer {
+        Layer::new_layer(
+            name,
+            LayerType::ImageLayer,
+            LayerDataContainer::ImageLayer {
+                image: image.to_string(),
+                transparentcolor: Option::None,
+            },
+        )
+    }
+
+    /// Create an empty group of layers.
+    pub fn new_group(name: &str) -> Layer {
+        Layer::new_layer(
+            name,
+            LayerType::Group,
+            LayerDataContainer::Group { layers: Vec::new() },
+        )
+    }
+
+    fn new_layer(name: &str, ltype: LayerType, layerdata: LayerDataContainer) -> Layer {
+        Layer {
+            id: Option::None,
+            name: name.to_string(),
+            opacity: 1.0,
+            visible: true,
+            width: 0,
+            height: 0,
+            offsetx: 0.0,
+            offsety: 0.0,
+            parallaxx: 1.0,
+            parallaxy: 1.0,
+            tintcolor: Option::None,
+            repeatx: false,
+            repeaty: false,
+            class: String::new(),
+            ltype,
+            layerdata,
+            properties: Vec::new(),
+        }
+    }
+}
+
+impl Object {
+    /// Create a rectangle.  Its id is given when it is added to a MapBuilder.
+    pub fn new_rect(x: f64, y: f64, width: f64, height: f64) -> Object {
+        let mut object = Object::new_point(x, y);
+        object.point = false;
+        object.width = width;
+        object.height = height;
+        object
+    }
+
+    /// Create an ellipse fitting in a rectangle.
+    pub fn new_ellipse(x: f64, y: f64, width: f64, height: f64) -> Object {
+        let mut object = Object::new_rect(x, y, width, height);
+        object.ellipse = true;
+        object
+    }
+
+    /// Create a point.
+    pub fn new_point(x: f64, y: f64) -> Object {
+        Object {
+            id: 0,
+            x,
+            y,
+            gid: Option::None,
+            name: String::new(),
+            otype: String::new(),
+            height: 0.0,
+            width: 0.0,
+            rotation: 0.0,
+            visible: true,
+            ellipse: false,
+            point: true,
+            polygon: Option::None,
+            polyline: Option::None,
+            text: Option::None,
+            properties: Vec::new(),
+        }
+    }
+
+    /// Create a polygon from points relative to (x, y).
+    pub fn new_polygon(x: f64, y: f64, points: Vec<Point>) -> Object {
+        let mut object = Object::new_rect(x, y, 0.0, 0.0);
+        object.polygon = Option::Some(points);
+        object
+    }
+
+    /// Create a polyline from points relative to (x, y).
+    pub fn new_polyline(x: f64, y: f64, points: Vec<Point>) -> Object {
+        let mut object = Object::new_rect(x, y, 0.0, 0.0);
+        object.polyline = Option::Some(points);
+        object
+    }
+
+    /// Create a tile object showing gid, hanging from its bottom-left corner
+    /// at (x, y).
+    pub fn new_tile(gid: u32, x: f64, y: f64, width: f64, height: f64) -> Object {
+        let mut object = Object::new_rect(x, y, width, height);
+        object.gid = Option::Some(gid);
+        object
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::MapOrientation;
+    use crate::tileset::Tile;
+    use crate::tileset::Tileset;
+
+    #[test]
+    fn tilesets_after_image_collections_do_not_overlap() {
+        let mut collection = Tileset::new("props", "", 0, 0, 16, 16);
+        collection.tilecount = 1;
+        collection.tiles = vec![Tile {
+            id: 3,
+            image: Option::Some("crate.png".to_string()),
+            imageheight: 16,
+            imagewidth: 16,
+            ttype: Option::None,
+            objectgroup: Option::None,
+            animation: Vec::new(),
+            properties: Vec::new(),
+        }];
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 2, 2, 16, 16);
+        assert_eq!(builder.add_tileset(collection), 1);
+        let terrain = Tileset::new("terrain", "terrain.png", 32, 32, 16, 16);
+        assert_eq!(builder.add_tileset(terrain), 5);
+        assert!(builder.build().validate().is_empty());
+    }
+
+    #[test]
+    fn layer_ids_run_out_without_panicking() {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 2, 2, 16, 16);
+        builder.map.nextlayerid = u16::MAX - 1;
+        let mut group = Layer::new_group("group");
+        if let Option::Some(layers) = group.get_layers_mut() {
+            layers.push(Layer::new_tile_layer("ground"));
+        }
+        assert!(builder.add_layer(group).is_err());
+        assert!(builder.map().layers.is_empty());
+        assert_eq!(builder.map().nextlayerid, u16::MAX - 1);
+
+        assert_eq!(builder.add_layer(Layer::new_tile_layer("last")), Ok(65534));
+        assert_eq!(builder.map().nextlayerid, u16::MAX);
+        assert!(builder.add_layer(Layer::new_tile_layer("more")).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//!
+//! Collision helpers turn the per-tile collision data defined in the Tiled
+//! tileset editor into world-space polygons for a whole tile layer.
+//!
+//! Every tile may carry an objectgroup (see Tileset::collision_by_gid()) with
+//! rectangles, ellipses and polygons describing its solid area.  The functions
+//! here apply the flip flags of each gid to those shapes, move them to the
+//! position of the cell in the map and merge neighbouring tiles that are solid
+//! over their whole area into larger rectangles.  The result is a list of
+//! polygons, each a vector of tiled_json::Point, that can be handed to a
+//! physics engine as is.
+//!
+//! Positions are computed for orthogonal maps.  Tiles larger than the map grid
+//! are anchored to the bottom-left corner of their cell the way Tiled draws them,
+//! and the tileset tileoffset as well as the layer offset are taken into account.
+//!
+//! Points, polylines and text objects cover no area and are ignored.
+//!
+
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::object::Point;
+use crate::tileset::Tileset;
+
+const EPSILON: f64 = 0.0001;
+
+/// Get the collision polygons of the tile referenced by gid, in pixels relative
+/// to the top-left corner of the tile.  The flip flags present on the gid are
+/// applied to every point.
+///
+/// You should be sure gid belongs to this tileset.  The vector is empty when
+/// the tile has no collision data.
+pub fn tile_collision_polygons(tileset: &Tileset, gid: u32) -> Vec<Vec<Point>> {
+    let mut polygons = Vec::new();
+    let objects = match tileset
+        .collision_by_gid(gid)
+        .and_then(|l| l.get_objects_vector())
+    {
+        Option::Some(o) => o,
+        Option::None => return polygons,
+    };
+
+    let width = tileset.tilewidth as f64;
+    let height = tileset.tileheight as f64;
+    for obj in objects.iter() {
+        if let Option::Some(points) = obj.outline() {
+            polygons.push(
+                points
+                    .into_iter()
+                    .map(|p| flip_point(p, gid, width, height))
+                    .collect(),
+            );
+        }
+    }
+    polygons
+}
+
+/// Collect the collision polygons of every tile in a tile layer, in world space.
+///
+/// Cells whose tile is solid over exactly the whole map cell (a single rectangle
+/// the size of the tile, with tiles the size of the map grid) are merged with
+/// their solid neighbours into as few rectangles as possible.  All other shapes
+/// are returned one polygon per collision object.
+///
+/// An empty vector is returned if the layer is not a tile layer.
+pub fn layer_collision_polygons(map: &Map, layer: &Layer) -> Vec<Vec<Point>> {
+    let mut polygons = Vec::new();
+    let data = match layer.get_data() {
+        Option::Some(d) => d,
+        Option::None => return polygons,
+    };
+
+    let columns = layer.width as usize;
+    let rows = layer.height as usize;
+    if columns == 0 || data.len() < columns * rows {
+        return polygons;
+    }
+
+    let cell_width = map.tilewidth as f64;
+    let cell_height = map.tileheight as f64;
+    let mut full = vec![false; columns * rows];
+
+    for (pos, gid) in data.iter().enumerate().take(columns * rows) {
+        if crate::gid_without_flags(*gid) == 0 {
+            continue;
+        }
+        let ts = match map.tileset_by_gid(*gid) {
+            Option::Some(ts) => ts,
+            Option::None => continue,
+        };
+
+        let shapes = tile_collision_polygons(ts, *gid);
+        if shapes.is_empty() {
+            continue;
+        }
+
+        let (off_x, off_y) = match ts.tileoffset {
+            Option::Some(o) => (o.x as f64, o.y as f64),
+            Option::None => (0.0, 0.0),
+        };
+        if shapes.len() == 1
+            && off_x == 0.0
+            && off_y == 0.0
+            && ts.tilewidth == map.tilewidth
+            && ts.tileheight == map.tileheight
+            && covers_rect(&shapes[0], cell_width, cell_height)
+        {
+            full[pos] = true;
+            continue;
+        }
+
+        // A diagonal flip swaps the width and height of the tile; it still
+        // rests on the bottom of the cell.
+        let tile_height = if crate::gid_flipped_hvd(*gid).2 {
+            ts.tilewidth as f64
+        } else {
+            ts.tileheight as f64
+        };
+        let origin_x = (pos % columns) as f64 * cell_width + layer.offsetx + off_x;
+        let origin_y =
+            ((pos / columns) + 1) as f64 * cell_height - tile_height + layer.offsety + off_y;
+        for shape in shapes.into_iter() {
+            polygons.push(
+                shape
+                    .into_iter()
+                    .map(|p| Point::new(p.x + origin_x, p.y + origin_y))
+                    .collect(),
+            );
+        }
+    }
+
+    for (x, y, w, h) in merge_cells(&mut full, columns, rows) {
+        let left = x as f64 * cell_width + layer.offsetx;
+        let top = y as f64 * cell_height + layer.offsety;
+        let right = (x + w) as f64 * cell_width + layer.offsetx;
+        let bottom = (y + h) as f64 * cell_height + layer.offsety;
+        polygons.push(vec![
+            Point::new(left, top),
+            Point::new(right, top),
+            Point::new(right, bottom),
+            Point::new(left, bottom),
+        ]);
+    }
+    polygons
+}
+
+/// Apply the flip flags of gid to a point in a tile of the given size.
+///
+/// Tiled flips diagonally first (swapping the x and y axes), then horizontally,
+/// then vertically.
+pub fn flip_point(p: Point, gid: u32, width: f64, height: f64) -> Point {
+    let (h, v, d) = crate::gid_flipped_hvd(gid);
+    let (mut x, mut y, mut w, mut ht) = (p.x, p.y, width, height);
+    if d {
+        std::mem::swap(&mut x, &mut y);
+        std::mem::swap(&mut w, &mut ht);
+    }
+    if h {
+        x = w - x;
+    }
+    if v {
+        y = ht - y;
+    }
+    Point::new(x, y)
+}
+
+// Whether a polygon is exactly the axis-aligned rectangle (0, 0, width, height).
+fn covers_rect(points: &[Point], width: f64, height: f64) -> bool {
+    if points.len() != 4 {
+        return false;
+    }
+    let corners = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+    corners.iter().all(|c| {
+        points
+            .iter()
+            .any(|p| (p.x - c.0).abs() < EPSILON && (p.y - c.1).abs() < EPSILON)
+    })
+}
+
+// Greedily merge marked cells into rectangles (x, y, width, height) in cells.
+// Runs are grown to the right first, then downward while the whole run is marked.
+fn merge_cells(cells: &mut [bool], columns: usize, rows: usize) -> Vec<(usize, usize, usize, usize)> {
+    let mut rects = Vec::new();
+    for y in 0..rows {
+        let mut x = 0;
+        while x < columns {
+            if !cells[y * columns + x] {
+                x += 1;
+                continue;
+            }
+            let mut w = 1;
+            while x + w < columns && cells[y * columns + x + w] {
+                w += 1;
+            }
+            let mut h = 1;
+            while y + h < rows && (x..x + w).all(|cx| cells[(y + h) * columns + cx]) {
+                h += 1;
+            }
+            for cy in y..y + h {
+                for cx in x..x + w {
+                    cells[cy * columns + cx] = false;
+                }
+            }
+            rects.push((x, y, w, h));
+            x += w;
+        }
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flip_point;
+    use super::layer_collision_polygons;
+    use super::merge_cells;
+    use crate::map::Map;
+    use crate::object::Point;
+
+    // A 3x1 map whose first two cells hold a tile solid over its whole area
+    // and whose last cell holds a tile solid over its bottom half.
+    const MAP: &str = r#"{
+        "orientation": "orthogonal", "width": 3, "height": 1,
+        "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 2,
+        "tilesets": [{
+            "firstgid": 1, "name": "walls", "image": "walls.png",
+            "imagewidth": 32, "imageheight": 16, "tilewidth": 16, "tileheight": 16,
+            "tilecount": 2, "columns": 2,
+            "tiles": [
+                { "id": 0, "objectgroup": { "type": "objectgroup", "objects": [
+                    { "id": 1, "x": 0, "y": 0, "width": 16, "height": 16 } ] } },
+                { "id": 1, "objectgroup": { "type": "objectgroup", "objects": [
+                    { "id": 1, "x": 0, "y": 8, "width": 16, "height": 8 } ] } }
+            ]
+        }],
+        "layers": [{ "type": "tilelayer", "id": 1, "name": "walls",
+                     "width": 3, "height": 1, "data": [1, 1, 2] }]
+    }"#;
+
+    fn coords(points: &[Point]) -> Vec<(f64, f64)> {
+        points.iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    #[test]
+    fn flip_point_applies_diagonal_first() {
+        let p = Point::new(1.0, 2.0);
+        let flipped = |flags| {
+            let p = flip_point(p, 1 | flags, 4.0, 8.0);
+            (p.x, p.y)
+        };
+        assert_eq!(flipped(0), (1.0, 2.0));
+        assert_eq!(flipped(crate::HORZ_FLIP_FLAG), (3.0, 2.0));
+        assert_eq!(flipped(crate::VERT_FLIP_FLAG), (1.0, 6.0));
+        // A diagonal flip swaps the axes, and the tile becomes 8x4.
+        assert_eq!(flipped(crate::DIAG_FLIP_FLAG), (2.0, 1.0));
+        assert_eq!(
+            flipped(crate::DIAG_FLIP_FLAG | crate::HORZ_FLIP_FLAG),
+            (6.0, 1.0)
+        );
+        assert_eq!(
+            flipped(crate::DIAG_FLIP_FLAG | crate::VERT_FLIP_FLAG),
+            (2.0, 3.0)
+        );
+    }
+
+    #[test]
+    fn merge_cells_grows_right_then_down() {
+        #[rustfmt::skip]
+        let mut cells = vec![
+            true,  true,  false,
+            true,  true,  false,
+            false, false, true,
+        ];
+        assert_eq!(
+            merge_cells(&mut cells, 3, 3),
+            vec![(0, 0, 2, 2), (2, 2, 1, 1)]
+        );
+        assert!(cells.iter().all(|c| !c));
+
+        // A run only grows down while the whole run is marked below it.
+        #[rustfmt::skip]
+        let mut cells = vec![
+            true, true, true,
+            true, true, false,
+        ];
+        assert_eq!(
+            merge_cells(&mut cells, 3, 2),
+            vec![(0, 0, 3, 1), (0, 1, 2, 1)]
+        );
+    }
+
+    #[test]
+    fn solid_cells_merge_and_other_shapes_stay() {
+        let map: Map = serde_json::from_str(MAP).unwrap();
+        let polygons = layer_collision_polygons(&map, &map.layers[0]);
+        assert_eq!(polygons.len(), 2);
+        assert_eq!(
+            coords(&polygons[0]),
+            vec![(32.0, 8.0), (48.0, 8.0), (48.0, 16.0), (32.0, 16.0)]
+        );
+        assert_eq!(
+            coords(&polygons[1]),
+            vec![(0.0, 0.0), (32.0, 0.0), (32.0, 16.0), (0.0, 16.0)]
+        );
+    }
+
+    #[test]
+    fn diagonally_flipped_tall_tiles_rest_on_the_cell() {
+        // 16x32 tiles whose top quarter is solid, on a grid of 16x16 cells.
+        let map: Map = serde_json::from_str(
+            r#"{
+                "orientation": "orthogonal", "width": 2, "height": 1,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 2,
+                "tilesets": [{
+                    "firstgid": 1, "name": "trees", "image": "trees.png",
+                    "imagewidth": 16, "imageheight": 32, "tilewidth": 16, "tileheight": 32,
+                    "tilecount": 1, "columns": 1,
+                    "tiles": [{ "id": 0, "objectgroup": { "type": "objectgroup", "objects": [
+                        { "id": 1, "x": 0, "y": 0, "width": 16, "height": 8 } ] } }]
+                }],
+                "layers": [{ "type": "tilelayer", "id": 1, "name": "trees",
+                             "width": 2, "height": 1, "data": [536870913, 1] }]
+            }"#,
+        )
+        .unwrap();
+        let polygons = layer_collision_polygons(&map, &map.layers[0]);
+        // Flipped, the tile is 32x16 and fits the height of the cell.
+        assert_eq!(
+            coords(&polygons[0]),
+            vec![(0.0, 0.0), (0.0, 16.0), (8.0, 16.0), (8.0, 0.0)]
+        );
+        // Upright, it reaches 16 pixels above the cell.
+        assert_eq!(
+            coords(&polygons[1]),
+            vec![(16.0, -16.0), (32.0, -16.0), (32.0, -8.0), (16.0, -8.0)]
+        );
+    }
+}
@@ -10,9 +10,10 @@
 //! 
 
 use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Deserialize, Clone, Copy)]
-#[serde(from = "String")]
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(from = "String", into = "String")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// The basic structure that describes color across all modules.
 pub struct Color {
@@ -115,6 +116,12 @@ impl std::fmt::Display for Color {
     }
 }
 
+impl From<Color> for String {
+    fn from(c: Color) -> Self {
+        c.to_string()
+    }
+}
+
 impl From<String> for Color {
     fn from(cs: String) -> Self {
         Color::new(&cs)
@@ -0,0 +1,677 @@
+//!
+//! Undoable edits of maps.
+//!
+//! Every change an editor makes to a map can be expressed as a Command: setting
+//! tiles, adding, removing, moving or editing objects, changing custom
+//! properties and reordering layers.  Applying a command with Command::apply()
+//! changes the map and returns the inverse command, which puts the map back
+//! exactly as it was when applied in turn.
+//!
+//! History keeps those inverses on an undo stack, and the inverses of the
+//! inverses on a redo stack.  Commands applied between History::begin() and
+//! History::commit() form one transaction, undone and redone as a whole; any
+//! other command is a transaction of its own.
+//!
+//! Layers and objects are found by id, and properties by name, so commands stay
+//! valid while other edits move things around.  A command whose target is gone
+//! fails with an error and leaves the map untouched.
+//!
+//! Adding an object with id 0 gives it the next free object id of the map.
+//! Undoing the addition does not give that id back, just as in Tiled, so ids
+//! are never reused.
+//!
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::diff::ObjectField;
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::object::Object;
+use crate::property::Property;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// What a property command works on: the map itself, a layer by id or an
+/// object by id.
+pub enum PropertyTarget {
+    Map,
+    Layer(u32),
+    Object(u32),
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// An edit of a map.  See the module documentation.
+pub enum Command {
+    /// Set cells (x, y) of a tile layer to gids, flags included.
+    SetTiles {
+        layer: u32,
+        cells: Vec<(u32, u32, u32)>,
+    },
+    /// Insert an object into an object group at index, or at the end when
+    /// index is past it.
+    AddObject {
+        layer: u32,
+        index: usize,
+        object: Object,
+    },
+    /// Remove an object, wherever it is.
+    RemoveObject { id: u32 },
+    /// Move an object to (x, y).
+    MoveObject { id: u32, x: f64, y: f64 },
+    /// Set a field of an object other than its position.
+    SetObjectField { id: u32, field: ObjectField },
+    /// Set a property, replacing the one of the same name or adding it at the
+    /// end.
+    SetProperty {
+        target: PropertyTarget,
+        property: Property,
+    },
+    /// Insert a property at index, or at the end when index is past it,
+    /// replacing any other of the same name.
+    InsertProperty {
+        target: PropertyTarget,
+        index: usize,
+        property: Property,
+    },
+    /// Remove a property by name.
+    RemoveProperty {
+        target: PropertyTarget,
+        name: String,
+    },
+    /// Move a layer to index within the layers holding it, either the map or
+    /// a group.
+    MoveLayer { id: u32, index: usize },
+}
+
+impl Command {
+    /// Apply the command to a map, returning the command undoing it.  When an
+    /// error is returned the map is unchanged.
+    pub fn apply(self, map: &mut Map) -> Result<Command, String> {
+        match self {
+            Command::SetTiles { layer, cells } => {
+                let l = tile_layer(map, layer)?;
+                if let Option::Some((x, y, _)) =
+                    cells.iter().find(|(x, y, _)| l.get_gid(*x, *y).is_none())
+                {
+                    return Err(format!(
+                        "Cell ({}, {}) is outside of layer {}.",
+                        x, y, layer
+                    ));
+                }
+                let mut old = Vec::with_capacity(cells.len());
+                for (x, y, gid) in cells.into_iter() {
+                    if let Option::Some(previous) = l.set_gid(x, y, gid) {
+                        old.push((x, y, previous));
+                    }
+                }
+                old.reverse();
+                Ok(Command::SetTiles { layer, cells: old })
+            }
+            Command::AddObject {
+                layer,
+                index,
+                mut object,
+            } => {
+                if object.id == 0 {
+                    object.id = map.nextobjectid;
+                } else if find_object(&map.layers, object.id).is_some() {
+                    return Err(format!("Object {} already exists.", object.id));
+                }
+                let next = object
+                    .id
+                    .checked_add(1)
+                    .ok_or(format!("Object {} has the last possible id.", object.id))?
+                    .max(map.nextobjectid);
+                let objects = map
+                    .layer_by_id_mut(layer)
+                    .and_then(|l| l.get_objects_vector_mut())
+                    .ok_or(format!("No object group has id {}.", layer))?;
+                let id = object.id;
+                objects.insert(index.min(objects.len()), object);
+                map.nextobjectid = next;
+                Ok(Command::RemoveObject { id })
+            }
+            Command::RemoveObject { id } => {
+                let (layer, index) =
+                    find_object(&map.layers, id).ok_or(format!("No object has id {}.", id))?;
+                let object = map
+                    .layer_by_id_mut(layer)
+                    .and_then(|l| l.get_objects_vector_mut())
+                    .map(|o| o.remove(index))
+                    .ok_or(format!("No object has id {}.", id))?;
+                Ok(Command::AddObject {
+                    layer,
+                    index,
+                    object,
+                })
+            }
+            Command::MoveObject { id, x, y } => {
+                let object = object_mut(map, id)?;
+                let inverse = Command::MoveObject {
+                    id,
+                    x: object.x,
+                    y: object.y,
+                };
+                object.x = x;
+                object.y = y;
+                Ok(inverse)
+            }
+            Command::SetObjectField { id, field } => {
+                let object = object_mut(map, id)?;
+                let inverse = Command::SetObjectField {
+                    id,
+                    field: field.of(object),
+                };
+                field.set(object);
+                Ok(inverse)
+            }
+            Command::SetProperty { target, property } => {
+                let properties = properties_mut(map, target)?;
+                match properties.iter().position(|p| p.name == property.name) {
+                    Option::Some(i) => Ok(Command::SetProperty {
+                        target,
+                        property: std::mem::replace(&mut properties[i], property),
+                    }),
+                    Option::None => {
+                        let name = property.name.clone();
+                        properties.push(property);
+                        Ok(Command::RemoveProperty { target, name })
+                    }
+                }
+            }
+            Command::InsertProperty {
+                target,
+                index,
+                property,
+            } => {
+                let properties = properties_mut(map, target)?;
+                let replaced = properties
+                    .iter()
+                    .position(|p| p.name == property.name)
+                    .map(|i| (i, properties.remove(i)));
+                let name = property.name.clone();
+                properties.insert(index.min(properties.len()), property);
+                Ok(match replaced {
+                    Option::Some((index, property)) => Command::InsertProperty {
+                        target,
+                        index,
+                        property,
+                    },
+                    Option::None => Command::RemoveProperty { target, name },
+                })
+            }
+            Command::RemoveProperty { target, name } => {
+                let properties = properties_mut(map, target)?;
+                let index = properties
+                    .iter()
+                    .position(|p| p.name == name)
+                    .ok_or(format!("No property is named {}.", name))?;
+                Ok(Command::InsertProperty {
+                    target,
+                    index,
+                    property: properties.remove(index),
+                })
+            }
+            Command::MoveLayer { id, index } => {
+                let layers = sibling_layers_mut(&mut map.layers, id)
+                    .ok_or(format!("No layer has id {}.", id))?;
+                let old = layers
+                    .iter()
+                    .position(|l| l.id == Option::Some(id))
+                    .unwrap_or(0);
+                let layer = layers.remove(old);
+                layers.insert(index.min(layers.len()), layer);
+                Ok(Command::MoveLayer { id, index: old })
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Undo and redo stacks of transactions.  See the module documentation.
+pub struct History {
+    undo: Vec<Vec<Command>>,
+    redo: Vec<Vec<Command>>,
+    open: Option<Vec<Command>>,
+}
+
+impl History {
+    /// Create an empty history.
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /// Apply a command to a map and record its inverse, in the open
+    /// transaction if there is one.  The redo stack is cleared.
+    pub fn apply(&mut self, map: &mut Map, command: Command) -> Result<(), String> {
+        let inverse = command.apply(map)?;
+        self.redo.clear();
+        match self.open {
+            Option::Some(ref mut transaction) => transaction.push(inverse),
+            Option::None => self.undo.push(vec![inverse]),
+        }
+        Ok(())
+    }
+
+    /// Open a transaction; the commands applied until commit() are undone as
+    /// one.  Opening a transaction while one is open does nothing.
+    pub fn begin(&mut self) {
+        if self.open.is_none() {
+            self.open = Option::Some(Vec::new());
+        }
+    }
+
+    /// Close the open transaction.  Empty transactions are dropped.
+    pub fn commit(&mut self) {
+        if let Option::Some(transaction) = self.open.take() {
+            if !transaction.is_empty() {
+                self.undo.push(transaction);
+            }
+        }
+    }
+
+    /// Undo the commands of the open transaction and close it without
+    /// recording anything.
+    pub fn rollback(&mut self, map: &mut Map) -> Result<(), String> {
+        match self.open.take() {
+            Option::Some(transaction) => revert(map, transaction).map(|_| ()).map_err(|(_, e)| e),
+            Option::None => Ok(()),
+        }
+    }
+
+    /// Undo the last transaction, closing the open one first.  Returns false
+    /// when there is nothing to undo.  When undoing fails, the map and the
+    /// history are left as they were.
+    pub fn undo(&mut self, map: &mut Map) -> Result<bool, String> {
+        self.commit();
+        replay(map, &mut self.undo, &mut self.redo)
+    }
+
+    /// Redo the last undone transaction.  Returns false when there is nothing
+    /// to redo.  When redoing fails, the map and the history are left as they
+    /// were.
+    pub fn redo(&mut self, map: &mut Map) -> Result<bool, String> {
+        self.commit();
+        replay(map, &mut self.redo, &mut self.undo)
+    }
+
+    /// Whether there is a transaction to undo, the open one included.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.open.as_ref().is_some_and(|t| !t.is_empty())
+    }
+
+    /// Whether there is a transaction to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forget every transaction, the open one included.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = Option::None;
+    }
+}
+
+// Revert the last transaction of from and push the commands redoing it onto
+// to.  A transaction that fails is put back onto from.
+fn replay(
+    map: &mut Map,
+    from: &mut Vec<Vec<Command>>,
+    to: &mut Vec<Vec<Command>>,
+) -> Result<bool, String> {
+    let transaction = match from.pop() {
+        Option::Some(t) => t,
+        Option::None => return Ok(false),
+    };
+    match revert(map, transaction) {
+        Ok(done) => {
+            to.push(done);
+            Ok(true)
+        }
+        Err((transaction, e)) => {
+            from.push(transaction);
+            Err(e)
+        }
+    }
+}
+
+// Apply the inverses of a transaction from last to first, returning the
+// commands redoing it in the same order as the transaction.  When one fails,
+// the ones already applied are reverted and the transaction is given back,
+// as it was, along with the error.
+fn revert(
+    map: &mut Map,
+    mut transaction: Vec<Command>,
+) -> Result<Vec<Command>, (Vec<Command>, String)> {
+    let mut done: Vec<Command> = Vec::with_capacity(transaction.len());
+    while let Option::Some(command) = transaction.pop() {
+        match command.clone().apply(map) {
+            Ok(inverse) => done.push(inverse),
+            Err(e) => {
+                // Reverting the commands already applied gives them back.
+                transaction.push(command);
+                for inverse in done.into_iter().rev() {
+                    if let Ok(command) = inverse.apply(map) {
+                        transaction.push(command);
+                    }
+                }
+                return Err((transaction, e));
+            }
+        }
+    }
+    done.reverse();
+    Ok(done)
+}
+
+fn tile_layer(map: &mut Map, id: u32) -> Result<&mut Layer, String> {
+    map.layer_by_id_mut(id)
+        .filter(|l| l.is_tile_layer())
+        .ok_or(format!("No tile layer has id {}.", id))
+}
+
+// The id of the object group holding an object and its index there.
+fn find_object(layers: &[Layer], id: u32) -> Option<(u32, usize)> {
+    for layer in layers.iter() {
+        if let Option::Some(i) = layer
+            .get_objects_vector()
+            .and_then(|o| o.iter().position(|o| o.id == id))
+        {
+            return layer.id.map(|l| (l, i));
+        }
+        if let Option::Some(found) = layer.get_layers().and_then(|l| find_object(l, id)) {
+            return Option::Some(found);
+        }
+    }
+    Option::None
+}
+
+fn object_mut(map: &mut Map, id: u32) -> Result<&mut Object, String> {
+    let (layer, index) = find_object(&map.layers, id).ok_or(format!("No object has id {}.", id))?;
+    map.layer_by_id_mut(layer)
+        .and_then(|l| l.get_objects_vector_mut())
+        .and_then(|o| o.get_mut(index))
+        .ok_or(format!("No object has id {}.", id))
+}
+
+fn properties_mut(map: &mut Map, target: PropertyTarget) -> Result<&mut Vec<Property>, String> {
+    match target {
+        PropertyTarget::Map => Ok(&mut map.properties),
+        PropertyTarget::Layer(id) => map
+            .layer_by_id_mut(id)
+            .map(|l| &mut l.properties)
+            .ok_or(format!("No layer has id {}.", id)),
+        PropertyTarget::Object(id) => object_mut(map, id).map(|o| &mut o.properties),
+    }
+}
+
+// The vector of layers, of the map or of a group, holding the layer with id.
+fn sibling_layers_mut(layers: &mut Vec<Layer>, id: u32) -> Option<&mut Vec<Layer>> {
+    if layers.iter().any(|l| l.id == Option::Some(id)) {
+        return Option::Some(layers);
+    }
+    layers
+        .iter_mut()
+        .filter_map(|l| l.get_layers_mut())
+        .find_map(|l| sibling_layers_mut(l, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Command;
+    use super::History;
+    use super::PropertyTarget;
+    use crate::map::Map;
+    use crate::object::Object;
+    use crate::property::Property;
+
+    // A map with a 2x2 tile layer (1), a group (2) holding the object groups 3
+    // and 5, and an empty object group (4).
+    fn map() -> Map {
+        serde_json::from_str(
+            r#"{
+                "orientation": "orthogonal", "width": 2, "height": 2,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 10, "nextlayerid": 6,
+                "properties": [{ "name": "a", "type": "int", "value": 1 }],
+                "layers": [
+                    { "type": "tilelayer", "id": 1, "name": "ground",
+                      "width": 2, "height": 2, "data": [1, 2, 3, 4] },
+                    { "type": "group", "id": 2, "name": "group", "layers": [
+                        { "type": "objectgroup", "id": 3, "name": "things", "objects": [
+                            { "id": 1, "x": 0, "y": 0, "width": 8, "height": 8,
+                              "properties": [
+                                { "name": "b", "type": "bool", "value": true },
+                                { "name": "c", "type": "string", "value": "old" }
+                              ] },
+                            { "id": 2, "x": 16, "y": 16, "width": 8, "height": 8 }
+                        ] },
+                        { "type": "objectgroup", "id": 5, "name": "more", "objects": [] }
+                    ] },
+                    { "type": "objectgroup", "id": 4, "name": "empty", "objects": [] }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn property(json: &str) -> Property {
+        serde_json::from_str(json).unwrap()
+    }
+
+    // Debug output covers every field of the map, which is enough to tell two
+    // states apart.
+    fn snapshot(map: &Map) -> String {
+        format!("{:?}", map)
+    }
+
+    // Apply a command, its inverse and the inverse of that, checking the map
+    // goes back and forth between the two states.
+    fn round_trip(command: Command) {
+        let mut map = map();
+        let before = snapshot(&map);
+        let inverse = command.apply(&mut map).unwrap();
+        let after = snapshot(&map);
+        assert_ne!(before, after);
+        let redo = inverse.apply(&mut map).unwrap();
+        assert_eq!(snapshot(&map), before);
+        redo.apply(&mut map).unwrap();
+        assert_eq!(snapshot(&map), after);
+    }
+
+    #[test]
+    fn every_command_round_trips() {
+        round_trip(Command::SetTiles {
+            layer: 1,
+            cells: vec![(0, 0, 5), (1, 1, 6 | crate::HORZ_FLIP_FLAG), (0, 0, 7)],
+        });
+        let mut object = Object::new_rect(1.0, 2.0, 3.0, 4.0);
+        object.id = 7;
+        round_trip(Command::AddObject {
+            layer: 3,
+            index: 1,
+            object,
+        });
+        round_trip(Command::RemoveObject { id: 1 });
+        round_trip(Command::MoveObject {
+            id: 2,
+            x: 3.0,
+            y: 4.0,
+        });
+        round_trip(Command::SetProperty {
+            target: PropertyTarget::Map,
+            property: property(r#"{ "name": "a", "type": "int", "value": 2 }"#),
+        });
+        round_trip(Command::SetProperty {
+            target: PropertyTarget::Layer(3),
+            property: property(r#"{ "name": "d", "type": "float", "value": 0.5 }"#),
+        });
+        round_trip(Command::InsertProperty {
+            target: PropertyTarget::Object(1),
+            index: 0,
+            property: property(r#"{ "name": "c", "type": "string", "value": "new" }"#),
+        });
+        round_trip(Command::RemoveProperty {
+            target: PropertyTarget::Map,
+            name: "a".to_string(),
+        });
+        round_trip(Command::MoveLayer { id: 4, index: 0 });
+        round_trip(Command::MoveLayer { id: 3, index: 5 });
+    }
+
+    #[test]
+    fn failed_commands_leave_the_map_alone() {
+        let mut map = map();
+        let before = snapshot(&map);
+        let outside = Command::SetTiles {
+            layer: 1,
+            cells: vec![(0, 0, 5), (2, 0, 5)],
+        };
+        assert!(outside.apply(&mut map).is_err());
+        assert!(Command::RemoveObject { id: 9 }.apply(&mut map).is_err());
+        let mut object = Object::new_point(0.0, 0.0);
+        object.id = 2;
+        let taken = Command::AddObject {
+            layer: 4,
+            index: 0,
+            object,
+        };
+        assert!(taken.apply(&mut map).is_err());
+        assert_eq!(snapshot(&map), before);
+    }
+
+    #[test]
+    fn added_objects_take_the_next_id_for_good() {
+        let mut map = map();
+        let add = Command::AddObject {
+            layer: 4,
+            index: 0,
+            object: Object::new_point(0.0, 0.0),
+        };
+        let inverse = add.apply(&mut map).unwrap();
+        assert_eq!(map.layers[2].get_objects_vector().unwrap()[0].id, 10);
+        inverse.apply(&mut map).unwrap();
+        assert_eq!(map.nextobjectid, 11);
+    }
+
+    #[test]
+    fn transactions_are_undone_and_redone_as_one() {
+        let mut map = map();
+        let mut history = History::new();
+        let start = snapshot(&map);
+
+        history.begin();
+        let tiles = Command::SetTiles {
+            layer: 1,
+            cells: vec![(1, 0, 9)],
+        };
+        history.apply(&mut map, tiles).unwrap();
+        let moved = Command::MoveObject {
+            id: 1,
+            x: 5.0,
+            y: 5.0,
+        };
+        history.apply(&mut map, moved).unwrap();
+        history.commit();
+        let middle = snapshot(&map);
+        history
+            .apply(&mut map, Command::RemoveObject { id: 2 })
+            .unwrap();
+        assert!(history
+            .apply(&mut map, Command::RemoveObject { id: 2 })
+            .is_err());
+        let end = snapshot(&map);
+
+        assert_eq!(history.undo(&mut map), Ok(true));
+        assert_eq!(snapshot(&map), middle);
+        assert_eq!(history.undo(&mut map), Ok(true));
+        assert_eq!(snapshot(&map), start);
+        assert_eq!(history.undo(&mut map), Ok(false));
+        assert!(!history.can_undo());
+
+        assert_eq!(history.redo(&mut map), Ok(true));
+        assert_eq!(snapshot(&map), middle);
+        assert_eq!(history.redo(&mut map), Ok(true));
+        assert_eq!(snapshot(&map), end);
+        assert_eq!(history.redo(&mut map), Ok(false));
+    }
+
+    #[test]
+    fn rollback_reverts_the_open_transaction() {
+        let mut map = map();
+        let mut history = History::new();
+        let start = snapshot(&map);
+        history.begin();
+        history
+            .apply(&mut map, Command::RemoveObject { id: 1 })
+            .unwrap();
+        assert!(history.can_undo());
+        history.rollback(&mut map).unwrap();
+        assert_eq!(snapshot(&map), start);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn failed_undo_and_redo_keep_the_transaction() {
+        let mut map = map();
+        let mut history = History::new();
+        let start = snapshot(&map);
+        history.begin();
+        history
+            .apply(&mut map, Command::RemoveObject { id: 2 })
+            .unwrap();
+        let tiles = Command::SetTiles {
+            layer: 1,
+            cells: vec![(0, 0, 9)],
+        };
+        history.apply(&mut map, tiles).unwrap();
+        history.commit();
+        let edited = snapshot(&map);
+
+        // Another object takes id 2, so putting the removed one back fails
+        // after the tiles were already restored.
+        let mut intruder = Object::new_point(0.0, 0.0);
+        intruder.id = 2;
+        let added = Command::AddObject {
+            layer: 4,
+            index: 0,
+            object: intruder,
+        };
+        let removal = added.apply(&mut map).unwrap();
+        let blocked = snapshot(&map);
+        assert!(history.undo(&mut map).is_err());
+        assert_eq!(snapshot(&map), blocked);
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+
+        removal.apply(&mut map).unwrap();
+        assert_eq!(snapshot(&map), edited);
+        assert_eq!(history.undo(&mut map), Ok(true));
+        assert_eq!(snapshot(&map), start);
+
+        // Redoing removes object 2, which is gone already.
+        Command::RemoveObject { id: 2 }.apply(&mut map).unwrap();
+        let gone = snapshot(&map);
+        assert!(history.redo(&mut map).is_err());
+        assert_eq!(snapshot(&map), gone);
+        assert!(history.can_redo());
+    }
+
+    #[test]
+    fn the_last_object_id_is_refused() {
+        let mut map = map();
+        let before = snapshot(&map);
+        let mut object = Object::new_point(0.0, 0.0);
+        object.id = u32::MAX;
+        let add = Command::AddObject {
+            layer: 4,
+            index: 0,
+            object,
+        };
+        assert!(add.apply(&mut map).is_err());
+        assert_eq!(snapshot(&map), before);
+    }
+}
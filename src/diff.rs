@@ -0,0 +1,668 @@
+//!
+//! Structural differences between maps.
+//!
+//! diff() compares two versions of a map and lists what changed as a MapDiff,
+//! a vector of Change values which can be walked by code or printed, one change
+//! per line:
+//! - the size of the map,
+//! - layers added or removed, by id, wherever they are nested,
+//! - tiles changed in every tile layer present in both maps, cell by cell,
+//! - objects added, removed, moved or otherwise edited, by id,
+//! - custom properties added, removed or changed on the map, layers, tilesets,
+//!   tiles and objects,
+//! - tilesets added, removed or changed, by name.
+//!
+//! Layers and objects are matched by id, since renaming or reordering them does
+//! not change their identity; tilesets and their tiles are matched by name and
+//! local id, since their firstgid moves when other tilesets change.  An object
+//! moved to another layer shows as removed from one and added to the other.
+//!
+//! Changes are listed in a stable order: the map, its tilesets in the order of
+//! the new map, its layers by id along with their tiles, and its objects by id.
+//!
+
+use std::collections::BTreeMap;
+
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::object::Object;
+use crate::object::Point;
+use crate::object::Text;
+use crate::property::Property;
+use crate::property::PropertyValue;
+use crate::tileset::Tileset;
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// What holds a changed property.  Tilesets are named, and tiles are named by
+/// their tileset and local id.
+pub enum DiffTarget {
+    Map,
+    Layer(u32),
+    Tileset(String),
+    Tile(String, u16),
+    Object(u32),
+}
+
+impl std::fmt::Display for DiffTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffTarget::Map => write!(f, "map"),
+            DiffTarget::Layer(id) => write!(f, "layer {}", id),
+            DiffTarget::Tileset(name) => write!(f, "tileset {:?}", name),
+            DiffTarget::Tile(name, id) => write!(f, "tile {} of tileset {:?}", id, name),
+            DiffTarget::Object(id) => write!(f, "object {}", id),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A field of an object, with its value.  The position of objects is reported
+/// by Change::ObjectMoved instead, and their properties by
+/// Change::PropertyChanged.
+pub enum ObjectField {
+    Name(String),
+    Type(String),
+    Size(f64, f64),
+    Rotation(f64),
+    Visible(bool),
+    Gid(Option<u32>),
+    Polygon(Option<Vec<Point>>),
+    Polyline(Option<Vec<Point>>),
+    Ellipse(bool),
+    Point(bool),
+    Text(Option<Text>),
+}
+
+impl ObjectField {
+    /// The name of the field, as in the Tiled JSON format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObjectField::Name(_) => "name",
+            ObjectField::Type(_) => "type",
+            ObjectField::Size(_, _) => "size",
+            ObjectField::Rotation(_) => "rotation",
+            ObjectField::Visible(_) => "visible",
+            ObjectField::Gid(_) => "gid",
+            ObjectField::Polygon(_) => "polygon",
+            ObjectField::Polyline(_) => "polyline",
+            ObjectField::Ellipse(_) => "ellipse",
+            ObjectField::Point(_) => "point",
+            ObjectField::Text(_) => "text",
+        }
+    }
+
+    /// Read the field from an object.
+    pub fn of(&self, obj: &Object) -> ObjectField {
+        match self {
+            ObjectField::Name(_) => ObjectField::Name(obj.name.clone()),
+            ObjectField::Type(_) => ObjectField::Type(obj.otype.clone()),
+            ObjectField::Size(_, _) => ObjectField::Size(obj.width, obj.height),
+            ObjectField::Rotation(_) => ObjectField::Rotation(obj.rotation),
+            ObjectField::Visible(_) => ObjectField::Visible(obj.visible),
+            ObjectField::Gid(_) => ObjectField::Gid(obj.gid),
+            ObjectField::Polygon(_) => ObjectField::Polygon(obj.polygon.clone()),
+            ObjectField::Polyline(_) => ObjectField::Polyline(obj.polyline.clone()),
+            ObjectField::Ellipse(_) => ObjectField::Ellipse(obj.ellipse),
+            ObjectField::Point(_) => ObjectField::Point(obj.point),
+            ObjectField::Text(_) => ObjectField::Text(obj.text.clone()),
+        }
+    }
+
+    /// Write the field into an object.
+    pub fn set(&self, obj: &mut Object) {
+        match self {
+            ObjectField::Name(s) => obj.name = s.clone(),
+            ObjectField::Type(s) => obj.otype = s.clone(),
+            ObjectField::Size(w, h) => {
+                obj.width = *w;
+                obj.height = *h;
+            }
+            ObjectField::Rotation(r) => obj.rotation = *r,
+            ObjectField::Visible(v) => obj.visible = *v,
+            ObjectField::Gid(g) => obj.gid = *g,
+            ObjectField::Polygon(p) => obj.polygon = p.clone(),
+            ObjectField::Polyline(p) => obj.polyline = p.clone(),
+            ObjectField::Ellipse(e) => obj.ellipse = *e,
+            ObjectField::Point(p) => obj.point = *p,
+            ObjectField::Text(t) => obj.text = t.clone(),
+        }
+    }
+
+    // Every field of an object.
+    fn all(obj: &Object) -> Vec<ObjectField> {
+        vec![
+            ObjectField::Name(obj.name.clone()),
+            ObjectField::Type(obj.otype.clone()),
+            ObjectField::Size(obj.width, obj.height),
+            ObjectField::Rotation(obj.rotation),
+            ObjectField::Visible(obj.visible),
+            ObjectField::Gid(obj.gid),
+            ObjectField::Polygon(obj.polygon.clone()),
+            ObjectField::Polyline(obj.polyline.clone()),
+            ObjectField::Ellipse(obj.ellipse),
+            ObjectField::Point(obj.point),
+            ObjectField::Text(obj.text.clone()),
+        ]
+    }
+}
+
+impl std::fmt::Display for ObjectField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectField::Name(s) | ObjectField::Type(s) => write!(f, "{:?}", s),
+            ObjectField::Size(w, h) => write!(f, "{}x{}", w, h),
+            ObjectField::Rotation(r) => write!(f, "{}", r),
+            ObjectField::Visible(v) | ObjectField::Ellipse(v) | ObjectField::Point(v) => {
+                write!(f, "{}", v)
+            }
+            ObjectField::Gid(g) => write!(f, "{}", g.unwrap_or(0)),
+            ObjectField::Polygon(p) | ObjectField::Polyline(p) => {
+                write!(f, "{} points", p.as_ref().map(|p| p.len()).unwrap_or(0))
+            }
+            ObjectField::Text(t) => match t {
+                Option::Some(t) => write!(f, "{:?}", t.text),
+                Option::None => write!(f, "no text"),
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// One difference between two maps.  Old values come first.
+pub enum Change {
+    MapResized {
+        old: (u16, u16),
+        new: (u16, u16),
+    },
+    TilesetAdded {
+        name: String,
+        firstgid: u32,
+        tilecount: u32,
+    },
+    TilesetRemoved {
+        name: String,
+    },
+    /// A field of a tileset changed; values are given as text.
+    TilesetChanged {
+        name: String,
+        field: &'static str,
+        old: String,
+        new: String,
+    },
+    LayerAdded {
+        id: u32,
+        name: String,
+    },
+    LayerRemoved {
+        id: u32,
+        name: String,
+    },
+    /// A cell of a tile layer changed; gids include their flags.
+    TileChanged {
+        layer: u32,
+        x: u32,
+        y: u32,
+        old: u32,
+        new: u32,
+    },
+    ObjectAdded {
+        layer: u32,
+        object: Object,
+    },
+    ObjectRemoved {
+        layer: u32,
+        id: u32,
+    },
+    ObjectMoved {
+        id: u32,
+        old: (f64, f64),
+        new: (f64, f64),
+    },
+    ObjectChanged {
+        id: u32,
+        old: ObjectField,
+        new: ObjectField,
+    },
+    /// A property was added (old is None), removed (new is None) or changed.
+    PropertyChanged {
+        target: DiffTarget,
+        name: String,
+        old: Option<PropertyValue>,
+        new: Option<PropertyValue>,
+    },
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::MapResized { old, new } => {
+                write!(
+                    f,
+                    "map resized from {}x{} to {}x{}",
+                    old.0, old.1, new.0, new.1
+                )
+            }
+            Change::TilesetAdded {
+                name,
+                firstgid,
+                tilecount,
+            } => write!(
+                f,
+                "tileset {:?} added with gids {}..{}",
+                name,
+                firstgid,
+                firstgid + tilecount
+            ),
+            Change::TilesetRemoved { name } => write!(f, "tileset {:?} removed", name),
+            Change::TilesetChanged {
+                name,
+                field,
+                old,
+                new,
+            } => write!(f, "tileset {:?}: {} {} -> {}", name, field, old, new),
+            Change::LayerAdded { id, name } => write!(f, "layer {} {:?} added", id, name),
+            Change::LayerRemoved { id, name } => write!(f, "layer {} {:?} removed", id, name),
+            Change::TileChanged {
+                layer,
+                x,
+                y,
+                old,
+                new,
+            } => write!(f, "layer {}: tile ({}, {}) {} -> {}", layer, x, y, old, new),
+            Change::ObjectAdded { layer, object } => {
+                write!(f, "layer {}: object {} added", layer, object.id)
+            }
+            Change::ObjectRemoved { layer, id } => {
+                write!(f, "layer {}: object {} removed", layer, id)
+            }
+            Change::ObjectMoved { id, old, new } => write!(
+                f,
+                "object {}: moved from ({}, {}) to ({}, {})",
+                id, old.0, old.1, new.0, new.1
+            ),
+            Change::ObjectChanged { id, old, new } => {
+                write!(f, "object {}: {} {} -> {}", id, old.name(), old, new)
+            }
+            Change::PropertyChanged {
+                target,
+                name,
+                old,
+                new,
+            } => match (old, new) {
+                (Option::None, Option::Some(new)) => {
+                    write!(f, "{}: property {:?} added as {}", target, name, new)
+                }
+                (Option::Some(_), Option::None) => {
+                    write!(f, "{}: property {:?} removed", target, name)
+                }
+                (Option::Some(old), Option::Some(new)) => {
+                    write!(f, "{}: property {:?} {} -> {}", target, name, old, new)
+                }
+                (Option::None, Option::None) => write!(f, "{}: property {:?}", target, name),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The list of changes between two maps.  Printing it gives one change per
+/// line.
+pub struct MapDiff {
+    pub changes: Vec<Change>,
+}
+
+impl MapDiff {
+    /// Borrow the changes.
+    pub fn changes(&self) -> &Vec<Change> {
+        &self.changes
+    }
+
+    /// Whether the maps compared are the same.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl std::fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in self.changes.iter() {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// List the changes turning old into new.  See the module documentation.
+pub fn diff(old: &Map, new: &Map) -> MapDiff {
+    let mut changes = Vec::new();
+
+    if (old.width, old.height) != (new.width, new.height) {
+        changes.push(Change::MapResized {
+            old: (old.width, old.height),
+            new: (new.width, new.height),
+        });
+    }
+    diff_properties(
+        &mut changes,
+        DiffTarget::Map,
+        &old.properties,
+        &new.properties,
+    );
+    diff_tilesets(&mut changes, &old.tilesets, &new.tilesets);
+
+    let old_layers = layers_by_id(&old.layers);
+    let new_layers = layers_by_id(&new.layers);
+    for (id, layer) in old_layers.iter() {
+        if !new_layers.contains_key(id) {
+            changes.push(Change::LayerRemoved {
+                id: *id,
+                name: layer.name.clone(),
+            });
+        }
+    }
+    for (id, layer) in new_layers.iter() {
+        match old_layers.get(id) {
+            Option::Some(old_layer) => {
+                diff_properties(
+                    &mut changes,
+                    DiffTarget::Layer(*id),
+                    &old_layer.properties,
+                    &layer.properties,
+                );
+                diff_tiles(&mut changes, *id, old_layer, layer);
+            }
+            Option::None => changes.push(Change::LayerAdded {
+                id: *id,
+                name: layer.name.clone(),
+            }),
+        }
+    }
+
+    let old_objects = objects_by_id(&old.layers);
+    let new_objects = objects_by_id(&new.layers);
+    for (id, (layer, _)) in old_objects.iter() {
+        if new_objects.get(id).is_none_or(|(l, _)| l != layer) {
+            changes.push(Change::ObjectRemoved {
+                layer: *layer,
+                id: *id,
+            });
+        }
+    }
+    for (id, (layer, obj)) in new_objects.iter() {
+        match old_objects.get(id).filter(|(l, _)| l == layer) {
+            Option::Some((_, old_obj)) => diff_object(&mut changes, old_obj, obj),
+            Option::None => changes.push(Change::ObjectAdded {
+                layer: *layer,
+                object: (*obj).clone(),
+            }),
+        }
+    }
+
+    MapDiff { changes }
+}
+
+fn diff_properties(
+    changes: &mut Vec<Change>,
+    target: DiffTarget,
+    old: &[Property],
+    new: &[Property],
+) {
+    for p in old.iter() {
+        if !new.iter().any(|n| n.name == p.name) {
+            changes.push(Change::PropertyChanged {
+                target: target.clone(),
+                name: p.name.clone(),
+                old: Option::Some(p.value.clone()),
+                new: Option::None,
+            });
+        }
+    }
+    for p in new.iter() {
+        let before = old.iter().find(|o| o.name == p.name).map(|o| &o.value);
+        if before != Option::Some(&p.value) {
+            changes.push(Change::PropertyChanged {
+                target: target.clone(),
+                name: p.name.clone(),
+                old: before.cloned(),
+                new: Option::Some(p.value.clone()),
+            });
+        }
+    }
+}
+
+fn diff_tilesets(changes: &mut Vec<Change>, old: &[Tileset], new: &[Tileset]) {
+    for ts in old.iter() {
+        if !new.iter().any(|n| n.name == ts.name) {
+            changes.push(Change::TilesetRemoved {
+                name: ts.name.clone(),
+            });
+        }
+    }
+    for ts in new.iter() {
+        let before = match old.iter().find(|o| o.name == ts.name) {
+            Option::Some(b) => b,
+            Option::None => {
+                changes.push(Change::TilesetAdded {
+                    name: ts.name.clone(),
+                    firstgid: ts.firstgid,
+                    tilecount: ts.tilecount,
+                });
+                continue;
+            }
+        };
+        let fields = [
+            (
+                "firstgid",
+                before.firstgid.to_string(),
+                ts.firstgid.to_string(),
+            ),
+            ("image", before.image.clone(), ts.image.clone()),
+            (
+                "tilecount",
+                before.tilecount.to_string(),
+                ts.tilecount.to_string(),
+            ),
+            (
+                "columns",
+                before.columns.to_string(),
+                ts.columns.to_string(),
+            ),
+            (
+                "tilewidth",
+                before.tilewidth.to_string(),
+                ts.tilewidth.to_string(),
+            ),
+            (
+                "tileheight",
+                before.tileheight.to_string(),
+                ts.tileheight.to_string(),
+            ),
+            ("margin", before.margin.to_string(), ts.margin.to_string()),
+            (
+                "spacing",
+                before.spacing.to_string(),
+                ts.spacing.to_string(),
+            ),
+        ];
+        for (field, old_value, new_value) in fields.iter() {
+            if old_value != new_value {
+                changes.push(Change::TilesetChanged {
+                    name: ts.name.clone(),
+                    field,
+                    old: old_value.clone(),
+                    new: new_value.clone(),
+                });
+            }
+        }
+        diff_properties(
+            changes,
+            DiffTarget::Tileset(ts.name.clone()),
+            &before.properties,
+            &ts.properties,
+        );
+        for tile in before.tiles.iter() {
+            if !ts.tiles.iter().any(|t| t.id == tile.id) {
+                diff_properties(
+                    changes,
+                    DiffTarget::Tile(ts.name.clone(), tile.id),
+                    &tile.properties,
+                    &[],
+                );
+            }
+        }
+        for tile in ts.tiles.iter() {
+            let old_properties = before
+                .tiles
+                .iter()
+                .find(|t| t.id == tile.id)
+                .map(|t| t.properties.as_slice())
+                .unwrap_or(&[]);
+            diff_properties(
+                changes,
+                DiffTarget::Tile(ts.name.clone(), tile.id),
+                old_properties,
+                &tile.properties,
+            );
+        }
+    }
+}
+
+// Compare the cells of two versions of a tile layer, over the larger of their
+// sizes; cells outside of a layer count as empty.
+fn diff_tiles(changes: &mut Vec<Change>, id: u32, old: &Layer, new: &Layer) {
+    if !old.is_tile_layer() || !new.is_tile_layer() {
+        return;
+    }
+    for y in 0..old.height.max(new.height) {
+        for x in 0..old.width.max(new.width) {
+            let before = old.get_gid(x, y).unwrap_or(0);
+            let after = new.get_gid(x, y).unwrap_or(0);
+            if before != after {
+                changes.push(Change::TileChanged {
+                    layer: id,
+                    x,
+                    y,
+                    old: before,
+                    new: after,
+                });
+            }
+        }
+    }
+}
+
+fn diff_object(changes: &mut Vec<Change>, old: &Object, new: &Object) {
+    if (old.x, old.y) != (new.x, new.y) {
+        changes.push(Change::ObjectMoved {
+            id: new.id,
+            old: (old.x, old.y),
+            new: (new.x, new.y),
+        });
+    }
+    for field in ObjectField::all(new).into_iter() {
+        let before = field.of(old);
+        if before != field {
+            changes.push(Change::ObjectChanged {
+                id: new.id,
+                old: before,
+                new: field,
+            });
+        }
+    }
+    diff_properties(
+        changes,
+        DiffTarget::Object(new.id),
+        &old.properties,
+        &new.properties,
+    );
+}
+
+// Every layer with an id, nested ones included.
+fn layers_by_id(layers: &[Layer]) -> BTreeMap<u32, &Layer> {
+    let mut found = BTreeMap::new();
+    collect_layers(layers, &mut found);
+    found
+}
+
+fn collect_layers<'a>(layers: &'a [Layer], found: &mut BTreeMap<u32, &'a Layer>) {
+    for layer in layers.iter() {
+        if let Option::Some(id) = layer.id {
+            found.insert(id, layer);
+        }
+        if let Option::Some(children) = layer.get_layers() {
+            collect_layers(children, found);
+        }
+    }
+}
+
+// Every object by id, with the id of the layer holding it.
+fn objects_by_id(layers: &[Layer]) -> BTreeMap<u32, (u32, &Object)> {
+    let mut found = BTreeMap::new();
+    for (id, layer) in layers_by_id(layers).into_iter() {
+        if let Option::Some(objects) = layer.get_objects_vector() {
+            for obj in objects.iter() {
+                found.insert(obj.id, (id, obj));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use super::Change;
+    use super::ObjectField;
+    use crate::builder::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+    use crate::object::Object;
+    use crate::object::Text;
+
+    fn map_with(objects: Vec<Object>) -> Map {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 2, 2, 16, 16);
+        let layer = builder
+            .add_layer(Layer::new_object_group("things"))
+            .unwrap();
+        for object in objects.into_iter() {
+            builder.add_object(layer, object);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn shape_and_text_changes_are_reported() {
+        let old = map_with(vec![
+            Object::new_rect(0.0, 0.0, 8.0, 8.0),
+            Object::new_point(4.0, 4.0),
+        ]);
+        let mut new = old.clone();
+        let objects = new.layers[0].get_objects_vector_mut().unwrap();
+        objects[0].ellipse = true;
+        objects[1].point = false;
+        let text: Text = serde_json::from_str(r#"{ "text": "Hello" }"#).unwrap();
+        objects[1].text = Option::Some(text.clone());
+
+        let changes: Vec<ObjectField> = diff(&old, &new)
+            .changes()
+            .iter()
+            .filter_map(|c| match c {
+                Change::ObjectChanged { new, .. } => Option::Some(new.clone()),
+                _ => Option::None,
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ObjectField::Ellipse(true),
+                ObjectField::Point(false),
+                ObjectField::Text(Option::Some(text)),
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+//!
+//! Drawing objects in the order their object group asks for.
+//!
+//! Object groups carry a DrawOrder.  With DrawOrder::Index objects are drawn in
+//! the order they are stored.  With DrawOrder::TopDown they are drawn from the
+//! top of the screen down, so objects lower on screen cover the ones behind
+//! them.  objects_in_draw_order() returns the objects of a group in that order.
+//!
+//! Objects are sorted by the screen y of their bottom edge, given by
+//! object_depth(): tile objects hang from their bottom-left corner (bottom
+//! centre on isometric maps), rotation is taken into account, and the
+//! positions of objects on isometric maps are projected first.  Objects at the
+//! same depth keep their stored order.
+//!
+//! Depth-sorted scenes, isometric ones especially, draw objects between the rows
+//! of a tile layer holding walls or furniture.  depth_sorted() merges the tiles
+//! and objects of several layers into one stream of DrawItems sorted by depth.
+//!
+
+use std::collections::HashMap;
+
+use crate::layer::DrawOrder;
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::map::MapOrientation;
+use crate::object::Object;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// One entry of a depth-sorted stream:
+/// - TileRow: the cells of a tile layer whose bottom edge lies at depth,
+///   excluding empty cells, in render order.
+/// - Object: an object of an object group, with its depth.
+pub enum DrawItem<'a> {
+    TileRow {
+        layer: &'a Layer,
+        depth: f64,
+        cells: Vec<(u32, u32)>,
+    },
+    Object {
+        layer: &'a Layer,
+        depth: f64,
+        object: &'a Object,
+    },
+}
+
+impl<'a> DrawItem<'a> {
+    /// The layer the item belongs to.
+    pub fn layer(&self) -> &'a Layer {
+        match self {
+            DrawItem::TileRow { layer, .. } => layer,
+            DrawItem::Object { layer, .. } => layer,
+        }
+    }
+
+    /// The screen y of the bottom edge of the item.
+    pub fn depth(&self) -> f64 {
+        match self {
+            DrawItem::TileRow { depth, .. } => *depth,
+            DrawItem::Object { depth, .. } => *depth,
+        }
+    }
+}
+
+/// The screen y, in pixels, of the bottom edge of an object.  Layer offsets are
+/// not included.
+pub fn object_depth(map: &Map, obj: &Object) -> f64 {
+    let (sin, cos) = obj.rotation.to_radians().sin_cos();
+    // Points relative to the position of the object, before rotation.
+    let local: Vec<(f64, f64)> = if obj.gid.is_some() {
+        if let MapOrientation::Isometric = map.orientation {
+            // Tile objects on isometric maps stand upright on the screen, from
+            // the projection of their position.
+            let ay = map.object_to_pixel(obj.x, obj.y).1;
+            let half = obj.width / 2.0;
+            return [
+                (-half, 0.0),
+                (half, 0.0),
+                (half, -obj.height),
+                (-half, -obj.height),
+            ]
+            .iter()
+            .map(|(x, y)| ay + x * sin + y * cos)
+            .fold(ay, f64::max);
+        }
+        vec![
+            (0.0, 0.0),
+            (obj.width, 0.0),
+            (obj.width, -obj.height),
+            (0.0, -obj.height),
+        ]
+    } else if let Option::Some(ref poly) = obj.polygon {
+        poly.iter().map(|p| (p.x, p.y)).collect()
+    } else if let Option::Some(ref line) = obj.polyline {
+        line.iter().map(|p| (p.x, p.y)).collect()
+    } else {
+        vec![
+            (0.0, 0.0),
+            (obj.width, 0.0),
+            (obj.width, obj.height),
+            (0.0, obj.height),
+        ]
+    };
+
+    local
+        .iter()
+        .map(|(x, y)| {
+            let (rx, ry) = (x * cos - y * sin, x * sin + y * cos);
+            map.object_to_pixel(obj.x + rx, obj.y + ry).1
+        })
+        .fold(map.object_to_pixel(obj.x, obj.y).1, f64::max)
+}
+
+/// The objects of an object group in the order they must be drawn, following
+/// the DrawOrder of the group.  Hidden objects are included.
+///
+/// The vector is empty for layers that are not object groups.
+pub fn objects_in_draw_order<'a>(map: &Map, layer: &'a Layer) -> Vec<&'a Object> {
+    let objects = match layer.get_objects_vector() {
+        Option::Some(o) => o,
+        Option::None => return Vec::new(),
+    };
+    match layer.get_draworder() {
+        Option::Some(DrawOrder::TopDown) => {
+            let mut sorted: Vec<(f64, &Object)> =
+                objects.iter().map(|o| (object_depth(map, o), o)).collect();
+            sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            sorted.into_iter().map(|(_, o)| o).collect()
+        }
+        _ => objects.iter().collect(),
+    }
+}
+
+/// Merge the rows of tile layers and the objects of object groups into one
+/// stream sorted by depth, the screen y of their bottom edges, layer offsets
+/// included.
+///
+/// Tile rows group the cells whose bottom edges line up on screen: rows of an
+/// orthogonal map, diagonals of an isometric one.  At equal depth, tile rows come
+/// before objects, and otherwise items keep the order of layers.  Objects of
+/// object groups drawn in DrawOrder::Index order are sorted by depth as well.
+/// Hidden layers and objects are skipped, as are layers of other kinds.
+pub fn depth_sorted<'a>(map: &Map, layers: &[&'a Layer]) -> Vec<DrawItem<'a>> {
+    let mut items: Vec<DrawItem<'a>> = Vec::new();
+    let cells = map.cells_in_render_order();
+    let width = map.width as u32;
+    let tile_height = map.tileheight as f64;
+
+    for layer in layers.iter().filter(|l| l.visible) {
+        if let Option::Some(data) = layer.get_data() {
+            let mut rows: Vec<(f64, Vec<(u32, u32)>)> = Vec::new();
+            let mut row_of_depth: HashMap<u64, usize> = HashMap::new();
+            for (x, y) in cells.iter() {
+                let gid = data.get((y * width + x) as usize).copied().unwrap_or(0);
+                if crate::gid_without_flags(gid) == 0 {
+                    continue;
+                }
+                let depth = map.tile_to_pixel(*x, *y).1 + tile_height + layer.offsety;
+                let row = *row_of_depth.entry(depth.to_bits()).or_insert_with(|| {
+                    rows.push((depth, Vec::new()));
+                    rows.len() - 1
+                });
+                rows[row].1.push((*x, *y));
+            }
+            items.extend(rows.into_iter().map(|(depth, cells)| DrawItem::TileRow {
+                layer,
+                depth,
+                cells,
+            }));
+        } else if let Option::Some(objects) = layer.get_objects_vector() {
+            items.extend(
+                objects
+                    .iter()
+                    .filter(|o| o.visible)
+                    .map(|o| DrawItem::Object {
+                        layer,
+                        depth: object_depth(map, o) + layer.offsety,
+                        object: o,
+                    }),
+            );
+        }
+    }
+
+    items.sort_by(|a, b| {
+        let kind = |i: &DrawItem| match i {
+            DrawItem::TileRow { .. } => 0,
+            DrawItem::Object { .. } => 1,
+        };
+        a.depth()
+            .partial_cmp(&b.depth())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(kind(a).cmp(&kind(b)))
+    });
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::depth_sorted;
+    use super::object_depth;
+    use super::objects_in_draw_order;
+    use super::DrawItem;
+    use crate::map::Map;
+
+    // A 2x2 orthogonal map of 16x16 cells with a full tile layer and an object
+    // group "things" drawn in draworder.
+    fn orthogonal(draworder: &str) -> Map {
+        let json = r#"{
+            "orientation": "orthogonal", "width": 2, "height": 2,
+            "tilewidth": 16, "tileheight": 16, "nextobjectid": 6, "nextlayerid": 3,
+            "layers": [
+                { "type": "tilelayer", "id": 1, "name": "floor",
+                  "width": 2, "height": 2, "data": [1, 1, 1, 1] },
+                { "type": "objectgroup", "id": 2, "name": "things", "draworder": "ORDER",
+                  "objects": [
+                    { "id": 1, "x": 10, "y": 20, "width": 30, "height": 40 },
+                    { "id": 2, "x": 0, "y": 30, "gid": 1, "width": 16, "height": 32 },
+                    { "id": 3, "x": 0, "y": 0,
+                      "polygon": [{ "x": 0, "y": 0 }, { "x": 5, "y": 16 }, { "x": -5, "y": 8 }] },
+                    { "id": 4, "x": 0, "y": 0, "width": 10, "height": 20, "rotation": 90 },
+                    { "id": 5, "x": 4, "y": 4, "width": 8, "height": 12 }
+                  ] }
+            ]
+        }"#;
+        serde_json::from_str(&json.replace("ORDER", draworder)).unwrap()
+    }
+
+    fn ids(objects: &[&crate::object::Object]) -> Vec<u32> {
+        objects.iter().map(|o| o.id).collect()
+    }
+
+    #[test]
+    fn depth_is_the_bottom_edge_on_screen() {
+        let map = orthogonal("topdown");
+        let depths: Vec<f64> = map.layers[1]
+            .get_objects_vector()
+            .unwrap()
+            .iter()
+            .map(|o| object_depth(&map, o).round())
+            .collect();
+        // Tile objects hang from their bottom edge, and the rectangle turned a
+        // quarter clockwise reaches 10 pixels down.
+        assert_eq!(depths, vec![60.0, 30.0, 16.0, 10.0, 16.0]);
+    }
+
+    #[test]
+    fn top_down_sorts_by_depth_and_keeps_ties_in_order() {
+        let map = orthogonal("topdown");
+        let sorted = objects_in_draw_order(&map, &map.layers[1]);
+        assert_eq!(ids(&sorted), vec![4, 3, 5, 2, 1]);
+
+        let map = orthogonal("index");
+        let stored = objects_in_draw_order(&map, &map.layers[1]);
+        assert_eq!(ids(&stored), vec![1, 2, 3, 4, 5]);
+        assert!(objects_in_draw_order(&map, &map.layers[0]).is_empty());
+    }
+
+    #[test]
+    fn isometric_positions_are_projected() {
+        let map: Map = serde_json::from_str(
+            r#"{
+                "orientation": "isometric", "width": 4, "height": 4,
+                "tilewidth": 32, "tileheight": 16, "nextobjectid": 3, "nextlayerid": 2,
+                "layers": [{ "type": "objectgroup", "id": 1, "name": "things", "objects": [
+                    { "id": 1, "x": 16, "y": 16, "width": 16, "height": 16 },
+                    { "id": 2, "x": 32, "y": 16, "gid": 1, "width": 32, "height": 32 }
+                ] }]
+            }"#,
+        )
+        .unwrap();
+        let objects = map.layers[0].get_objects_vector().unwrap();
+        // The far corner of the rectangle, (32, 32), lies 32 pixels down.
+        assert_eq!(object_depth(&map, &objects[0]), 32.0);
+        // A tile object stands upright on the projection of its position.
+        assert_eq!(object_depth(&map, &objects[1]), 24.0);
+    }
+
+    #[test]
+    fn tile_rows_come_before_objects_at_the_same_depth() {
+        let map = orthogonal("index");
+        let layers = [&map.layers[0], &map.layers[1]];
+        let stream: Vec<(f64, Option<u32>)> = depth_sorted(&map, &layers)
+            .iter()
+            .map(|item| match item {
+                DrawItem::TileRow { depth, .. } => (depth.round(), Option::None),
+                DrawItem::Object { depth, object, .. } => (depth.round(), Option::Some(object.id)),
+            })
+            .collect();
+        assert_eq!(
+            stream,
+            vec![
+                (10.0, Option::Some(4)),
+                (16.0, Option::None),
+                (16.0, Option::Some(3)),
+                (16.0, Option::Some(5)),
+                (30.0, Option::Some(2)),
+                (32.0, Option::None),
+                (60.0, Option::Some(1)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//!
+//! Editing tile layers.
+//!
+//! Layers loaded from a map are plain data, and the data of tile layers can be
+//! changed through Layer::get_data_mut().  The methods here do the index math
+//! and the bookkeeping of flip flags for level editors and procedural tools:
+//! - Layer::get_gid() and Layer::set_gid() read and write single cells.
+//! - Layer::fill_rect() paints a rectangle with one gid.
+//! - Layer::copy_region() cuts a TileStamp out of a layer, and
+//!   Layer::paste_region() stamps it back anywhere, optionally leaving the cells
+//!   under empty stamp cells untouched.
+//! - Layer::flip_horizontally(), Layer::flip_vertically(),
+//!   Layer::rotate_clockwise() and Layer::rotate_counterclockwise() transform a
+//!   whole layer, and TileStamp offers the same for stamps.
+//!
+//! Flipping or rotating moves the cells and also changes the flip flags of every
+//! gid, so each tile is drawn mirrored or turned along with the layer, exactly
+//! as Tiled does.  The gid_* functions below do this for a single gid.  These
+//! transforms are meant for orthogonal maps; the grids of other orientations do
+//! not map onto themselves this way.
+//!
+//! All coordinates are in cells, and the width and height of the layer give
+//! the size of its data.
+//!
+
+use crate::layer::Layer;
+
+const FLAGS: u32 = crate::HORZ_FLIP_FLAG | crate::VERT_FLIP_FLAG | crate::DIAG_FLIP_FLAG;
+
+// Transforms of a tile as 2x2 matrices acting on (x, y) around its centre, with y
+// pointing down.
+type Matrix = [[i32; 2]; 2];
+const MIRROR_X: Matrix = [[-1, 0], [0, 1]];
+const MIRROR_Y: Matrix = [[1, 0], [0, -1]];
+const ROTATE_CW: Matrix = [[0, -1], [1, 0]];
+const ROTATE_CCW: Matrix = [[0, 1], [-1, 0]];
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A rectangle of gids cut out of a tile layer, row by row.  Gid 0 marks an
+/// empty cell.
+pub struct TileStamp {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u32>,
+}
+
+impl TileStamp {
+    /// Create a stamp filled with gid.  An error is returned when the stamp
+    /// has more cells than can be addressed.
+    pub fn new(width: u32, height: u32, gid: u32) -> Result<TileStamp, String> {
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or(format!("A stamp of {}x{} cells is too large.", width, height))?;
+        Ok(TileStamp {
+            width,
+            height,
+            data: vec![gid; size],
+        })
+    }
+
+    /// Width of the stamp in cells.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the stamp in cells.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Borrow the gids of the stamp.
+    pub fn data(&self) -> &Vec<u32> {
+        &self.data
+    }
+
+    /// Get the gid of a cell of the stamp, or None outside of it.
+    pub fn get_gid(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        self.data
+            .get(y as usize * self.width as usize + x as usize)
+            .copied()
+    }
+
+    /// Mirror the stamp left to right.
+    pub fn flip_horizontally(&mut self) {
+        let (w, h) = (self.width, self.height);
+        self.data = remap(&self.data, w, h, w, |x, y| (w - 1 - x, y), MIRROR_X);
+    }
+
+    /// Mirror the stamp top to bottom.
+    pub fn flip_vertically(&mut self) {
+        let (w, h) = (self.width, self.height);
+        self.data = remap(&self.data, w, h, w, |x, y| (x, h - 1 - y), MIRROR_Y);
+    }
+
+    /// Turn the stamp a quarter turn clockwise, swapping its width and height.
+    pub fn rotate_clockwise(&mut self) {
+        let (w, h) = (self.width, self.height);
+        self.data = remap(&self.data, w, h, h, |x, y| (h - 1 - y, x), ROTATE_CW);
+        self.width = h;
+        self.height = w;
+    }
+
+    /// Turn the stamp a quarter turn counterclockwise, swapping its width and
+    /// height.
+    pub fn rotate_counterclockwise(&mut self) {
+        let (w, h) = (self.width, self.height);
+        self.data = remap(&self.data, w, h, h, |x, y| (y, w - 1 - x), ROTATE_CCW);
+        self.width = h;
+        self.height = w;
+    }
+}
+
+impl Layer {
+    /// Get the gid of a cell of a tile layer, flags included.  None is returned
+    /// outside of the layer and for layers that are not tile layers.
+    pub fn get_gid(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        self.get_data()?.get((y * self.width + x) as usize).copied()
+    }
+
+    /// Set the gid of a cell of a tile layer, returning the gid it replaced.
+    /// Nothing is changed, and None is returned, outside of the layer and for
+    /// layers that are not tile layers.
+    pub fn set_gid(&mut self, x: u32, y: u32, gid: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        let width = self.width;
+        let cell = self.get_data_mut()?.get_mut((y * width + x) as usize)?;
+        Option::Some(std::mem::replace(cell, gid))
+    }
+
+    /// Set every cell of a rectangle to gid.  The part of the rectangle outside
+    /// of the layer is ignored.
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, gid: u32) {
+        let (right, bottom) = self.clip(x, y, width, height);
+        let layer_width = self.width as usize;
+        if let Option::Some(data) = self.get_data_mut() {
+            for cy in y..bottom {
+                for cx in x..right {
+                    if let Option::Some(cell) =
+                        data.get_mut(cy as usize * layer_width + cx as usize)
+                    {
+                        *cell = gid;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copy a rectangle of a tile layer into a stamp.  The rectangle is clipped
+    /// to the layer first, so the stamp is smaller than asked when the
+    /// rectangle reaches past the right or bottom edge, and empty when it lies
+    /// outside of the layer.
+    pub fn copy_region(&self, x: u32, y: u32, width: u32, height: u32) -> TileStamp {
+        let (right, bottom) = self.clip(x, y, width, height);
+        let (width, height) = (right.saturating_sub(x), bottom.saturating_sub(y));
+        let mut data = Vec::with_capacity(width as usize * height as usize);
+        for cy in y..bottom {
+            for cx in x..right {
+                data.push(self.get_gid(cx, cy).unwrap_or(0));
+            }
+        }
+        TileStamp {
+            width,
+            height,
+            data,
+        }
+    }
+
+    // The right and bottom edges, exclusive, of a rectangle clipped to the
+    // layer.
+    fn clip(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        (
+            x.saturating_add(width).min(self.width),
+            y.saturating_add(height).min(self.height),
+        )
+    }
+
+    /// Paste a stamp with its top-left corner on cell (x, y), which may lie
+    /// outside of the layer.  When transparent is true, empty cells of the
+    /// stamp leave the layer untouched; otherwise they clear it.
+    pub fn paste_region(&mut self, stamp: &TileStamp, x: i64, y: i64, transparent: bool) {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let data = match self.get_data_mut() {
+            Option::Some(d) => d,
+            Option::None => return,
+        };
+        for sy in 0..stamp.height as i64 {
+            for sx in 0..stamp.width as i64 {
+                let (tx, ty) = (x + sx, y + sy);
+                if tx < 0 || ty < 0 || tx >= width || ty >= height {
+                    continue;
+                }
+                let gid = stamp.data[(sy * stamp.width as i64 + sx) as usize];
+                if transparent && crate::gid_without_flags(gid) == 0 {
+                    continue;
+                }
+                if let Option::Some(cell) = data.get_mut((ty * width + tx) as usize) {
+                    *cell = gid;
+                }
+            }
+        }
+    }
+
+    /// Mirror a tile layer left to right.
+    pub fn flip_horizontally(&mut self) {
+        self.transform_cells(TileStamp::flip_horizontally);
+    }
+
+    /// Mirror a tile layer top to bottom.
+    pub fn flip_vertically(&mut self) {
+        self.transform_cells(TileStamp::flip_vertically);
+    }
+
+    /// Turn a tile layer a quarter turn clockwise.  The width and height of the
+    /// layer are swapped; unless the layer is square, the map holding it must be
+    /// resized to match.
+    pub fn rotate_clockwise(&mut self) {
+        self.transform_cells(TileStamp::rotate_clockwise);
+    }
+
+    /// Turn a tile layer a quarter turn counterclockwise.  The width and height
+    /// of the layer are swapped; unless the layer is square, the map holding it
+    /// must be resized to match.
+    pub fn rotate_counterclockwise(&mut self) {
+        self.transform_cells(TileStamp::rotate_counterclockwise);
+    }
+
+    fn transform_cells(&mut self, transform: fn(&mut TileStamp)) {
+        let mut stamp = self.copy_region(0, 0, self.width, self.height);
+        transform(&mut stamp);
+        if let Option::Some(data) = self.get_data_mut() {
+            *data = stamp.data;
+            self.width = stamp.width;
+            self.height = stamp.height;
+        }
+    }
+}
+
+/// The gid of a tile mirrored left to right, flags adjusted.  Gid 0 stays 0.
+pub fn gid_flip_horizontally(gid: u32) -> u32 {
+    transform_gid(gid, MIRROR_X)
+}
+
+/// The gid of a tile mirrored top to bottom, flags adjusted.  Gid 0 stays 0.
+pub fn gid_flip_vertically(gid: u32) -> u32 {
+    transform_gid(gid, MIRROR_Y)
+}
+
+/// The gid of a tile turned a quarter turn clockwise, flags adjusted.  Gid 0
+/// stays 0.
+pub fn gid_rotate_clockwise(gid: u32) -> u32 {
+    transform_gid(gid, ROTATE_CW)
+}
+
+/// The gid of a tile turned a quarter turn counterclockwise, flags adjusted.
+/// Gid 0 stays 0.
+pub fn gid_rotate_counterclockwise(gid: u32) -> u32 {
+    transform_gid(gid, ROTATE_CCW)
+}
+
+// Move every cell (x, y) of data to to(x, y) in a grid new_width wide, turning
+// each gid by matrix.
+fn remap<T>(
+    data: &[u32],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    to: T,
+    matrix: Matrix,
+) -> Vec<u32>
+where
+    T: Fn(u32, u32) -> (u32, u32),
+{
+    let mut out = vec![0; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (nx, ny) = to(x, y);
+            out[(ny * new_width + nx) as usize] =
+                transform_gid(data[(y * width + x) as usize], matrix);
+        }
+    }
+    out
+}
+
+// Apply matrix after the flips already on gid, and find the flags giving the
+// combined transform.
+fn transform_gid(gid: u32, matrix: Matrix) -> u32 {
+    if crate::gid_without_flags(gid) == 0 {
+        return gid;
+    }
+    let target = multiply(matrix, flags_matrix(gid));
+    let flags = (0..8u32)
+        .map(|i| {
+            let mut f = 0;
+            if i & 1 != 0 {
+                f |= crate::HORZ_FLIP_FLAG;
+            }
+            if i & 2 != 0 {
+                f |= crate::VERT_FLIP_FLAG;
+            }
+            if i & 4 != 0 {
+                f |= crate::DIAG_FLIP_FLAG;
+            }
+            f
+        })
+        .find(|f| flags_matrix(*f) == target)
+        .unwrap_or(0);
+    crate::gid_without_flags(gid) | flags
+}
+
+// Tiled flips a tile diagonally first, then horizontally, then vertically.
+fn flags_matrix(gid: u32) -> Matrix {
+    let (h, v, d) = crate::gid_flipped_hvd(gid & FLAGS);
+    let mut m: Matrix = [[1, 0], [0, 1]];
+    if d {
+        m = multiply([[0, 1], [1, 0]], m);
+    }
+    if h {
+        m = multiply(MIRROR_X, m);
+    }
+    if v {
+        m = multiply(MIRROR_Y, m);
+    }
+    m
+}
+
+fn multiply(a: Matrix, b: Matrix) -> Matrix {
+    let mut m = [[0; 2]; 2];
+    for (r, row) in m.iter_mut().enumerate() {
+        for (c, cell) in row.iter_mut().enumerate() {
+            *cell = a[r][0] * b[0][c] + a[r][1] * b[1][c];
+        }
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flags_matrix;
+    use super::gid_flip_horizontally;
+    use super::gid_flip_vertically;
+    use super::gid_rotate_clockwise;
+    use super::gid_rotate_counterclockwise;
+    use super::TileStamp;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::DIAG_FLIP_FLAG as D;
+    use crate::HORZ_FLIP_FLAG as H;
+    use crate::VERT_FLIP_FLAG as V;
+
+    const ALL_FLAGS: [u32; 8] = [0, H, V, D, H | V, H | D, V | D, H | V | D];
+
+    // A 3x2 map whose only layer holds gids 1 to 6, row by row.
+    fn layer() -> Layer {
+        let mut map: Map = serde_json::from_str(
+            r#"{
+                "orientation": "orthogonal", "width": 3, "height": 2,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 2,
+                "layers": [{ "type": "tilelayer", "id": 1, "name": "ground",
+                             "width": 3, "height": 2, "data": [1, 2, 3, 4, 5, 6] }]
+            }"#,
+        )
+        .unwrap();
+        map.layers.remove(0)
+    }
+
+    #[test]
+    fn flags_give_every_turn_and_mirror_once() {
+        // Diagonal flip transposes, then horizontal and vertical flips negate x
+        // and y.
+        assert_eq!(flags_matrix(0), [[1, 0], [0, 1]]);
+        assert_eq!(flags_matrix(H), [[-1, 0], [0, 1]]);
+        assert_eq!(flags_matrix(V), [[1, 0], [0, -1]]);
+        assert_eq!(flags_matrix(D), [[0, 1], [1, 0]]);
+        assert_eq!(flags_matrix(H | V), [[-1, 0], [0, -1]]);
+        assert_eq!(flags_matrix(H | D), [[0, -1], [1, 0]]);
+        assert_eq!(flags_matrix(V | D), [[0, 1], [-1, 0]]);
+        assert_eq!(flags_matrix(H | V | D), [[0, -1], [-1, 0]]);
+    }
+
+    #[test]
+    fn gid_transforms_compose_like_tiled() {
+        assert_eq!(gid_rotate_clockwise(7), 7 | H | D);
+        assert_eq!(gid_rotate_clockwise(gid_rotate_clockwise(7)), 7 | H | V);
+        assert_eq!(gid_rotate_counterclockwise(7), 7 | V | D);
+        assert_eq!(gid_rotate_clockwise(7 | H), 7 | H | V | D);
+        assert_eq!(gid_flip_horizontally(7 | D), 7 | H | D);
+        assert_eq!(gid_flip_vertically(7 | H | D), 7 | H | V | D);
+        assert_eq!(gid_rotate_clockwise(0), 0);
+
+        for flags in ALL_FLAGS.iter() {
+            let gid = 7 | flags;
+            let turned = (0..4).fold(gid, |g, _| gid_rotate_clockwise(g));
+            assert_eq!(turned, gid);
+            assert_eq!(gid_rotate_counterclockwise(gid_rotate_clockwise(gid)), gid);
+            assert_eq!(gid_flip_horizontally(gid_flip_horizontally(gid)), gid);
+            assert_eq!(gid_flip_vertically(gid_flip_vertically(gid)), gid);
+            // Mirroring both ways is a half turn.
+            assert_eq!(
+                gid_flip_vertically(gid_flip_horizontally(gid)),
+                gid_rotate_clockwise(gid_rotate_clockwise(gid))
+            );
+        }
+    }
+
+    #[test]
+    fn layers_move_cells_and_turn_tiles() {
+        let mut rotated = layer();
+        rotated.rotate_clockwise();
+        assert_eq!((rotated.width, rotated.height), (2, 3));
+        let hd = H | D;
+        assert_eq!(
+            rotated.get_data().unwrap(),
+            &vec![4 | hd, 1 | hd, 5 | hd, 2 | hd, 6 | hd, 3 | hd]
+        );
+        rotated.rotate_counterclockwise();
+        assert_eq!(rotated.get_data().unwrap(), &vec![1, 2, 3, 4, 5, 6]);
+
+        let mut flipped = layer();
+        flipped.flip_horizontally();
+        assert_eq!(
+            flipped.get_data().unwrap(),
+            &vec![3 | H, 2 | H, 1 | H, 6 | H, 5 | H, 4 | H]
+        );
+        flipped.flip_vertically();
+        let hv = H | V;
+        assert_eq!(
+            flipped.get_data().unwrap(),
+            &vec![6 | hv, 5 | hv, 4 | hv, 3 | hv, 2 | hv, 1 | hv]
+        );
+    }
+
+    #[test]
+    fn stamps_copy_and_paste() {
+        let mut layer = layer();
+        // The rectangle is clipped to the layer.
+        let mut stamp = layer.copy_region(1, 0, 3, 1);
+        assert_eq!((stamp.width(), stamp.height()), (2, 1));
+        assert_eq!(stamp.data(), &vec![2, 3]);
+        stamp.rotate_clockwise();
+        assert_eq!((stamp.width(), stamp.height()), (1, 2));
+
+        layer.paste_region(&stamp, 0, -1, true);
+        assert_eq!(layer.get_data().unwrap(), &vec![3 | H | D, 2, 3, 4, 5, 6]);
+        layer.paste_region(&stamp, 2, -1, false);
+        assert_eq!(layer.get_gid(2, 0), Option::Some(3 | H | D));
+        let empty = TileStamp::new(1, 1, 0).unwrap();
+        layer.paste_region(&empty, 2, 1, true);
+        assert_eq!(layer.get_gid(2, 1), Option::Some(6));
+        layer.paste_region(&empty, 2, 1, false);
+        assert_eq!(layer.get_gid(2, 1), Option::Some(0));
+
+        layer.fill_rect(1, 1, 5, 5, 9);
+        assert_eq!(
+            layer.get_data().unwrap(),
+            &vec![3 | H | D, 2, 3 | H | D, 4, 9, 9]
+        );
+        assert_eq!(layer.set_gid(3, 0, 1), Option::None);
+    }
+
+    #[test]
+    fn huge_rectangles_are_clipped_before_anything_is_allocated() {
+        let mut layer = layer();
+        layer.fill_rect(u32::MAX - 1, 0, u32::MAX, u32::MAX, 7);
+        layer.fill_rect(2, 1, u32::MAX, u32::MAX, 7);
+        assert_eq!(layer.get_data().unwrap(), &vec![1, 2, 3, 4, 5, 7]);
+
+        let all = layer.copy_region(0, 0, u32::MAX, u32::MAX);
+        assert_eq!((all.width(), all.height()), (3, 2));
+        assert_eq!(all.data(), layer.get_data().unwrap());
+        let outside = layer.copy_region(5, 5, u32::MAX, u32::MAX);
+        assert_eq!((outside.width(), outside.height()), (0, 0));
+        assert!(outside.data().is_empty());
+    }
+}
@@ -0,0 +1,374 @@
+//!
+//! Field-of-view and line-of-sight over the cells of a tile layer.
+//!
+//! FieldOfView::compute() finds every cell visible from a cell within a radius
+//! using recursive shadowcasting.  line_of_sight() checks whether two cells can
+//! see each other along a straight line, walked either with Bresenham's
+//! algorithm or as a supercover line (every cell the line touches, including
+//! both cells beside a corner it passes exactly through).
+//!
+//! Opacity is decided per gid, by a closure receiving each gid as stored in the
+//! layer data (flags included, 0 for empty cells) or by a boolean tile property
+//! such as `opaque`.  Cells outside the map block sight.  The cell looked from
+//! and the cell looked at never block themselves, so walls can be seen.
+//!
+//! Distances are measured in cells on a square grid, which suits orthogonal and
+//! isometric maps.
+//!
+
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::property::HasProperty;
+
+// Octant transforms for shadowcasting: (xx, xy, yx, yy).
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The cells visible from a point of view, indexed like tile layer data
+/// (y * width + x).
+pub struct FieldOfView {
+    pub width: u32,
+    pub height: u32,
+    pub visible: Vec<bool>,
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// LineAlgorithm selects how a line between two cells is walked:
+/// - Bresenham: one cell per step along the longer axis.
+/// - Supercover: every cell the line touches.  More conservative; sight does
+///   not slip between two diagonal walls.
+pub enum LineAlgorithm {
+    Bresenham,
+    Supercover,
+}
+
+impl FieldOfView {
+    /// Compute the cells visible from origin within radius cells in a tile layer.
+    ///
+    /// opaque receives each gid of the layer and returns true for the ones that
+    /// block sight.  The origin is always visible.
+    pub fn compute<F>(map: &Map, layer: &Layer, origin: (u32, u32), radius: u32, opaque: F) -> FieldOfView
+    where
+        F: Fn(u32) -> bool,
+    {
+        let width = map.width as u32;
+        let height = map.height as u32;
+        let mut fov = FieldOfView {
+            width,
+            height,
+            visible: vec![false; (width * height) as usize],
+        };
+        if origin.0 >= width || origin.1 >= height {
+            return fov;
+        }
+
+        let blocks = |x: i32, y: i32| is_opaque(layer, width, height, x, y, &opaque);
+        fov.visible[(origin.1 * width + origin.0) as usize] = true;
+        for octant in OCTANTS.iter() {
+            fov.cast_light(
+                (origin.0 as i32, origin.1 as i32),
+                1,
+                1.0,
+                0.0,
+                radius as i32,
+                *octant,
+                &blocks,
+            );
+        }
+        fov
+    }
+
+    /// Compute the cells visible from origin within radius cells, with the tiles
+    /// whose boolean property name is true blocking sight.
+    pub fn from_property(map: &Map, layer: &Layer, origin: (u32, u32), radius: u32, name: &str) -> FieldOfView {
+        FieldOfView::compute(map, layer, origin, radius, |gid| {
+            map.tile_by_gid(gid)
+                .and_then(|t| t.get_property(name))
+                .and_then(|p| p.get_bool())
+                .unwrap_or(false)
+        })
+    }
+
+    /// Width of the field in cells.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the field in cells.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Is the cell visible?  Cells outside the map never are.
+    pub fn is_visible(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.visible[(y * self.width + x) as usize]
+    }
+
+    /// List every visible cell, row by row.
+    pub fn visible_cells(&self) -> Vec<(u32, u32)> {
+        self.visible
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v)
+            .map(|(i, _)| (i as u32 % self.width, i as u32 / self.width))
+            .collect()
+    }
+
+    // Recursive shadowcasting of one octant, scanning rows outward from row and
+    // lighting the cells between the start and end slopes.
+    #[allow(clippy::too_many_arguments)]
+    fn cast_light<B>(
+        &mut self,
+        origin: (i32, i32),
+        row: i32,
+        mut start: f64,
+        end: f64,
+        radius: i32,
+        octant: (i32, i32, i32, i32),
+        blocks: &B,
+    ) where
+        B: Fn(i32, i32) -> bool,
+    {
+        if start < end {
+            return;
+        }
+        let (xx, xy, yx, yy) = octant;
+        let mut new_start = 0.0;
+        for j in row..=radius {
+            let dy = -j;
+            let mut blocked = false;
+            for dx in -j..=0 {
+                let x = origin.0 + dx * xx + dy * xy;
+                let y = origin.1 + dx * yx + dy * yy;
+                let l_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let r_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                if start < r_slope {
+                    continue;
+                } else if end > l_slope {
+                    break;
+                }
+
+                if dx * dx + dy * dy <= radius * radius
+                    && x >= 0
+                    && y >= 0
+                    && (x as u32) < self.width
+                    && (y as u32) < self.height
+                {
+                    self.visible[(y as u32 * self.width + x as u32) as usize] = true;
+                }
+
+                if blocked {
+                    if blocks(x, y) {
+                        new_start = r_slope;
+                    } else {
+                        blocked = false;
+                        start = new_start;
+                    }
+                } else if blocks(x, y) && j < radius {
+                    blocked = true;
+                    self.cast_light(origin, j + 1, start, l_slope, radius, octant, blocks);
+                    new_start = r_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+/// Can from see to in a tile layer?  Every cell on the line between them, but
+/// not the two ends, must be clear.
+///
+/// opaque receives each gid of the layer and returns true for the ones that
+/// block sight.
+pub fn line_of_sight<F>(
+    map: &Map,
+    layer: &Layer,
+    from: (u32, u32),
+    to: (u32, u32),
+    algorithm: LineAlgorithm,
+    opaque: F,
+) -> bool
+where
+    F: Fn(u32) -> bool,
+{
+    let width = map.width as u32;
+    let height = map.height as u32;
+    let (x0, y0) = (from.0 as i32, from.1 as i32);
+    let (x1, y1) = (to.0 as i32, to.1 as i32);
+    let cells = match algorithm {
+        LineAlgorithm::Bresenham => bresenham_line((x0, y0), (x1, y1)),
+        LineAlgorithm::Supercover => supercover_line((x0, y0), (x1, y1)),
+    };
+    cells
+        .into_iter()
+        .filter(|c| *c != (x0, y0) && *c != (x1, y1))
+        .all(|(x, y)| !is_opaque(layer, width, height, x, y, &opaque))
+}
+
+/// The cells on a Bresenham line from one cell to another, both ends included.
+pub fn bresenham_line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut cells = vec![(x, y)];
+    while (x, y) != to {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+        cells.push((x, y));
+    }
+    cells
+}
+
+/// The cells touched by a line from the centre of one cell to the centre of
+/// another, both ends included.  When the line passes exactly through a corner,
+/// both cells beside that corner are included.
+pub fn supercover_line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let nx = (to.0 - x).abs();
+    let ny = (to.1 - y).abs();
+    let sx = if to.0 > x { 1 } else { -1 };
+    let sy = if to.1 > y { 1 } else { -1 };
+    let (mut ix, mut iy) = (0, 0);
+    let mut cells = vec![(x, y)];
+    while ix < nx || iy < ny {
+        let decision = (1 + 2 * ix) * ny - (1 + 2 * iy) * nx;
+        if decision == 0 {
+            cells.push((x + sx, y));
+            cells.push((x, y + sy));
+            x += sx;
+            y += sy;
+            ix += 1;
+            iy += 1;
+        } else if decision < 0 {
+            x += sx;
+            ix += 1;
+        } else {
+            y += sy;
+            iy += 1;
+        }
+        cells.push((x, y));
+    }
+    cells
+}
+
+fn is_opaque<F>(layer: &Layer, width: u32, height: u32, x: i32, y: i32, opaque: &F) -> bool
+where
+    F: Fn(u32) -> bool,
+{
+    if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+        return true;
+    }
+    match layer.get_data() {
+        Option::Some(data) => data
+            .get((y as u32 * width + x as u32) as usize)
+            .is_none_or(|gid| opaque(*gid)),
+        Option::None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::line_of_sight;
+    use super::FieldOfView;
+    use super::LineAlgorithm;
+    use crate::map::Map;
+
+    // A 7x7 map with a pillar (gid 1) on cell (3, 2) and a crate (gid 2) on
+    // cell (1, 0).
+    fn pillar() -> Map {
+        let mut data = vec![0; 49];
+        data[2 * 7 + 3] = 1;
+        data[1] = 2;
+        let json = format!(
+            r#"{{
+                "orientation": "orthogonal", "width": 7, "height": 7,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 2,
+                "layers": [{{ "type": "tilelayer", "id": 1, "name": "walls",
+                              "width": 7, "height": 7, "data": {:?} }}]
+            }}"#,
+            data
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn pillar_casts_a_shadow() {
+        let map = pillar();
+        // The crate is see-through here.
+        let fov = FieldOfView::compute(&map, &map.layers[0], (3, 0), 6, |gid| gid == 1);
+        assert!(fov.is_visible(3, 0));
+        assert!(fov.is_visible(3, 2));
+        assert!(!fov.is_visible(3, 3));
+        assert!(!fov.is_visible(3, 6));
+        assert!(fov.is_visible(0, 0));
+        assert!(fov.is_visible(0, 5));
+        // Beyond the radius.
+        assert!(!fov.is_visible(0, 6));
+
+        let near = FieldOfView::compute(&map, &map.layers[0], (0, 0), 2, |gid| gid == 1);
+        assert!(near.is_visible(2, 0));
+        assert!(!near.is_visible(3, 0));
+    }
+
+    #[test]
+    fn supercover_is_blocked_at_corners() {
+        let map = pillar();
+        let opaque = |gid| gid != 0;
+        let layer = &map.layers[0];
+        assert!(line_of_sight(
+            &map,
+            layer,
+            (0, 0),
+            (2, 2),
+            LineAlgorithm::Bresenham,
+            opaque
+        ));
+        assert!(!line_of_sight(
+            &map,
+            layer,
+            (0, 0),
+            (2, 2),
+            LineAlgorithm::Supercover,
+            opaque
+        ));
+        assert!(!line_of_sight(
+            &map,
+            layer,
+            (3, 0),
+            (3, 4),
+            LineAlgorithm::Bresenham,
+            opaque
+        ));
+        assert!(line_of_sight(
+            &map,
+            layer,
+            (3, 1),
+            (3, 2),
+            LineAlgorithm::Bresenham,
+            opaque
+        ));
+    }
+}
@@ -0,0 +1,188 @@
+//!
+//! Grid helpers shared by the modules that walk over tile layer cells.
+//!
+//! Which cells count as neighbours depends on the orientation of the map:
+//! orthogonal and isometric maps use plain 4-way or 8-way neighbourhoods,
+//! hexagonal maps have six neighbours whose positions depend on the stagger
+//! axis and index, and staggered maps connect each diamond to the four cells
+//! it shares an edge with.  Neighborhood captures those rules so pathfinding,
+//! flood fills and the like treat every orientation the same way.
+//!
+//! Cells are addressed by (x, y) in tiles, with (0, 0) in the top-left corner,
+//! exactly like the index into tile layer data (y * width + x).
+//!
+
+use crate::map::Map;
+use crate::map::MapOrientation;
+use crate::map::StaggerAxis;
+use crate::map::StaggerIndex;
+
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Neighborhood describes which cells are adjacent to a cell.  It has 4 variants:
+/// - Four: left, right, up and down.
+/// - Eight: Four plus the diagonals.
+/// - Hex: the six cells sharing an edge with a hexagon.
+/// - Staggered: the four cells sharing an edge with a staggered diamond, plus
+///   the four touching its corners when the boolean is true.
+pub enum Neighborhood {
+    Four,
+    Eight,
+    Hex(StaggerAxis, StaggerIndex),
+    Staggered(StaggerAxis, StaggerIndex, bool),
+}
+
+impl Neighborhood {
+    /// Choose the neighborhood matching the orientation of a map.
+    ///
+    /// diagonal selects 8-way movement on orthogonal and isometric maps and adds
+    /// corner neighbours on staggered maps.  It has no effect on hexagonal maps.
+    pub fn from_map(map: &Map, diagonal: bool) -> Neighborhood {
+        let axis = map.staggeraxis.unwrap_or(StaggerAxis::StaggerY);
+        let index = map.staggerindex.unwrap_or(StaggerIndex::Odd);
+        match map.orientation {
+            MapOrientation::Orthogonal | MapOrientation::Isometric => {
+                if diagonal {
+                    Neighborhood::Eight
+                } else {
+                    Neighborhood::Four
+                }
+            }
+            MapOrientation::Hexagonal => Neighborhood::Hex(axis, index),
+            MapOrientation::Staggered => Neighborhood::Staggered(axis, index, diagonal),
+        }
+    }
+
+    /// List the neighbours of the cell (x, y).  Cells outside of the map are
+    /// included; it is up to the caller to drop them.
+    pub fn neighbors(self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        match self {
+            Neighborhood::Four => vec![(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)],
+            Neighborhood::Eight => vec![
+                (x, y - 1),
+                (x + 1, y - 1),
+                (x + 1, y),
+                (x + 1, y + 1),
+                (x, y + 1),
+                (x - 1, y + 1),
+                (x - 1, y),
+                (x - 1, y - 1),
+            ],
+            Neighborhood::Hex(axis, index) => {
+                let mut n = staggered_edges(axis, index, x, y);
+                match axis {
+                    StaggerAxis::StaggerY => n.extend_from_slice(&[(x - 1, y), (x + 1, y)]),
+                    StaggerAxis::StaggerX => n.extend_from_slice(&[(x, y - 1), (x, y + 1)]),
+                }
+                n
+            }
+            Neighborhood::Staggered(axis, index, diagonal) => {
+                let mut n = staggered_edges(axis, index, x, y);
+                if diagonal {
+                    match axis {
+                        StaggerAxis::StaggerY => n.extend_from_slice(&[
+                            (x - 1, y),
+                            (x + 1, y),
+                            (x, y - 2),
+                            (x, y + 2),
+                        ]),
+                        StaggerAxis::StaggerX => n.extend_from_slice(&[
+                            (x, y - 1),
+                            (x, y + 1),
+                            (x - 2, y),
+                            (x + 2, y),
+                        ]),
+                    }
+                }
+                n
+            }
+        }
+    }
+
+    /// The length of a single step from (x, y) to the neighbour (nx, ny), in
+    /// cells.  Diagonal steps of an Eight neighborhood measure the square root of
+    /// two; every other step measures one.
+    pub fn step_length(self, x: i32, y: i32, nx: i32, ny: i32) -> f64 {
+        match self {
+            Neighborhood::Eight if x != nx && y != ny => std::f64::consts::SQRT_2,
+            _ => 1.0,
+        }
+    }
+
+    /// The least number of steps between two cells, ignoring obstacles.
+    ///
+    /// This is exact for the Four, Eight (counting diagonals as one step) and Hex
+    /// neighborhoods, as well as Staggered without corner neighbours.  Staggered with
+    /// corner neighbours gives a lower bound of the real value.
+    pub fn distance(self, x0: i32, y0: i32, x1: i32, y1: i32) -> u32 {
+        let dx = (x1 - x0).abs();
+        let dy = (y1 - y0).abs();
+        match self {
+            Neighborhood::Four => (dx + dy) as u32,
+            Neighborhood::Eight => dx.max(dy) as u32,
+            Neighborhood::Hex(axis, index) => {
+                let (q0, r0) = hex_axial(axis, index, x0, y0);
+                let (q1, r1) = hex_axial(axis, index, x1, y1);
+                let (dq, dr) = (q1 - q0, r1 - r0);
+                ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u32
+            }
+            Neighborhood::Staggered(axis, index, diagonal) => {
+                // Measured in half tiles across the stagger axis, every edge step
+                // moves one half tile along both axes.
+                let half = |a: i32, x: i32, y: i32| {
+                    2 * a + if is_shifted(axis, index, x, y) { 1 } else { 0 }
+                };
+                let (along, across) = match axis {
+                    StaggerAxis::StaggerY => (dy, (half(x1, x1, y1) - half(x0, x0, y0)).abs()),
+                    StaggerAxis::StaggerX => (dx, (half(y1, x1, y1) - half(y0, x0, y0)).abs()),
+                };
+                if diagonal {
+                    ((along.max(across) + 1) / 2) as u32
+                } else {
+                    along.max(across) as u32
+                }
+            }
+        }
+    }
+}
+
+/// Is the row (for StaggerY) or column (for StaggerX) holding the cell shifted
+/// by half a tile?
+pub fn is_shifted(axis: StaggerAxis, index: StaggerIndex, x: i32, y: i32) -> bool {
+    let n = match axis {
+        StaggerAxis::StaggerY => y,
+        StaggerAxis::StaggerX => x,
+    };
+    let odd = n.rem_euclid(2) == 1;
+    match index {
+        StaggerIndex::Odd => odd,
+        StaggerIndex::Even => !odd,
+    }
+}
+
+// The four cells sharing an edge with (x, y) across the stagger axis.
+fn staggered_edges(axis: StaggerAxis, index: StaggerIndex, x: i32, y: i32) -> Vec<(i32, i32)> {
+    let shifted = is_shifted(axis, index, x, y);
+    match axis {
+        StaggerAxis::StaggerY => {
+            let (l, r) = if shifted { (x, x + 1) } else { (x - 1, x) };
+            vec![(l, y - 1), (r, y - 1), (r, y + 1), (l, y + 1)]
+        }
+        StaggerAxis::StaggerX => {
+            let (t, b) = if shifted { (y, y + 1) } else { (y - 1, y) };
+            vec![(x - 1, t), (x + 1, t), (x + 1, b), (x - 1, b)]
+        }
+    }
+}
+
+// Convert staggered hex coordinates to axial coordinates (q, r).
+fn hex_axial(axis: StaggerAxis, index: StaggerIndex, x: i32, y: i32) -> (i32, i32) {
+    let round_up = match index {
+        StaggerIndex::Odd => 0,
+        StaggerIndex::Even => 1,
+    };
+    match axis {
+        StaggerAxis::StaggerY => (x - (y + round_up).div_euclid(2), y),
+        StaggerAxis::StaggerX => (x, y - (x + round_up).div_euclid(2)),
+    }
+}
@@ -51,7 +51,13 @@
 //! 
 //!         // Get group data if the layer refers to a group of layers.
 //!         tiled_json::Layer::get_layers(&self) -> Option<&Vec<Layer>>;
-//! 
+//!
+//! Layers also carry parallax factors, a tint color and a class, and image
+//! layers may repeat their image along either axis.  Since groups affect the
+//! layers they hold, Layer::transform() and LayerTransform::combine() work out
+//! the offset, opacity, visibility, parallax and tint a layer is really drawn
+//! with, and LayerTransform::screen_offset() where it lands for a camera.
+//!
 //! This struct implements the trait HasProperty, which enables easy access of 
 //! Tiled properties for layers.  The relevant functions are:
 //!     
@@ -63,6 +69,7 @@
 
 use crate::color::Color;
 use crate::layerreader::LayerReader;
+use crate::map::Map;
 use crate::object::Object;
 use crate::property::HasProperty;
 use crate::property::Property;
@@ -77,7 +84,7 @@ pub const LAYER_IMAGE: &str = "imagelayer";
 pub const LAYER_GROUP: &str = "group";
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(try_from = "LayerReader")]
 /// The primary method of describing nodes in maps.
 pub struct Layer {
@@ -91,6 +98,16 @@ pub struct Layer {
     pub offsetx: f64,
     pub offsety: f64,
 
+    pub parallaxx: f64,
+    pub parallaxy: f64,
+    pub tintcolor: Option<Color>,
+
+    // Only used by image layers.
+    pub repeatx: bool,
+    pub repeaty: bool,
+
+    pub class: String,
+
     #[serde(rename = "type")]
     pub ltype: LayerType,
 
@@ -224,6 +241,16 @@ impl Layer {
         }
     }
 
+    /// Mutably borrow the tile data of a Tile Layer, for editing.
+    /// It will return None if this layer is not a TileLayer.
+    pub fn get_data_mut(&mut self) -> Option<&mut Vec<u32>> {
+        if let LayerDataContainer::TileLayer { data: ref mut x } = self.layerdata {
+            Option::Some(x)
+        } else {
+            Option::None
+        }
+    }
+
     /// A shortcut method to get the draworder of an objgroup layer.
     /// it will return None if the layer is not an ObjGroup layer.
     pub fn get_draworder(&self) -> Option<DrawOrder> {
@@ -250,6 +277,20 @@ impl Layer {
         }
     }
 
+    /// Mutably borrow the Vector of Objects in an ObjectGroup layer, for editing.
+    /// This will return None if the layer is not an ObjectGroup layer.
+    pub fn get_objects_vector_mut(&mut self) -> Option<&mut Vec<Object>> {
+        if let LayerDataContainer::ObjectGroup {
+            objects: ref mut obj,
+            ..
+        } = self.layerdata
+        {
+            Option::Some(obj)
+        } else {
+            Option::None
+        }
+    }
+
     /// A shortcut method to borrow the image string of an ImageLayer.
     /// You will get None back if self doesn't reference an ImageLayer.
     pub fn get_image(&self) -> Option<&String> {
@@ -285,6 +326,19 @@ impl Layer {
         }
     }
 
+    /// Mutably borrow the layers of a Group layer, for editing.
+    /// This will return None if self is not describing a Group layer.
+    pub fn get_layers_mut(&mut self) -> Option<&mut Vec<Layer>> {
+        if let LayerDataContainer::Group {
+            layers: ref mut lays,
+        } = self.layerdata
+        {
+            Option::Some(lays)
+        } else {
+            Option::None
+        }
+    }
+
     /// Get the x value of the layer (always 0).
     pub fn x(&self) -> u32 {
         0
@@ -336,12 +390,129 @@ impl Layer {
         self.offsety
     }
 
+    /// Get the horizontal parallax factor of the layer.  1.0 scrolls with the
+    /// map, smaller values scroll slower and 0.0 stays fixed on screen.
+    pub fn parallax_x(&self) -> f64 {
+        self.parallaxx
+    }
+
+    /// Get the vertical parallax factor of the layer.
+    pub fn parallax_y(&self) -> f64 {
+        self.parallaxy
+    }
+
+    /// Get the color multiplied with every pixel of the layer, if any.
+    pub fn tint_color(&self) -> Option<Color> {
+        self.tintcolor
+    }
+
+    /// Is the image of an image layer repeated horizontally?
+    pub fn repeat_x(&self) -> bool {
+        self.repeatx
+    }
+
+    /// Is the image of an image layer repeated vertically?
+    pub fn repeat_y(&self) -> bool {
+        self.repeaty
+    }
+
+    /// Get the class of the layer.  Empty when unset.
+    pub fn class(&self) -> &String {
+        &self.class
+    }
+
     /// Get the Layer Type: one of LayerType::{Tile Layer, ObjectGroup, ImageLayer, Group}
     pub fn layer_type(&self) -> LayerType {
         self.ltype
     }
+
+    /// The transform of this layer alone, as if it were not inside any group.
+    pub fn transform(&self) -> LayerTransform {
+        LayerTransform::default().combine(self)
+    }
 }
 
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// LayerTransform is the combined effect of a layer and the groups holding it.
+/// Tiled combines them this way:
+/// - offsets add up,
+/// - opacities and parallax factors multiply,
+/// - tint colors multiply component by component,
+/// - a layer is visible only when all of its groups are.
+///
+/// Map::layer_transforms() gives the transform of every layer of a map.
+pub struct LayerTransform {
+    pub offsetx: f64,
+    pub offsety: f64,
+    pub opacity: f64,
+    pub visible: bool,
+    pub parallaxx: f64,
+    pub parallaxy: f64,
+    pub tintcolor: Option<Color>,
+}
+
+impl Default for LayerTransform {
+    /// The identity transform: no offset, fully opaque, visible, no parallax
+    /// and no tint.
+    fn default() -> LayerTransform {
+        LayerTransform {
+            offsetx: 0.0,
+            offsety: 0.0,
+            opacity: 1.0,
+            visible: true,
+            parallaxx: 1.0,
+            parallaxy: 1.0,
+            tintcolor: Option::None,
+        }
+    }
+}
+
+impl LayerTransform {
+    /// The transform of a layer placed inside a group with this transform.
+    pub fn combine(&self, layer: &Layer) -> LayerTransform {
+        let tintcolor = match (self.tintcolor, layer.tintcolor) {
+            (Option::Some(a), Option::Some(b)) => Option::Some(Color {
+                r: multiply_u8(a.r, b.r),
+                g: multiply_u8(a.g, b.g),
+                b: multiply_u8(a.b, b.b),
+                a: multiply_u8(a.a, b.a),
+            }),
+            (a, b) => a.or(b),
+        };
+        LayerTransform {
+            offsetx: self.offsetx + layer.offsetx,
+            offsety: self.offsety + layer.offsety,
+            opacity: self.opacity * layer.opacity,
+            visible: self.visible && layer.visible,
+            parallaxx: self.parallaxx * layer.parallaxx,
+            parallaxy: self.parallaxy * layer.parallaxy,
+            tintcolor,
+        }
+    }
+
+    /// The offset at which the layer must be drawn on screen, in pixels, for a
+    /// camera centred on (camera_x, camera_y) in map pixels.
+    ///
+    /// A layer with parallax factors of 1.0 is drawn at its own offset wherever
+    /// the camera is.  Otherwise the layer is shifted along with the camera by
+    /// (1 - parallax) times the distance between the camera and the parallax
+    /// origin of the map, so it appears to scroll slower (or faster, for
+    /// factors above 1.0).
+    pub fn screen_offset(&self, map: &Map, camera_x: f64, camera_y: f64) -> (f64, f64) {
+        (
+            self.offsetx + (1.0 - self.parallaxx) * (camera_x - map.parallaxoriginx),
+            self.offsety + (1.0 - self.parallaxy) * (camera_y - map.parallaxoriginy),
+        )
+    }
+}
+
+// Multiply two color components as fractions of 255.
+fn multiply_u8(a: u8, b: u8) -> u8 {
+    ((a as u32 * b as u32 + 127) / 255) as u8
+}
+
+#[derive(Clone)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// The LayerDataContainer is an enum that describes the four different types of 
 /// layers that can be present within a map.  You can access these values
@@ -409,3 +580,92 @@ impl std::fmt::Display for DrawOrder {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::map::Map;
+
+    // A group "sky" holding a tile layer "clouds" and a hidden, repeating image
+    // layer "far", then a plain tile layer "ground".
+    const MAP: &str = r##"{
+        "orientation": "orthogonal", "width": 1, "height": 1,
+        "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 5,
+        "parallaxoriginx": 100,
+        "layers": [
+            { "type": "group", "id": 1, "name": "sky", "offsetx": 10, "opacity": 0.5,
+              "parallaxx": 0.5, "tintcolor": "#ff0000", "layers": [
+                { "type": "tilelayer", "id": 2, "name": "clouds", "class": "weather",
+                  "offsetx": 5, "offsety": 2, "opacity": 0.5,
+                  "parallaxx": 0.5, "parallaxy": 0.25, "tintcolor": "#80ff8000",
+                  "width": 1, "height": 1, "data": [0] },
+                { "type": "imagelayer", "id": 3, "name": "far", "image": "far.png",
+                  "repeatx": true, "visible": false }
+            ] },
+            { "type": "tilelayer", "id": 4, "name": "ground",
+              "width": 1, "height": 1, "data": [0] }
+        ]
+    }"##;
+
+    fn rgba(color: Option<Color>) -> Option<(u8, u8, u8, u8)> {
+        color.map(|c| (c.r, c.g, c.b, c.a))
+    }
+
+    #[test]
+    fn new_fields_are_parsed() {
+        let map: Map = serde_json::from_str(MAP).unwrap();
+        let sky = &map.layers[0];
+        let clouds = &sky.get_layers().unwrap()[0];
+        let far = &sky.get_layers().unwrap()[1];
+        let ground = &map.layers[1];
+
+        assert_eq!((clouds.parallax_x(), clouds.parallax_y()), (0.5, 0.25));
+        assert_eq!(rgba(clouds.tint_color()), Option::Some((255, 128, 0, 128)));
+        assert_eq!(clouds.class(), "weather");
+        assert!(far.repeat_x() && !far.repeat_y());
+
+        assert_eq!((ground.parallax_x(), ground.parallax_y()), (1.0, 1.0));
+        assert!(ground.tint_color().is_none());
+        assert!(ground.class().is_empty());
+        assert!(!ground.repeat_x() && !ground.repeat_y());
+        assert_eq!(map.parallax_origin(), (100.0, 0.0));
+    }
+
+    #[test]
+    fn groups_combine_with_the_layers_they_hold() {
+        let map: Map = serde_json::from_str(MAP).unwrap();
+        let transforms = map.layer_transforms();
+        let names: Vec<&str> = transforms.iter().map(|(l, _)| l.name.as_str()).collect();
+        assert_eq!(names, vec!["sky", "clouds", "far", "ground"]);
+
+        let clouds = transforms[1].1;
+        assert_eq!((clouds.offsetx, clouds.offsety), (15.0, 2.0));
+        assert_eq!(clouds.opacity, 0.25);
+        assert_eq!((clouds.parallaxx, clouds.parallaxy), (0.25, 0.25));
+        assert_eq!(rgba(clouds.tintcolor), Option::Some((255, 0, 0, 128)));
+        assert!(clouds.visible);
+        assert!(!transforms[2].1.visible);
+
+        // The group alone keeps its own tint.
+        assert_eq!(
+            rgba(transforms[0].1.tintcolor),
+            Option::Some((255, 0, 0, 255))
+        );
+        assert_eq!(
+            rgba(map.layers[0].transform().tintcolor),
+            rgba(transforms[0].1.tintcolor)
+        );
+    }
+
+    #[test]
+    fn parallax_shifts_layers_with_the_camera() {
+        let map: Map = serde_json::from_str(MAP).unwrap();
+        let transforms = map.layer_transforms();
+        let clouds = transforms[1].1;
+        assert_eq!(clouds.screen_offset(&map, 300.0, 40.0), (165.0, 32.0));
+        // At the parallax origin every layer sits at its own offset.
+        assert_eq!(clouds.screen_offset(&map, 100.0, 0.0), (15.0, 2.0));
+        let ground = transforms[3].1;
+        assert_eq!(ground.screen_offset(&map, 300.0, 40.0), (0.0, 0.0));
+    }
+}
@@ -3,7 +3,7 @@ use flate2::bufread::GzDecoder;
 use flate2::bufread::ZlibDecoder;
 use std::io::Read;
 
-use serde::export::TryFrom;
+use std::convert::TryFrom;
 use serde::Deserialize;
 
 use crate::color::Color;
@@ -43,6 +43,24 @@ pub struct LayerReader {
     #[serde(default = "default_to_true")]
     visible: bool,
 
+    #[serde(default = "default_to_one_f64")]
+    parallaxx: f64,
+
+    #[serde(default = "default_to_one_f64")]
+    parallaxy: f64,
+
+    #[serde(default)]
+    tintcolor: Option<Color>,
+
+    #[serde(default)]
+    repeatx: bool,
+
+    #[serde(default)]
+    repeaty: bool,
+
+    #[serde(default)]
+    class: String,
+
     #[serde(default)]
     transparentcolor: Option<Color>,
 
@@ -142,6 +160,12 @@ impl TryFrom<LayerReader> for Layer {
         let height = lr.height;
         let offsetx = lr.offsetx;
         let offsety = lr.offsety;
+        let parallaxx = lr.parallaxx;
+        let parallaxy = lr.parallaxy;
+        let tintcolor = lr.tintcolor;
+        let repeatx = lr.repeatx;
+        let repeaty = lr.repeaty;
+        let class = lr.class;
         let properties = lr.properties.unwrap_or_default();
 
         Ok(Self {
@@ -153,6 +177,12 @@ impl TryFrom<LayerReader> for Layer {
             height,
             offsetx,
             offsety,
+            parallaxx,
+            parallaxy,
+            tintcolor,
+            repeatx,
+            repeaty,
+            class,
             ltype,
             layerdata,
             properties,
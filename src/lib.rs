@@ -104,20 +104,67 @@
 
 #![allow(dead_code)]
 
+pub mod animation;
+pub mod batch;
+pub mod builder;
+pub mod collision;
 pub mod color;
+pub mod command;
+pub mod diff;
+pub mod draworder;
+pub mod edit;
+pub mod fov;
+pub mod grid;
 pub mod layer;
 mod layerreader;
 pub mod map;
+pub mod merge;
+pub mod mesh;
+pub mod minimap;
+pub mod navgrid;
+pub mod navmesh;
 pub mod object;
+pub mod outline;
+pub mod patch;
 pub mod property;
+pub mod region;
+pub mod remap;
+pub mod resize;
+pub mod schema;
+#[cfg(feature = "render")]
+pub mod render;
 pub mod tileset;
+pub mod triangulate;
+pub mod validate;
 
+pub use crate::animation::*;
+pub use crate::batch::*;
+pub use crate::builder::*;
+pub use crate::collision::*;
 pub use crate::color::*;
+pub use crate::command::*;
+pub use crate::diff::*;
+pub use crate::draworder::*;
+pub use crate::edit::*;
+pub use crate::fov::*;
+pub use crate::grid::*;
 pub use crate::layer::*;
 pub use crate::map::*;
+pub use crate::mesh::*;
+pub use crate::minimap::*;
+pub use crate::navgrid::*;
+pub use crate::navmesh::*;
 pub use crate::object::*;
+pub use crate::outline::*;
+pub use crate::patch::*;
 pub use crate::property::*;
+pub use crate::region::*;
+#[cfg(feature = "render")]
+pub use crate::render::*;
+pub use crate::schema::*;
 pub use crate::tileset::*;
+pub use crate::triangulate::*;
+pub use crate::validate::*;
 
 use std::fs::File;
 use std::io::prelude::*;
@@ -12,6 +12,7 @@
 //! 
 //!         tiled_json::Map::tilesets(&self) -> &Vec<tiled_json::Tileset>;
 //!         tiled_json::Map::tileset_by_gid(&self, gid: u32) -> Option<&Tileset>;
+//!         tiled_json::Map::tile_by_gid(&self, gid: u32) -> Option<&Tile>;
 //! 
 //! This struct implements the trait HasProperty, which enables easy access of 
 //! Tiled properties for maps.  The relevant functions are:
@@ -28,6 +29,7 @@ use crate::color::Color;
 use crate::layer::*;
 use crate::property::HasProperty;
 use crate::property::Property;
+use crate::tileset::Tile;
 use crate::tileset::Tileset;
 
 const MAP_ORTHOGONAL: &str = "orthogonal";
@@ -45,7 +47,7 @@ const STAGGER_EVEN: &str = "even";
 const STAGGER_X: &str = "x";
 const STAGGER_Y: &str = "y";
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// The primary structure of all modules.
 pub struct Map {
@@ -78,6 +80,12 @@ pub struct Map {
     #[serde(default)]
     pub staggerindex: Option<StaggerIndex>,
 
+    #[serde(default)]
+    pub parallaxoriginx: f64,
+
+    #[serde(default)]
+    pub parallaxoriginy: f64,
+
     #[serde(default)]
     pub tilesets: Vec<Tileset>,
 
@@ -116,6 +124,16 @@ impl Map {
         Option::None
     }
 
+    /// Find a layer by id, searching inside groups as well.
+    pub fn layer_by_id(&self, id: u32) -> Option<&Layer> {
+        find_layer(&self.layers, id)
+    }
+
+    /// Mutably borrow a layer by id, searching inside groups as well.
+    pub fn layer_by_id_mut(&mut self, id: u32) -> Option<&mut Layer> {
+        find_layer_mut(&mut self.layers, id)
+    }
+
 
     /// Borrow the tileset vector. 
     /// 
@@ -144,6 +162,18 @@ impl Map {
         Option::None
     }
 
+    /// Looks for the Tile definition of the gid provided, if the tileset it belongs
+    /// to defines one.  Flags present on the gid are stripped automatically.
+    ///
+    /// This is the quickest way to reach the properties, animation or collision data
+    /// of a tile straight from tile layer data.  Gid 0 (an empty cell) never has one.
+    pub fn tile_by_gid(&self, gid: u32) -> Option<&Tile> {
+        if crate::gid_without_flags(gid) == 0 {
+            return Option::None;
+        }
+        self.tileset_by_gid(gid).and_then(|ts| ts.tile_by_gid(gid))
+    }
+
 
     /// Get the map width in tiles.
     pub fn width(&self) -> u16 {
@@ -208,10 +238,240 @@ impl Map {
         self.staggerindex
     }
 
+    /// Get the point of the map, in pixels, where layers with parallax factors
+    /// line up with the rest of the map.
+    pub fn parallax_origin(&self) -> (f64, f64) {
+        (self.parallaxoriginx, self.parallaxoriginy)
+    }
+
+    /// Walk every layer of the map, depth first, paired with its transform
+    /// combined with the transforms of the groups holding it.  Groups are
+    /// listed before the layers they hold.
+    pub fn layer_transforms(&self) -> Vec<(&Layer, LayerTransform)> {
+        let mut out = Vec::new();
+        let mut stack: Vec<(&Layer, LayerTransform)> = self
+            .layers
+            .iter()
+            .rev()
+            .map(|l| (l, LayerTransform::default().combine(l)))
+            .collect();
+        while let Option::Some((layer, transform)) = stack.pop() {
+            if let Option::Some(children) = layer.get_layers() {
+                stack.extend(children.iter().rev().map(|l| (l, transform.combine(l))));
+            }
+            out.push((layer, transform));
+        }
+        out
+    }
+
     /// Get the version of tiled the map was compiled with.
     pub fn tiled_version(&self) -> &String {
         &self.tiledversion
     }
+
+    /// Get the size of the whole map in pixels, as Tiled lays it out for the
+    /// orientation of the map.
+    pub fn pixel_size(&self) -> (u32, u32) {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let (tw, th) = (self.tilewidth as f64, self.tileheight as f64);
+        let (width, height) = match self.orientation {
+            MapOrientation::Orthogonal => (w * tw, h * th),
+            MapOrientation::Isometric => ((w + h) * tw / 2.0, (w + h) * th / 2.0),
+            MapOrientation::Staggered | MapOrientation::Hexagonal => {
+                let (column_width, row_height, side_x, side_y) = self.stagger_metrics();
+                match self.staggeraxis.unwrap_or(StaggerAxis::StaggerY) {
+                    StaggerAxis::StaggerY => (
+                        w * tw + if self.height > 1 { column_width } else { 0.0 },
+                        h * row_height + side_y,
+                    ),
+                    StaggerAxis::StaggerX => (
+                        w * column_width + side_x,
+                        h * th + if self.width > 1 { row_height } else { 0.0 },
+                    ),
+                }
+            }
+        };
+        (width as u32, height as u32)
+    }
+
+    /// Get the position in pixels of the top-left corner of the box holding the
+    /// cell (x, y), for the orientation of the map.  The box is tilewidth by
+    /// tileheight pixels; tiles taller than the map grid stick out of its top.
+    pub fn tile_to_pixel(&self, x: u32, y: u32) -> (f64, f64) {
+        let (fx, fy) = (x as f64, y as f64);
+        let (tw, th) = (self.tilewidth as f64, self.tileheight as f64);
+        match self.orientation {
+            MapOrientation::Orthogonal => (fx * tw, fy * th),
+            MapOrientation::Isometric => {
+                let origin_x = self.height as f64 * tw / 2.0;
+                ((fx - fy) * tw / 2.0 + origin_x - tw / 2.0, (fx + fy) * th / 2.0)
+            }
+            MapOrientation::Staggered | MapOrientation::Hexagonal => {
+                let (column_width, row_height, _, _) = self.stagger_metrics();
+                let axis = self.staggeraxis.unwrap_or(StaggerAxis::StaggerY);
+                let index = self.staggerindex.unwrap_or(StaggerIndex::Odd);
+                let shifted = crate::grid::is_shifted(axis, index, x as i32, y as i32);
+                match axis {
+                    StaggerAxis::StaggerY => (
+                        fx * tw + if shifted { column_width } else { 0.0 },
+                        fy * row_height,
+                    ),
+                    StaggerAxis::StaggerX => (
+                        fx * column_width,
+                        fy * th + if shifted { row_height } else { 0.0 },
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Get the position in pixels on screen of a point given in object
+    /// coordinates.
+    ///
+    /// Objects on isometric maps are placed along the isometric axes, in units
+    /// of tileheight pixels, and are projected here.  Every other orientation
+    /// already stores screen pixels, which are returned unchanged.
+    pub fn object_to_pixel(&self, x: f64, y: f64) -> (f64, f64) {
+        match self.orientation {
+            MapOrientation::Isometric => {
+                let (tw, th) = (self.tilewidth as f64, self.tileheight as f64);
+                let (tx, ty) = (x / th, y / th);
+                let origin_x = self.height as f64 * tw / 2.0;
+                ((tx - ty) * tw / 2.0 + origin_x, (tx + ty) * th / 2.0)
+            }
+            _ => (x, y),
+        }
+    }
+
+    /// Find the cell holding a point given in object coordinates, or None when
+    /// the point lies outside the map.
+    ///
+    /// On staggered and hexagonal maps the cell whose centre is closest to the
+    /// point is chosen, measured so that the shape of the tiles is respected.
+    pub fn object_to_tile(&self, x: f64, y: f64) -> Option<(u32, u32)> {
+        let (tw, th) = (self.tilewidth as f64, self.tileheight as f64);
+        let (w, h) = (self.width as i64, self.height as i64);
+        let inside = |cx: i64, cy: i64| cx >= 0 && cy >= 0 && cx < w && cy < h;
+        let (cx, cy) = match self.orientation {
+            MapOrientation::Orthogonal => ((x / tw).floor() as i64, (y / th).floor() as i64),
+            MapOrientation::Isometric => ((x / th).floor() as i64, (y / th).floor() as i64),
+            MapOrientation::Staggered | MapOrientation::Hexagonal => {
+                let (column_width, row_height, _, _) = self.stagger_metrics();
+                let (rx, ry) = match self.staggeraxis.unwrap_or(StaggerAxis::StaggerY) {
+                    StaggerAxis::StaggerY => ((x / tw).floor(), (y / row_height).floor()),
+                    StaggerAxis::StaggerX => ((x / column_width).floor(), (y / th).floor()),
+                };
+                let distance = |cx: i64, cy: i64| {
+                    let (px, py) = self.tile_to_pixel(cx as u32, cy as u32);
+                    let (dx, dy) = (x - px - tw / 2.0, y - py - th / 2.0);
+                    match self.orientation {
+                        MapOrientation::Staggered => dx.abs() / tw + dy.abs() / th,
+                        _ => (dx * dx + dy * dy).sqrt(),
+                    }
+                };
+                let mut best: Option<((i64, i64), f64)> = Option::None;
+                for cy in (ry as i64 - 1)..=(ry as i64 + 1) {
+                    for cx in (rx as i64 - 1)..=(rx as i64 + 1) {
+                        if !inside(cx, cy) {
+                            continue;
+                        }
+                        let d = distance(cx, cy);
+                        if best.is_none_or(|(_, bd)| d < bd) {
+                            best = Option::Some(((cx, cy), d));
+                        }
+                    }
+                }
+                // A point beyond the outer half of a border cell is off the map.
+                let ((cx, cy), d) = best?;
+                let limit = match self.orientation {
+                    MapOrientation::Staggered => 0.5,
+                    _ => tw.max(th) / 2.0,
+                };
+                if d > limit {
+                    return Option::None;
+                }
+                (cx, cy)
+            }
+        };
+        if inside(cx, cy) {
+            Option::Some((cx as u32, cy as u32))
+        } else {
+            Option::None
+        }
+    }
+
+    /// List every cell of the map in the order its tiles must be drawn.
+    ///
+    /// Orthogonal maps follow renderorder.  Other orientations are drawn row by
+    /// row from the top; on maps staggered along x, the columns shifted down are
+    /// drawn after the others in each row.
+    pub fn cells_in_render_order(&self) -> Vec<(u32, u32)> {
+        let (w, h) = (self.width as u32, self.height as u32);
+        let mut cells = Vec::with_capacity((w * h) as usize);
+        match self.orientation {
+            MapOrientation::Orthogonal => {
+                let (left, up) = match self.renderorder {
+                    RenderOrder::RightDown => (false, false),
+                    RenderOrder::RightUp => (false, true),
+                    RenderOrder::LeftDown => (true, false),
+                    RenderOrder::LeftUp => (true, true),
+                };
+                for row in 0..h {
+                    let y = if up { h - 1 - row } else { row };
+                    for col in 0..w {
+                        let x = if left { w - 1 - col } else { col };
+                        cells.push((x, y));
+                    }
+                }
+            }
+            MapOrientation::Staggered | MapOrientation::Hexagonal
+                if self.staggeraxis.is_some_and(|a| matches!(a, StaggerAxis::StaggerX)) =>
+            {
+                let index = self.staggerindex.unwrap_or(StaggerIndex::Odd);
+                for y in 0..h {
+                    for shifted in [false, true].iter() {
+                        for x in 0..w {
+                            let s = crate::grid::is_shifted(StaggerAxis::StaggerX, index, x as i32, 0);
+                            if s == *shifted {
+                                cells.push((x, y));
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                for y in 0..h {
+                    for x in 0..w {
+                        cells.push((x, y));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    // (column width, row height, side offset x, side offset y) of a staggered or
+    // hexagonal map, following Tiled's hexagonal renderer.  Staggered maps are
+    // hexagonal maps with no side length.
+    pub(crate) fn stagger_metrics(&self) -> (f64, f64, f64, f64) {
+        let (tw, th) = (self.tilewidth as f64, self.tileheight as f64);
+        let side = match self.orientation {
+            MapOrientation::Hexagonal => self.hexsidelength as f64,
+            _ => 0.0,
+        };
+        match self.staggeraxis.unwrap_or(StaggerAxis::StaggerY) {
+            StaggerAxis::StaggerY => {
+                let side_x = tw / 2.0;
+                let side_y = (th - side) / 2.0;
+                (side_x, side_y + side, side_x, side_y)
+            }
+            StaggerAxis::StaggerX => {
+                let side_x = (tw - side) / 2.0;
+                let side_y = th / 2.0;
+                (side_x + side, side_y, side_x, side_y)
+            }
+        }
+    }
 }
 
 impl HasProperty for Map {
@@ -221,7 +481,7 @@ impl HasProperty for Map {
     }
 }
 
-#[derive(Deserialize, Copy, Clone)]
+#[derive(Deserialize, Copy, Clone, PartialEq)]
 #[serde(from = "String")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// MapOrientation is an enum with 4 values:
@@ -252,7 +512,7 @@ impl std::fmt::Display for MapOrientation {
     }
 }
 
-#[derive(Deserialize, Copy, Clone)]
+#[derive(Deserialize, Copy, Clone, PartialEq)]
 #[serde(from = "String")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// RenderOrder is an enum with 4 values:
@@ -285,7 +545,7 @@ impl std::fmt::Display for RenderOrder {
     }
 }
 
-#[derive(Deserialize, Copy, Clone)]
+#[derive(Deserialize, Copy, Clone, PartialEq)]
 #[serde(from = "String")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// StaggerIndex is an enum with 2 values:
@@ -312,7 +572,7 @@ impl std::fmt::Display for StaggerIndex {
     }
 }
 
-#[derive(Deserialize, Copy, Clone)]
+#[derive(Deserialize, Copy, Clone, PartialEq)]
 #[serde(from = "String")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// StaggerAxis is an enum with 2 values:
@@ -386,3 +646,27 @@ impl From<String> for StaggerAxis {
 fn default_to_right_down() -> RenderOrder {
     RenderOrder::RightDown
 }
+
+fn find_layer(layers: &[Layer], id: u32) -> Option<&Layer> {
+    for layer in layers.iter() {
+        if layer.id == Option::Some(id) {
+            return Option::Some(layer);
+        }
+        if let Option::Some(found) = layer.get_layers().and_then(|l| find_layer(l, id)) {
+            return Option::Some(found);
+        }
+    }
+    Option::None
+}
+
+fn find_layer_mut(layers: &mut [Layer], id: u32) -> Option<&mut Layer> {
+    for layer in layers.iter_mut() {
+        if layer.id == Option::Some(id) {
+            return Option::Some(layer);
+        }
+        if let Option::Some(found) = layer.get_layers_mut().and_then(|l| find_layer_mut(l, id)) {
+            return Option::Some(found);
+        }
+    }
+    Option::None
+}
@@ -0,0 +1,289 @@
+//!
+//! Stitching maps together.
+//!
+//! Map::merge() places a copy of one map into another at an offset in cells,
+//! the way rooms authored as small maps are stitched into a floor:
+//! - Tilesets are unified.  A tileset of the same name, image and tile count as
+//!   one already in the map is reused; any other is added after the existing
+//!   ones with the next free firstgid.  Every gid of the merged map, in tile
+//!   layers and tile objects, is renumbered to match, keeping its flip flags.
+//! - Layers are matched by name and kind at each level.  Tile layers are
+//!   stamped over the matching layer, leaving the cells under empty cells
+//!   untouched; object groups receive the objects; groups are merged layer by
+//!   layer.  Image layers already present are kept as they are.  Layers with no
+//!   match are added on top, inside the matching group if they are nested.
+//! - Every layer added receives a fresh layer id, and every object added a
+//!   fresh object id, so nothing collides with the map merged into.  Merging
+//!   fails, leaving the map untouched, when the ids could run out.
+//!
+//! Both maps must share their orientation and tile size.  The merged map is
+//! clipped to the map merged into; resize it with Map::resize() first to make
+//! room.  Properties of the merged map itself are left out.
+//!
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::layer::Layer;
+use crate::layer::LayerDataContainer;
+use crate::map::Map;
+use crate::tileset::Tileset;
+
+impl Map {
+    /// Merge a copy of other into this map with its top-left cell on cell
+    /// (x, y).  See the module documentation.
+    pub fn merge(&mut self, other: &Map, x: i32, y: i32) -> Result<(), String> {
+        if self.orientation != other.orientation
+            || self.tilewidth != other.tilewidth
+            || self.tileheight != other.tileheight
+        {
+            return Err(format!(
+                "Cannot merge a map with {} orientation and {}x{} tiles into one with {} orientation and {}x{} tiles.",
+                other.orientation,
+                other.tilewidth,
+                other.tileheight,
+                self.orientation,
+                self.tilewidth,
+                self.tileheight
+            ));
+        }
+
+        // Every layer and object of other may need a fresh id.
+        let (layers, objects) = count_ids(&other.layers);
+        u16::try_from(layers)
+            .ok()
+            .and_then(|n| self.nextlayerid.checked_add(n))
+            .ok_or(format!(
+                "Cannot merge {} layers: layer ids would run out.",
+                layers
+            ))?;
+        u32::try_from(objects)
+            .ok()
+            .and_then(|n| self.nextobjectid.checked_add(n))
+            .ok_or(format!(
+                "Cannot merge {} objects: object ids would run out.",
+                objects
+            ))?;
+
+        let firstgids = self.unify_tilesets(&other.tilesets);
+        let mut placed = other.clone();
+        placed.map_gids(|gid| {
+            let raw = crate::gid_without_flags(gid);
+            other
+                .tileset_by_gid(raw)
+                .and_then(|ts| {
+                    let firstgid = firstgids.get(&ts.firstgid)?;
+                    Option::Some((gid - raw) | (firstgid + raw - ts.firstgid))
+                })
+                .unwrap_or(gid)
+        });
+        placed.resize(self.width, self.height, x, y, false);
+
+        let mut layers = std::mem::take(&mut self.layers);
+        self.merge_layers(&mut layers, placed.layers);
+        self.layers = layers;
+        Ok(())
+    }
+
+    // Add the tilesets missing from this map, returning the new firstgid of
+    // each tileset by its old firstgid.
+    fn unify_tilesets(&mut self, tilesets: &[Tileset]) -> HashMap<u32, u32> {
+        let mut firstgids = HashMap::new();
+        for ts in tilesets.iter() {
+            let same = self
+                .tilesets
+                .iter()
+                .find(|t| t.name == ts.name && t.image == ts.image && t.tilecount == ts.tilecount);
+            let firstgid = match same {
+                Option::Some(t) => t.firstgid,
+                Option::None => {
+                    let mut added = ts.clone();
+                    added.firstgid = self
+                        .tilesets
+                        .iter()
+                        .map(|t| t.firstgid + t.gid_span())
+                        .max()
+                        .unwrap_or(1);
+                    let firstgid = added.firstgid;
+                    self.tilesets.push(added);
+                    firstgid
+                }
+            };
+            firstgids.insert(ts.firstgid, firstgid);
+        }
+        firstgids
+    }
+
+    fn merge_layers(&mut self, into: &mut Vec<Layer>, layers: Vec<Layer>) {
+        for mut layer in layers.into_iter() {
+            let target = into.iter_mut().find(|l| {
+                l.name == layer.name
+                    && std::mem::discriminant(&l.layerdata)
+                        == std::mem::discriminant(&layer.layerdata)
+            });
+            let target = match target {
+                Option::Some(t) => t,
+                Option::None => {
+                    self.renumber(&mut layer);
+                    into.push(layer);
+                    continue;
+                }
+            };
+            match (&mut target.layerdata, layer.layerdata) {
+                (
+                    LayerDataContainer::TileLayer { data },
+                    LayerDataContainer::TileLayer { data: incoming },
+                ) => {
+                    for (cell, gid) in data.iter_mut().zip(incoming) {
+                        if crate::gid_without_flags(gid) != 0 {
+                            *cell = gid;
+                        }
+                    }
+                }
+                (
+                    LayerDataContainer::ObjectGroup { objects, .. },
+                    LayerDataContainer::ObjectGroup {
+                        objects: incoming, ..
+                    },
+                ) => {
+                    for mut object in incoming.into_iter() {
+                        object.id = self.nextobjectid;
+                        self.nextobjectid += 1;
+                        objects.push(object);
+                    }
+                }
+                (
+                    LayerDataContainer::Group { layers },
+                    LayerDataContainer::Group { layers: incoming },
+                ) => self.merge_layers(layers, incoming),
+                _ => {}
+            }
+        }
+    }
+
+    // Give a layer and everything it holds fresh ids.  merge() checks first
+    // that there are enough.
+    fn renumber(&mut self, layer: &mut Layer) {
+        layer.id = Option::Some(self.nextlayerid as u32);
+        self.nextlayerid += 1;
+        if let Option::Some(objects) = layer.get_objects_vector_mut() {
+            for object in objects.iter_mut() {
+                object.id = self.nextobjectid;
+                self.nextobjectid += 1;
+            }
+        }
+        if let Option::Some(layers) = layer.get_layers_mut() {
+            for child in layers.iter_mut() {
+                self.renumber(child);
+            }
+        }
+    }
+}
+
+// The number of layers and of objects in layers, nested ones included.
+fn count_ids(layers: &[Layer]) -> (usize, usize) {
+    layers.iter().fold((0, 0), |(l, o), layer| {
+        let objects = layer.get_objects_vector().map(|v| v.len()).unwrap_or(0);
+        let (nested_l, nested_o) = layer.get_layers().map(|v| count_ids(v)).unwrap_or((0, 0));
+        (l + 1 + nested_l, o + objects + nested_o)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+    use crate::object::Object;
+    use crate::tileset::Tile;
+    use crate::tileset::Tileset;
+
+    fn tile(id: u16, image: &str) -> Tile {
+        Tile {
+            id,
+            image: Option::Some(image.to_string()),
+            imageheight: 16,
+            imagewidth: 16,
+            ttype: Option::None,
+            objectgroup: Option::None,
+            animation: Vec::new(),
+            properties: Vec::new(),
+        }
+    }
+
+    // A map with one tile layer whose cell (0, 0) holds gid.
+    fn map_with(tileset: Tileset, gid: u32) -> Map {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 2, 2, 16, 16);
+        builder.add_tileset(tileset);
+        let layer = builder.add_layer(Layer::new_tile_layer("ground")).unwrap();
+        builder.layer_mut(layer).unwrap().set_gid(0, 0, gid);
+        builder.build()
+    }
+
+    #[test]
+    fn merge_after_image_collection_with_sparse_ids() {
+        // Two tiles, but the second has id 5, so the tileset takes gids 1..=6.
+        let mut collection = Tileset::new("props", "", 0, 0, 16, 16);
+        collection.tilecount = 2;
+        collection.tiles = vec![tile(0, "barrel.png"), tile(5, "crate.png")];
+        let mut map = map_with(collection, 6);
+
+        let other = map_with(Tileset::new("terrain", "terrain.png", 64, 32, 16, 16), 3);
+        map.merge(&other, 1, 0).unwrap();
+
+        assert_eq!(map.tilesets[1].firstgid, 7);
+        let ground = map.layer_by_name("ground").unwrap();
+        assert_eq!(ground.get_gid(0, 0), Option::Some(6));
+        assert_eq!(ground.get_gid(1, 0), Option::Some(9));
+        assert_eq!(map.tileset_by_gid(6).unwrap().name, "props");
+        assert_eq!(map.tileset_by_gid(9).unwrap().name, "terrain");
+        assert!(map.validate().is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_flip_flags() {
+        let mut map = map_with(Tileset::new("a", "a.png", 32, 16, 16, 16), 1);
+        let flipped = crate::HORZ_FLIP_FLAG | 2;
+        let other = map_with(Tileset::new("b", "b.png", 32, 16, 16, 16), flipped);
+        map.merge(&other, 0, 1).unwrap();
+
+        let ground = map.layer_by_name("ground").unwrap();
+        assert_eq!(
+            ground.get_gid(0, 1),
+            Option::Some(crate::HORZ_FLIP_FLAG | 4)
+        );
+    }
+
+    #[test]
+    fn merge_fails_before_ids_run_out() {
+        // Two layers and an object that may all need fresh ids.
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 2, 2, 16, 16);
+        builder.add_tileset(Tileset::new("terrain", "terrain.png", 64, 32, 16, 16));
+        builder.add_layer(Layer::new_tile_layer("ground")).unwrap();
+        let things = builder
+            .add_layer(Layer::new_object_group("things"))
+            .unwrap();
+        builder.add_object(things, Object::new_point(0.0, 0.0));
+        let other = builder.build();
+
+        let mut map = map_with(Tileset::new("a", "a.png", 32, 16, 16, 16), 1);
+        map.nextlayerid = u16::MAX - 1;
+        assert!(map.merge(&other, 0, 0).is_err());
+        assert_eq!(map.tilesets.len(), 1);
+        assert_eq!(map.layers.len(), 1);
+
+        map.nextlayerid = u16::MAX - 2;
+        map.nextobjectid = u32::MAX;
+        assert!(map.merge(&other, 0, 0).is_err());
+        assert_eq!(map.tilesets.len(), 1);
+
+        map.nextobjectid = u32::MAX - 1;
+        map.merge(&other, 0, 0).unwrap();
+        assert_eq!(map.nextlayerid, u16::MAX - 1);
+        assert_eq!(map.nextobjectid, u32::MAX);
+        let things = map.layer_by_name("things").unwrap();
+        assert_eq!(things.id, Option::Some(u16::MAX as u32 - 2));
+        assert_eq!(things.get_objects_vector().unwrap()[0].id, u32::MAX - 1);
+    }
+}
@@ -0,0 +1,356 @@
+//!
+//! Chunked meshes for large tile layers.
+//!
+//! Rebuilding the quads of a whole tile layer every time one tile changes is too
+//! slow for large maps.  ChunkedMesh splits a tile layer into chunks of a fixed
+//! number of cells (16 by 16, for example) and keeps the vertices of each chunk
+//! apart, grouped in one MeshBatch per tileset, so every batch draws with a
+//! single texture.
+//!
+//! After editing the data of the layer, mark the changed cells dirty and call
+//! ChunkedMesh::rebuild(): only the chunks holding dirty cells are generated
+//! again, and their indices are returned so they can be uploaded anew.
+//!
+//! Vertices are those of Quad::vertices(): six [x, y, u, v] vertices (two
+//! triangles) per tile, flattened.  Within a batch, tiles follow the render
+//! order of the map.
+//!
+
+use crate::batch::tile_quad;
+use crate::layer::Layer;
+use crate::map::Map;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The mesh of a tile layer, split into chunks of chunk_width by chunk_height
+/// cells.  chunks are stored row by row.
+pub struct ChunkedMesh {
+    pub width: u32,
+    pub height: u32,
+    pub chunk_width: u32,
+    pub chunk_height: u32,
+    pub chunks: Vec<MeshChunk>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// One chunk of a ChunkedMesh.  x and y are the column and row of the chunk,
+/// cells are the cells it covers in render order, and dirty is true while the
+/// chunk waits to be rebuilt.
+pub struct MeshChunk {
+    pub x: u32,
+    pub y: u32,
+    pub cells: Vec<(u32, u32)>,
+    pub batches: Vec<MeshBatch>,
+    pub dirty: bool,
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The vertices of the tiles of one tileset within a chunk.  tileset is the
+/// index of the tileset in Map::tilesets().
+pub struct MeshBatch {
+    pub tileset: usize,
+    pub vertices: Vec<f32>,
+}
+
+impl MeshBatch {
+    /// The index of the tileset the batch draws from.
+    pub fn tileset(&self) -> usize {
+        self.tileset
+    }
+
+    /// Borrow the flattened [x, y, u, v] vertices of the batch.
+    pub fn vertices(&self) -> &Vec<f32> {
+        &self.vertices
+    }
+
+    /// Number of vertices in the batch.
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// Number of tiles drawn by the batch.
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 24
+    }
+}
+
+impl MeshChunk {
+    /// The column of the chunk.
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    /// The row of the chunk.
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    /// Borrow the cells covered by the chunk, in render order.
+    pub fn cells(&self) -> &Vec<(u32, u32)> {
+        &self.cells
+    }
+
+    /// Borrow the batches of the chunk, sorted by tileset.
+    pub fn batches(&self) -> &Vec<MeshBatch> {
+        &self.batches
+    }
+
+    /// Does the chunk wait to be rebuilt?
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn build(&mut self, map: &Map, layer: &Layer) {
+        let mut batches: Vec<MeshBatch> = Vec::new();
+        for (x, y) in self.cells.iter() {
+            let quad = match tile_quad(map, layer, *x, *y) {
+                Option::Some(q) => q,
+                Option::None => continue,
+            };
+            let pos = match batches.binary_search_by_key(&quad.tileset, |b| b.tileset) {
+                Ok(pos) => pos,
+                Err(pos) => {
+                    batches.insert(
+                        pos,
+                        MeshBatch {
+                            tileset: quad.tileset,
+                            vertices: Vec::new(),
+                        },
+                    );
+                    pos
+                }
+            };
+            for vertex in quad.vertices().iter() {
+                batches[pos].vertices.extend_from_slice(vertex);
+            }
+        }
+        self.batches = batches;
+        self.dirty = false;
+    }
+}
+
+impl ChunkedMesh {
+    /// Build the mesh of a tile layer in chunks of chunk_width by chunk_height
+    /// cells.  Chunk sizes of 0 are treated as 1.
+    pub fn new(map: &Map, layer: &Layer, chunk_width: u32, chunk_height: u32) -> ChunkedMesh {
+        let width = map.width as u32;
+        let height = map.height as u32;
+        let chunk_width = chunk_width.max(1);
+        let chunk_height = chunk_height.max(1);
+        let columns = width.div_ceil(chunk_width);
+        let rows = height.div_ceil(chunk_height);
+
+        let mut chunks: Vec<MeshChunk> = (0..columns * rows)
+            .map(|i| MeshChunk {
+                x: i % columns,
+                y: i / columns,
+                cells: Vec::new(),
+                batches: Vec::new(),
+                dirty: true,
+            })
+            .collect();
+        for (x, y) in map.cells_in_render_order() {
+            let i = (y / chunk_height) * columns + x / chunk_width;
+            chunks[i as usize].cells.push((x, y));
+        }
+
+        let mut mesh = ChunkedMesh {
+            width,
+            height,
+            chunk_width,
+            chunk_height,
+            chunks,
+        };
+        mesh.rebuild(map, layer);
+        mesh
+    }
+
+    /// Width of the layer in cells.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the layer in cells.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Width of a chunk in cells.
+    pub fn chunk_width(&self) -> u32 {
+        self.chunk_width
+    }
+
+    /// Height of a chunk in cells.
+    pub fn chunk_height(&self) -> u32 {
+        self.chunk_height
+    }
+
+    /// Number of chunk columns.
+    pub fn columns(&self) -> u32 {
+        self.width.div_ceil(self.chunk_width)
+    }
+
+    /// Number of chunk rows.
+    pub fn rows(&self) -> u32 {
+        self.height.div_ceil(self.chunk_height)
+    }
+
+    /// Borrow every chunk, row by row.
+    pub fn chunks(&self) -> &Vec<MeshChunk> {
+        &self.chunks
+    }
+
+    /// Get a chunk by column and row.
+    pub fn chunk(&self, x: u32, y: u32) -> Option<&MeshChunk> {
+        if x >= self.columns() || y >= self.rows() {
+            return Option::None;
+        }
+        self.chunks.get((y * self.columns() + x) as usize)
+    }
+
+    /// The index in chunks() of the chunk holding a cell.
+    pub fn chunk_index_at(&self, x: u32, y: u32) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        Option::Some(((y / self.chunk_height) * self.columns() + x / self.chunk_width) as usize)
+    }
+
+    /// Mark the chunk holding a cell dirty.  Cells outside the layer are ignored.
+    pub fn mark_dirty(&mut self, x: u32, y: u32) {
+        if let Option::Some(i) = self.chunk_index_at(x, y) {
+            self.chunks[i].dirty = true;
+        }
+    }
+
+    /// Mark every chunk overlapping a rectangle of cells dirty.
+    pub fn mark_dirty_rect(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        if width == 0 || height == 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let right = x.saturating_add(width - 1).min(self.width - 1);
+        let bottom = y.saturating_add(height - 1).min(self.height - 1);
+        let columns = self.columns();
+        for cy in (y / self.chunk_height)..=(bottom / self.chunk_height) {
+            for cx in (x / self.chunk_width)..=(right / self.chunk_width) {
+                self.chunks[(cy * columns + cx) as usize].dirty = true;
+            }
+        }
+    }
+
+    /// Mark every chunk dirty, after a change affecting the whole layer such as
+    /// a new tileset.
+    pub fn mark_all_dirty(&mut self) {
+        for chunk in self.chunks.iter_mut() {
+            chunk.dirty = true;
+        }
+    }
+
+    /// The indices of the chunks waiting to be rebuilt.
+    pub fn dirty_chunks(&self) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.dirty)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Regenerate the vertices of every dirty chunk from the current data of
+    /// the layer, returning the indices of the chunks rebuilt.
+    ///
+    /// map and layer must be the ones the mesh was built from, or at least have
+    /// the same size.
+    pub fn rebuild(&mut self, map: &Map, layer: &Layer) -> Vec<usize> {
+        let dirty = self.dirty_chunks();
+        for i in dirty.iter() {
+            self.chunks[*i].build(map, layer);
+        }
+        dirty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkedMesh;
+    use crate::layer::LayerDataContainer;
+    use crate::map::Map;
+
+    // A 5x3 map cut into 2x2 chunks: three columns and two rows of chunks,
+    // the last column and row only partly covered.
+    fn map() -> Map {
+        serde_json::from_str(
+            r#"{
+                "orientation": "orthogonal", "width": 5, "height": 3,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 2,
+                "tilesets": [{
+                    "firstgid": 1, "name": "tiles", "image": "tiles.png",
+                    "imagewidth": 32, "imageheight": 16, "tilewidth": 16, "tileheight": 16,
+                    "tilecount": 2, "columns": 2
+                }],
+                "layers": [{ "type": "tilelayer", "id": 1, "name": "ground",
+                             "width": 5, "height": 3, "data": [
+                    1, 1, 2, 0, 1,
+                    1, 0, 0, 0, 0,
+                    2, 0, 1, 0, 1
+                ] }]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn quads(mesh: &ChunkedMesh) -> Vec<usize> {
+        mesh.chunks()
+            .iter()
+            .map(|c| c.batches().iter().map(|b| b.quad_count()).sum())
+            .collect()
+    }
+
+    #[test]
+    fn chunks_cover_the_layer() {
+        let map = map();
+        let mesh = ChunkedMesh::new(&map, &map.layers[0], 2, 2);
+        assert_eq!((mesh.columns(), mesh.rows()), (3, 2));
+        assert!(mesh.dirty_chunks().is_empty());
+        assert_eq!(mesh.chunk(2, 1).unwrap().cells(), &vec![(4, 2)]);
+        assert_eq!(quads(&mesh), vec![3, 1, 1, 1, 1, 1]);
+        assert_eq!(mesh.chunk_index_at(3, 2), Option::Some(4));
+        assert_eq!(mesh.chunk_index_at(5, 0), Option::None);
+    }
+
+    #[test]
+    fn only_the_edited_chunk_is_rebuilt() {
+        let mut map = map();
+        let mut mesh = ChunkedMesh::new(&map, &map.layers[0], 2, 2);
+        let before = mesh.clone();
+
+        if let LayerDataContainer::TileLayer { ref mut data } = map.layers[0].layerdata {
+            data[2 * 5 + 3] = 2;
+        }
+        mesh.mark_dirty(3, 2);
+        mesh.mark_dirty(9, 9);
+        assert_eq!(mesh.dirty_chunks(), vec![4]);
+        assert_eq!(mesh.rebuild(&map, &map.layers[0]), vec![4]);
+        assert_eq!(quads(&mesh), vec![3, 1, 1, 1, 2, 1]);
+        for (i, (old, new)) in before.chunks().iter().zip(mesh.chunks()).enumerate() {
+            if i != 4 {
+                assert_eq!(old.batches()[0].vertices(), new.batches()[0].vertices());
+            }
+        }
+        assert!(mesh.rebuild(&map, &map.layers[0]).is_empty());
+    }
+
+    #[test]
+    fn dirty_rect_marks_every_chunk_it_overlaps() {
+        let map = map();
+        let mut mesh = ChunkedMesh::new(&map, &map.layers[0], 2, 2);
+        mesh.mark_dirty_rect(1, 1, 2, 1);
+        assert_eq!(mesh.dirty_chunks(), vec![0, 1]);
+        mesh.mark_dirty_rect(4, 0, 100, 100);
+        assert_eq!(mesh.dirty_chunks(), vec![0, 1, 2, 5]);
+        mesh.mark_all_dirty();
+        assert_eq!(mesh.rebuild(&map, &map.layers[0]).len(), 6);
+    }
+}
@@ -0,0 +1,458 @@
+//!
+//! Minimaps built from tile layers.
+//!
+//! A Minimap is a small RGBA image with one square block of cell_size by
+//! cell_size pixels per cell of the map, laid out on a square grid whatever the
+//! orientation of the map.  The color of each cell comes from its tiles:
+//! - Minimap::new() asks a closure for the color of every gid.
+//! - Minimap::from_property() reads a color property of the tiles, such as
+//!   `minimap_color`.
+//! - Minimap::from_images(), only available with the `render` feature, uses the
+//!   average color of the image of each tile, as given by
+//!   Renderer::average_color().
+//!
+//! Several layers can be combined; each one is blended over the previous ones,
+//! so a layer of walls can sit on top of a layer of floors.  Objects are then
+//! marked on top, by type with Minimap::mark_objects() or through a closure with
+//! Minimap::mark_objects_by().
+//!
+//! Building a minimap fails with an error when its pixels would not fit in
+//! memory, for huge maps or cell sizes.
+//!
+
+use std::collections::HashMap;
+
+use crate::color::Color;
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::map::MapOrientation;
+use crate::object::Object;
+use crate::property::HasProperty;
+#[cfg(feature = "render")]
+use crate::render::Renderer;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// An RGBA minimap: width * height pixels of four bytes (red, green, blue and
+/// alpha), row by row from the top-left corner.  Cells without a color are
+/// transparent.
+pub struct Minimap {
+    pub width: u32,
+    pub height: u32,
+    pub cell_size: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Minimap {
+    /// Build a minimap from tile layers with cell_size by cell_size pixels per
+    /// cell, asking color for the color of every gid.
+    ///
+    /// color receives each gid as stored in the layer data (flags included),
+    /// but never 0, and is only asked once per gid.  Cells whose gid has no
+    /// color are left as they are.  Layers that are not tile layers are skipped.
+    /// A cell_size of 0 is treated as 1.
+    pub fn new<F>(
+        map: &Map,
+        layers: &[&Layer],
+        cell_size: u32,
+        color: F,
+    ) -> Result<Minimap, String>
+    where
+        F: Fn(u32) -> Option<Color>,
+    {
+        let cell_size = cell_size.max(1);
+        let (columns, rows) = (map.width as u32, map.height as u32);
+        let too_large = || {
+            format!(
+                "A minimap of {}x{} cells of {} pixels is too large.",
+                columns, rows, cell_size
+            )
+        };
+        let width = columns.checked_mul(cell_size).ok_or_else(too_large)?;
+        let height = rows.checked_mul(cell_size).ok_or_else(too_large)?;
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(too_large)?;
+        let mut minimap = Minimap {
+            width,
+            height,
+            cell_size,
+            pixels: vec![0; size],
+        };
+
+        let mut colors: HashMap<u32, Option<Color>> = HashMap::new();
+        for layer in layers.iter() {
+            let data = match layer.get_data() {
+                Option::Some(d) => d,
+                Option::None => continue,
+            };
+            for (i, gid) in data.iter().enumerate().take((columns * rows) as usize) {
+                if crate::gid_without_flags(*gid) == 0 {
+                    continue;
+                }
+                let c = *colors.entry(*gid).or_insert_with(|| color(*gid));
+                if let Option::Some(c) = c {
+                    minimap.blend_cell(i as u32 % columns, i as u32 / columns, c);
+                }
+            }
+        }
+        Ok(minimap)
+    }
+
+    /// Build a minimap from tile layers, reading the color of every tile from
+    /// its color property name.  Tiles without the property are left out.
+    pub fn from_property(
+        map: &Map,
+        layers: &[&Layer],
+        cell_size: u32,
+        name: &str,
+    ) -> Result<Minimap, String> {
+        Minimap::new(map, layers, cell_size, |gid| {
+            map.tile_by_gid(gid)
+                .and_then(|t| t.get_property(name))
+                .and_then(|p| p.get_color())
+        })
+    }
+
+    /// Build a minimap from tile layers, coloring every cell with the average
+    /// color of the image of its tile.  renderer must hold the images of map.
+    /// Tiles without an image are left out.
+    ///
+    /// Only available with the `render` feature.
+    #[cfg(feature = "render")]
+    pub fn from_images(
+        map: &Map,
+        layers: &[&Layer],
+        cell_size: u32,
+        renderer: &Renderer,
+    ) -> Result<Minimap, String> {
+        Minimap::new(map, layers, cell_size, |gid| renderer.average_color(gid))
+    }
+
+    /// Width of the minimap in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the minimap in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Size of the block of pixels drawn for each cell.
+    pub fn cell_size(&self) -> u32 {
+        self.cell_size
+    }
+
+    /// Borrow the raw RGBA bytes.
+    pub fn pixels(&self) -> &Vec<u8> {
+        &self.pixels
+    }
+
+    /// Get the color of a pixel, or None outside of the minimap.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        let i = self.index(x, y);
+        Option::Some(Color {
+            r: self.pixels[i],
+            g: self.pixels[i + 1],
+            b: self.pixels[i + 2],
+            a: self.pixels[i + 3],
+        })
+    }
+
+    /// Get the color of the block of a cell, or None outside of the map.
+    pub fn get_cell(&self, x: u32, y: u32) -> Option<Color> {
+        self.get_pixel(x * self.cell_size, y * self.cell_size)
+    }
+
+    /// Paint the block of a cell with a color, replacing what was there.
+    /// Cells outside of the map are ignored.
+    pub fn set_cell(&mut self, x: u32, y: u32, color: Color) {
+        self.paint_cell(x, y, |_| [color.r, color.g, color.b, color.a]);
+    }
+
+    /// Blend a color over the block of a cell, according to its alpha.
+    /// Cells outside of the map are ignored.
+    pub fn blend_cell(&mut self, x: u32, y: u32, color: Color) {
+        let a = color.a as f64 / 255.0;
+        self.paint_cell(x, y, |dst| {
+            let dst_a = dst[3] as f64 / 255.0;
+            let out_a = a + dst_a * (1.0 - a);
+            if out_a <= 0.0 {
+                return [0, 0, 0, 0];
+            }
+            let mix = |src: u8, dst: u8| {
+                ((src as f64 * a + dst as f64 * dst_a * (1.0 - a)) / out_a).round() as u8
+            };
+            [
+                mix(color.r, dst[0]),
+                mix(color.g, dst[1]),
+                mix(color.b, dst[2]),
+                (out_a * 255.0).round() as u8,
+            ]
+        });
+    }
+
+    /// Mark every object of an object group whose type is otype with a block
+    /// of color, in the cell holding the centre of the object.
+    pub fn mark_objects(&mut self, map: &Map, layer: &Layer, otype: &str, color: Color) {
+        self.mark_objects_by(map, layer, |o| {
+            if o.otype == otype {
+                Option::Some(color)
+            } else {
+                Option::None
+            }
+        });
+    }
+
+    /// Mark objects of an object group, asking marker for the color of each
+    /// one; objects without a color are not marked.  The block of the cell
+    /// holding the centre of the object is painted, and objects outside of the
+    /// map are ignored, as are hidden ones.
+    ///
+    /// Objects are placed where they are drawn: the offsets of the layer and of
+    /// the groups holding it, when it belongs to map, move them.
+    pub fn mark_objects_by<F>(&mut self, map: &Map, layer: &Layer, marker: F)
+    where
+        F: Fn(&Object) -> Option<Color>,
+    {
+        let objects = match layer.get_objects_vector() {
+            Option::Some(o) => o,
+            Option::None => return,
+        };
+        let (offsetx, offsety) = map
+            .layer_transforms()
+            .into_iter()
+            .find(|(l, _)| std::ptr::eq(*l, layer))
+            .map(|(_, t)| (t.offsetx, t.offsety))
+            .unwrap_or((layer.offsetx, layer.offsety));
+        // Offsets are in screen pixels; isometric maps place objects in a space
+        // of their own.
+        let (dx, dy) = match map.orientation {
+            MapOrientation::Isometric => {
+                let ratio = map.tileheight as f64 / map.tilewidth as f64;
+                (offsety + offsetx * ratio, offsety - offsetx * ratio)
+            }
+            _ => (offsetx, offsety),
+        };
+        for obj in objects.iter().filter(|o| o.visible) {
+            let color = match marker(obj) {
+                Option::Some(c) => c,
+                Option::None => continue,
+            };
+            let (cx, cy) = object_centre(obj);
+            if let Option::Some((x, y)) = map.object_to_tile(cx + dx, cy + dy) {
+                self.set_cell(x, y, color);
+            }
+        }
+    }
+
+    // The index of the first byte of a pixel inside the minimap.
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    fn paint_cell<P>(&mut self, x: u32, y: u32, paint: P)
+    where
+        P: Fn([u8; 4]) -> [u8; 4],
+    {
+        let size = self.cell_size;
+        if x >= self.width / size || y >= self.height / size {
+            return;
+        }
+        for py in (y * size)..((y + 1) * size) {
+            for px in (x * size)..((x + 1) * size) {
+                let i = self.index(px, py);
+                let dst = [
+                    self.pixels[i],
+                    self.pixels[i + 1],
+                    self.pixels[i + 2],
+                    self.pixels[i + 3],
+                ];
+                self.pixels[i..i + 4].copy_from_slice(&paint(dst));
+            }
+        }
+    }
+}
+
+// The centre of the bounding box of an object, or its position when it covers
+// no area.
+fn object_centre(obj: &Object) -> (f64, f64) {
+    match obj.outline() {
+        Option::Some(points) => {
+            let min_x = points.iter().fold(f64::INFINITY, |m, p| m.min(p.x));
+            let max_x = points.iter().fold(f64::NEG_INFINITY, |m, p| m.max(p.x));
+            let min_y = points.iter().fold(f64::INFINITY, |m, p| m.min(p.y));
+            let max_y = points.iter().fold(f64::NEG_INFINITY, |m, p| m.max(p.y));
+            ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0)
+        }
+        Option::None => (obj.x, obj.y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::Minimap;
+    use crate::color::Color;
+    use crate::map::Map;
+
+    // A 3x2 map of 16x16 cells: a floor of tile 0 (opaque blue), a wall of
+    // tile 1 (half transparent red) on cell (1, 0), and a chest and a door.
+    fn map() -> Map {
+        serde_json::from_str(
+            r##"{
+                "orientation": "orthogonal", "width": 3, "height": 2,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 3, "nextlayerid": 4,
+                "tilesets": [{
+                    "firstgid": 1, "name": "tiles", "image": "tiles.png",
+                    "imagewidth": 32, "imageheight": 16, "tilewidth": 16, "tileheight": 16,
+                    "tilecount": 2, "columns": 2,
+                    "tiles": [
+                        { "id": 0, "properties": [
+                            { "name": "minimap_color", "type": "color", "value": "#ff0000ff" } ] },
+                        { "id": 1, "properties": [
+                            { "name": "minimap_color", "type": "color", "value": "#80ff0000" } ] }
+                    ]
+                }],
+                "layers": [
+                    { "type": "tilelayer", "id": 1, "name": "floor",
+                      "width": 3, "height": 2, "data": [1, 1, 1, 1, 1, 0] },
+                    { "type": "tilelayer", "id": 2, "name": "walls",
+                      "width": 3, "height": 2, "data": [0, 2, 0, 0, 0, 0] },
+                    { "type": "objectgroup", "id": 3, "name": "things", "objects": [
+                        { "id": 1, "type": "chest", "x": 40, "y": 20, "width": 8, "height": 8 },
+                        { "id": 2, "type": "door", "x": 0, "y": 0, "width": 16, "height": 16 }
+                    ] }
+                ]
+            }"##,
+        )
+        .unwrap()
+    }
+
+    fn rgba(color: Option<Color>) -> Option<(u8, u8, u8, u8)> {
+        color.map(|c| (c.r, c.g, c.b, c.a))
+    }
+
+    #[test]
+    fn layers_blend_over_each_other() {
+        let map = map();
+        let layers = [&map.layers[0], &map.layers[1]];
+        let minimap = Minimap::from_property(&map, &layers, 2, "minimap_color").unwrap();
+        assert_eq!((minimap.width(), minimap.height()), (6, 4));
+        assert_eq!(minimap.pixels().len(), 6 * 4 * 4);
+
+        assert_eq!(rgba(minimap.get_cell(0, 0)), Option::Some((0, 0, 255, 255)));
+        assert_eq!(
+            rgba(minimap.get_cell(1, 0)),
+            Option::Some((128, 0, 127, 255))
+        );
+        assert_eq!(rgba(minimap.get_pixel(3, 1)), rgba(minimap.get_cell(1, 0)));
+        assert_eq!(rgba(minimap.get_cell(2, 1)), Option::Some((0, 0, 0, 0)));
+        assert!(minimap.get_cell(3, 0).is_none());
+    }
+
+    #[test]
+    fn colors_are_asked_once_per_gid() {
+        let map = map();
+        let asked = Cell::new(0);
+        Minimap::new(&map, &[&map.layers[0], &map.layers[2]], 1, |_| {
+            asked.set(asked.get() + 1);
+            Option::None
+        })
+        .unwrap();
+        assert_eq!(asked.get(), 1);
+    }
+
+    #[test]
+    fn objects_are_marked_in_the_cell_of_their_centre() {
+        let map = map();
+        let green = Color {
+            r: 0,
+            g: 255,
+            b: 0,
+            a: 255,
+        };
+        let mut minimap =
+            Minimap::from_property(&map, &[&map.layers[0]], 1, "minimap_color").unwrap();
+        minimap.mark_objects(&map, &map.layers[2], "chest", green);
+        assert_eq!(rgba(minimap.get_cell(2, 1)), Option::Some((0, 255, 0, 255)));
+        assert_eq!(rgba(minimap.get_cell(0, 0)), Option::Some((0, 0, 255, 255)));
+    }
+
+    #[test]
+    fn layer_and_group_offsets_move_the_marks() {
+        let mut map = map();
+        map.layers[2].offsetx = -16.0;
+        let mut minimap = Minimap::new(&map, &[], 1, |_| Option::None).unwrap();
+        minimap.mark_objects(&map, &map.layers[2], "chest", Color::new("#00ff00"));
+        assert_eq!(rgba(minimap.get_cell(1, 1)), Option::Some((0, 255, 0, 255)));
+        assert_eq!(rgba(minimap.get_cell(2, 1)), Option::Some((0, 0, 0, 0)));
+
+        // On an isometric map, 16 pixels to the right is half a cell along x
+        // and half a cell back along y.
+        let map: Map = serde_json::from_str(
+            r#"{
+                "orientation": "isometric", "width": 4, "height": 4,
+                "tilewidth": 32, "tileheight": 16, "nextobjectid": 2, "nextlayerid": 3,
+                "layers": [{ "type": "group", "id": 1, "name": "group", "offsetx": 8,
+                             "layers": [{ "type": "objectgroup", "id": 2, "name": "things",
+                                          "offsetx": 8, "objects": [
+                    { "id": 1, "type": "chest", "x": 8, "y": 8, "point": true }
+                ] }] }]
+            }"#,
+        )
+        .unwrap();
+        let things = map.layers[0].get_layers().unwrap();
+        let mut minimap = Minimap::new(&map, &[], 1, |_| Option::None).unwrap();
+        minimap.mark_objects(&map, &things[0], "chest", Color::new("#00ff00"));
+        assert_eq!(rgba(minimap.get_cell(1, 0)), Option::Some((0, 255, 0, 255)));
+        assert_eq!(rgba(minimap.get_cell(0, 0)), Option::Some((0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn oversized_minimaps_are_refused() {
+        let map = map();
+        assert!(Minimap::new(&map, &[], u32::MAX, |_| Option::None).is_err());
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn images_give_their_average_color() {
+        // tiles.png holds tile 0, opaque blue, and tile 1, half red and half
+        // transparent.
+        let dir = std::env::temp_dir().join(format!("minimap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut pixels = Vec::new();
+        for y in 0..16 {
+            for x in 0..32 {
+                pixels.extend_from_slice(match (x < 16, y < 8) {
+                    (true, _) => &[0, 0, 255, 255],
+                    (false, true) => &[255, 0, 0, 255],
+                    (false, false) => &[0, 0, 0, 0],
+                });
+            }
+        }
+        let rgba8 = image::ExtendedColorType::Rgba8;
+        image::save_buffer(dir.join("tiles.png"), &pixels, 32, 16, rgba8).unwrap();
+        let map = map();
+        let renderer = crate::render::Renderer::new(&map, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let renderer = renderer.unwrap();
+
+        // Half of tile 1 is transparent, so its average color is half
+        // transparent red, much like its property color.
+        let layers = [&map.layers[0], &map.layers[1]];
+        let minimap = Minimap::from_images(&map, &layers, 1, &renderer).unwrap();
+        assert_eq!(rgba(minimap.get_cell(0, 0)), Option::Some((0, 0, 255, 255)));
+        assert_eq!(
+            rgba(minimap.get_cell(1, 0)),
+            Option::Some((127, 0, 128, 255))
+        );
+    }
+}
@@ -0,0 +1,478 @@
+//!
+//! NavGrid is a pathfinding grid built from the tile layers of a map.
+//!
+//! Each cell of the grid has a movement cost, or no cost at all when it cannot
+//! be walked on.  Costs are derived per gid, either through a closure or from a
+//! named tile property, and several layers can be combined: a cell is blocked if
+//! any layer blocks it, and otherwise costs as much as its most expensive layer.
+//! Tiles carrying collision objects can be blocked afterwards with
+//! NavGrid::block_collisions().
+//!
+//! Paths are searched with A* (NavGrid::find_path()) and whole distance maps
+//! with Dijkstra (NavGrid::dijkstra()).  Movement follows the orientation of the
+//! map through tiled_json::Neighborhood: 4-way or 8-way on orthogonal and
+//! isometric maps, 6-way on hexagonal maps.  Entering a cell costs its cost
+//! times the length of the step, so diagonal steps cost the square root of two
+//! times as much, and diagonal steps never cut the corner of a blocked cell.
+//!
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::grid::Neighborhood;
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::property::HasProperty;
+use crate::property::PropertyValue;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A grid of movement costs the size of a map.
+pub struct NavGrid {
+    pub width: u32,
+    pub height: u32,
+    pub neighborhood: Neighborhood,
+    pub costs: Vec<Option<f64>>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A path found through a NavGrid: every cell from start to goal (both
+/// included) and the total cost of walking it.
+pub struct NavPath {
+    pub cells: Vec<(u32, u32)>,
+    pub cost: f64,
+}
+
+impl NavPath {
+    /// Borrow the cells of the path, from start to goal.
+    pub fn cells(&self) -> &Vec<(u32, u32)> {
+        &self.cells
+    }
+
+    /// The total cost of the path.
+    pub fn cost(&self) -> f64 {
+        self.cost
+    }
+}
+
+impl NavGrid {
+    /// Build a grid from tile layers, asking cost for the cost of every gid.
+    ///
+    /// cost receives each gid as stored in the layer data (flags included, and
+    /// 0 for empty cells) and returns None when the cell cannot be walked on.
+    /// Layers that are not tile layers are skipped.  diagonal selects 8-way
+    /// movement on orthogonal and isometric maps.
+    ///
+    /// Costs must be positive and finite: an error is returned for the first
+    /// gid whose cost is zero, negative, infinite or NaN.
+    pub fn new<F>(
+        map: &Map,
+        layers: &[&Layer],
+        diagonal: bool,
+        cost: F,
+    ) -> Result<NavGrid, String>
+    where
+        F: Fn(u32) -> Option<f64>,
+    {
+        let width = map.width as u32;
+        let height = map.height as u32;
+        let size = (width * height) as usize;
+        let mut costs = vec![Option::Some(1.0); size];
+        let mut seen = vec![false; size];
+
+        for layer in layers.iter() {
+            let data = match layer.get_data() {
+                Option::Some(d) => d,
+                Option::None => continue,
+            };
+            for (pos, gid) in data.iter().enumerate().take(size) {
+                let c = cost(*gid);
+                if let Option::Some(c) = c {
+                    check_cost(c).map_err(|e| format!("Gid {}: {}", gid, e))?;
+                }
+                costs[pos] = if !seen[pos] {
+                    c
+                } else {
+                    match (costs[pos], c) {
+                        (Option::Some(old), Option::Some(new)) => Option::Some(old.max(new)),
+                        _ => Option::None,
+                    }
+                };
+                seen[pos] = true;
+            }
+        }
+
+        Ok(NavGrid {
+            width,
+            height,
+            neighborhood: Neighborhood::from_map(map, diagonal),
+            costs,
+        })
+    }
+
+    /// Build a grid from tile layers, reading the cost of every gid from the
+    /// tile property name.
+    ///
+    /// A boolean property makes the tile walkable with a cost of 1 when true and
+    /// blocked when false.  An int or float property gives the cost directly;
+    /// zero, negative, infinite and NaN values block the tile.  Empty cells and
+    /// tiles without the property are walkable with a cost of 1.
+    pub fn from_property(map: &Map, layers: &[&Layer], name: &str, diagonal: bool) -> NavGrid {
+        NavGrid::new(map, layers, diagonal, |gid| {
+            let value = map.tile_by_gid(gid).and_then(|t| t.get_property_value(name));
+            match value {
+                Option::Some(PropertyValue::Bool(false)) => Option::None,
+                Option::Some(PropertyValue::Int(i)) if *i <= 0 => Option::None,
+                Option::Some(PropertyValue::Int(i)) => Option::Some(*i as f64),
+                Option::Some(PropertyValue::Float(f)) if check_cost(*f).is_err() => Option::None,
+                Option::Some(PropertyValue::Float(f)) => Option::Some(*f),
+                _ => Option::Some(1.0),
+            }
+        })
+        .expect("costs read from properties are positive and finite")
+    }
+
+    /// Block every cell of a tile layer whose tile has collision objects
+    /// defined in its tileset.
+    pub fn block_collisions(&mut self, map: &Map, layer: &Layer) {
+        let data = match layer.get_data() {
+            Option::Some(d) => d,
+            Option::None => return,
+        };
+        for (pos, gid) in data.iter().enumerate().take(self.costs.len()) {
+            let solid = map
+                .tile_by_gid(*gid)
+                .and_then(|t| t.object_group())
+                .and_then(|l| l.get_objects_vector())
+                .is_some_and(|o| !o.is_empty());
+            if solid {
+                self.costs[pos] = Option::None;
+            }
+        }
+    }
+
+    /// Width of the grid in cells.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the grid in cells.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The neighborhood used to move from cell to cell.
+    pub fn neighborhood(&self) -> Neighborhood {
+        self.neighborhood
+    }
+
+    /// The cost of entering the cell, or None if it is blocked or outside the grid.
+    pub fn cost(&self, x: u32, y: u32) -> Option<f64> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        self.costs[(y * self.width + x) as usize]
+    }
+
+    /// Change the cost of a cell; None blocks it.  Cells outside the grid are ignored.
+    /// An error is returned, and the cell left alone, when the cost is not
+    /// positive and finite.
+    pub fn set_cost(&mut self, x: u32, y: u32, cost: Option<f64>) -> Result<(), String> {
+        if let Option::Some(c) = cost {
+            check_cost(c)?;
+        }
+        if x < self.width && y < self.height {
+            self.costs[(y * self.width + x) as usize] = cost;
+        }
+        Ok(())
+    }
+
+    /// Can the cell be walked on?
+    pub fn is_walkable(&self, x: u32, y: u32) -> bool {
+        self.cost(x, y).is_some()
+    }
+
+    /// Find the cheapest path from start to goal with A*.
+    ///
+    /// None is returned when either end is blocked or no path exists.
+    pub fn find_path(&self, start: (u32, u32), goal: (u32, u32)) -> Option<NavPath> {
+        if !self.is_walkable(start.0, start.1) || !self.is_walkable(goal.0, goal.1) {
+            return Option::None;
+        }
+
+        let min_cost = self
+            .costs
+            .iter()
+            .flatten()
+            .fold(f64::INFINITY, |m, c| m.min(*c));
+        let heuristic = |i: usize| -> f64 {
+            let (x, y) = ((i as u32 % self.width) as i32, (i as u32 / self.width) as i32);
+            let (gx, gy) = (goal.0 as i32, goal.1 as i32);
+            let steps = match self.neighborhood {
+                Neighborhood::Eight => {
+                    let (dx, dy) = ((gx - x).abs() as f64, (gy - y).abs() as f64);
+                    dx.max(dy) + (std::f64::consts::SQRT_2 - 1.0) * dx.min(dy)
+                }
+                n => n.distance(x, y, gx, gy) as f64,
+            };
+            steps * min_cost
+        };
+
+        let start = self.index(start.0, start.1);
+        let goal = self.index(goal.0, goal.1);
+        let mut dist = vec![f64::INFINITY; self.costs.len()];
+        let mut came_from = vec![usize::MAX; self.costs.len()];
+        let mut open = BinaryHeap::new();
+        dist[start] = 0.0;
+        open.push(OpenCell {
+            priority: heuristic(start),
+            index: start,
+        });
+
+        while let Option::Some(OpenCell { priority, index }) = open.pop() {
+            if index == goal {
+                let mut cells = vec![index];
+                let mut cur = index;
+                while came_from[cur] != usize::MAX {
+                    cur = came_from[cur];
+                    cells.push(cur);
+                }
+                cells.reverse();
+                return Option::Some(NavPath {
+                    cells: cells.into_iter().map(|i| self.coords(i)).collect(),
+                    cost: dist[goal],
+                });
+            }
+            if priority > dist[index] + heuristic(index) {
+                continue;
+            }
+            for (next, step) in self.steps(index) {
+                let d = dist[index] + step;
+                if d < dist[next] {
+                    dist[next] = d;
+                    came_from[next] = index;
+                    open.push(OpenCell {
+                        priority: d + heuristic(next),
+                        index: next,
+                    });
+                }
+            }
+        }
+        Option::None
+    }
+
+    /// Compute the cost of the cheapest path from start to every cell with
+    /// Dijkstra's algorithm.
+    ///
+    /// The vector is indexed like tile layer data (y * width + x); unreachable
+    /// and blocked cells hold None.
+    pub fn dijkstra(&self, start: (u32, u32)) -> Vec<Option<f64>> {
+        let mut dist = vec![f64::INFINITY; self.costs.len()];
+        if self.is_walkable(start.0, start.1) {
+            let start = self.index(start.0, start.1);
+            let mut open = BinaryHeap::new();
+            dist[start] = 0.0;
+            open.push(OpenCell {
+                priority: 0.0,
+                index: start,
+            });
+            while let Option::Some(OpenCell { priority, index }) = open.pop() {
+                if priority > dist[index] {
+                    continue;
+                }
+                for (next, step) in self.steps(index) {
+                    let d = priority + step;
+                    if d < dist[next] {
+                        dist[next] = d;
+                        open.push(OpenCell {
+                            priority: d,
+                            index: next,
+                        });
+                    }
+                }
+            }
+        }
+        dist.into_iter()
+            .map(|d| if d.is_finite() { Option::Some(d) } else { Option::None })
+            .collect()
+    }
+
+    // The walkable cells reachable in one step from index, with the cost of the step.
+    fn steps(&self, index: usize) -> Vec<(usize, f64)> {
+        let (x, y) = self.coords(index);
+        let (x, y) = (x as i32, y as i32);
+        let mut out = Vec::new();
+        for (nx, ny) in self.neighborhood.neighbors(x, y) {
+            let cost = match self.signed_cost(nx, ny) {
+                Option::Some(c) => c,
+                Option::None => continue,
+            };
+            if let Neighborhood::Eight = self.neighborhood {
+                if nx != x
+                    && ny != y
+                    && (self.signed_cost(nx, y).is_none() || self.signed_cost(x, ny).is_none())
+                {
+                    continue;
+                }
+            }
+            let step = self.neighborhood.step_length(x, y, nx, ny);
+            out.push(((ny as u32 * self.width + nx as u32) as usize, cost * step));
+        }
+        out
+    }
+
+    fn signed_cost(&self, x: i32, y: i32) -> Option<f64> {
+        if x < 0 || y < 0 {
+            return Option::None;
+        }
+        self.cost(x as u32, y as u32)
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    fn coords(&self, index: usize) -> (u32, u32) {
+        (index as u32 % self.width, index as u32 / self.width)
+    }
+}
+
+// Dijkstra and A* need every step to cost something: a zero or negative cost
+// breaks the heuristic and NaN breaks the ordering of the open set.
+fn check_cost(cost: f64) -> Result<(), String> {
+    if cost > 0.0 && cost.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("a cost of {} is not positive and finite.", cost))
+    }
+}
+
+// An entry of the open set, ordered so that the lowest priority pops first.
+struct OpenCell {
+    priority: f64,
+    index: usize,
+}
+
+impl PartialEq for OpenCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority
+    }
+}
+
+impl Eq for OpenCell {}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Option::Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .partial_cmp(&self.priority)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NavGrid;
+    use crate::map::Map;
+
+    // A 5x5 map whose column x = 2 is a wall (gid 1) with a gap at the bottom,
+    // and whose cell (1, 1) is mud (gid 2).
+    fn walled() -> Map {
+        serde_json::from_str(
+            r#"{
+                "orientation": "orthogonal", "width": 5, "height": 5,
+                "tilewidth": 16, "tileheight": 16, "nextobjectid": 1, "nextlayerid": 2,
+                "layers": [{ "type": "tilelayer", "id": 1, "name": "ground",
+                             "width": 5, "height": 5, "data": [
+                    0, 0, 1, 0, 0,
+                    0, 2, 1, 0, 0,
+                    0, 0, 1, 0, 0,
+                    0, 0, 1, 0, 0,
+                    0, 0, 0, 0, 0
+                ] }]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn grid(map: &Map, diagonal: bool) -> NavGrid {
+        NavGrid::new(map, &[&map.layers[0]], diagonal, |gid| match gid {
+            1 => Option::None,
+            2 => Option::Some(5.0),
+            _ => Option::Some(1.0),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn a_star_goes_around_the_wall() {
+        let map = walled();
+        let path = grid(&map, false).find_path((0, 0), (4, 0)).unwrap();
+        assert_eq!(path.cost(), 12.0);
+        assert_eq!(path.cells().len(), 13);
+        assert_eq!(path.cells()[0], (0, 0));
+        assert_eq!(path.cells()[12], (4, 0));
+        // The cheapest way goes through the gap and around the mud.
+        assert!(path.cells().contains(&(2, 4)));
+        assert!(!path.cells().contains(&(1, 1)));
+        assert!(path.cells().windows(2).all(|w| {
+            let (a, b) = (w[0], w[1]);
+            (a.0 as i32 - b.0 as i32).abs() + (a.1 as i32 - b.1 as i32).abs() == 1
+        }));
+    }
+
+    #[test]
+    fn diagonal_steps_do_not_cut_corners() {
+        let map = walled();
+        let path = grid(&map, true).find_path((1, 3), (3, 3)).unwrap();
+        // Stepping diagonally into or out of the gap would cut the corner of
+        // the wall at (2, 3).
+        assert_eq!(path.cells(), &vec![(1, 3), (1, 4), (2, 4), (3, 4), (3, 3)]);
+        assert_eq!(path.cost(), 4.0);
+
+        // In the open, diagonal steps cost the square root of two.
+        let path = grid(&map, true).find_path((3, 0), (4, 1)).unwrap();
+        assert_eq!(path.cells().len(), 2);
+        assert!((path.cost() - std::f64::consts::SQRT_2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn blocked_ends_and_dijkstra() {
+        let map = walled();
+        let mut grid = grid(&map, false);
+        assert!(grid.find_path((0, 0), (2, 0)).is_none());
+
+        let dist = grid.dijkstra((0, 0));
+        assert_eq!(dist[4], Option::Some(12.0));
+        assert_eq!(dist[2], Option::None);
+
+        grid.set_cost(2, 4, Option::None).unwrap();
+        assert!(grid.find_path((0, 0), (4, 0)).is_none());
+    }
+
+    #[test]
+    fn costs_must_be_positive_and_finite() {
+        let map = walled();
+        for bad in [0.0, -1.0, f64::NAN, f64::INFINITY].iter() {
+            let grid = NavGrid::new(&map, &[&map.layers[0]], false, |gid| match gid {
+                2 => Option::Some(*bad),
+                _ => Option::Some(1.0),
+            });
+            assert!(grid.unwrap_err().starts_with("Gid 2: "));
+        }
+
+        let mut grid = grid(&map, false);
+        assert!(grid.set_cost(0, 0, Option::Some(0.0)).is_err());
+        assert!(grid.set_cost(0, 0, Option::Some(f64::NAN)).is_err());
+        assert_eq!(grid.cost(0, 0), Option::Some(1.0));
+        assert!(grid.set_cost(0, 0, Option::Some(2.5)).is_ok());
+        assert_eq!(grid.cost(0, 0), Option::Some(2.5));
+    }
+}
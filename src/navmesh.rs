@@ -0,0 +1,488 @@
+//!
+//! NavMesh is a polygon navigation mesh for smooth, any-angle movement.
+//!
+//! The walkable area is described either by the shapes of an object group
+//! (polygons, rectangles and ellipses drawn in Tiled) or by the traced outlines
+//! of walkable tiles.  Obstacle objects are cut out of that area, and what
+//! remains is kept as a set of convex polygons linked to each other through
+//! portals, the stretches of edge that two polygons share.
+//!
+//! NavMesh::find_path() searches the polygons with A* and straightens the
+//! resulting corridor with the funnel algorithm, giving the shortest path that
+//! goes through it as a list of points.
+//!
+//! All coordinates are map pixels, like the x and y of objects.  Layer offsets
+//! of the object groups used are applied.
+//!
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::object::Point;
+use crate::outline::trace_outlines;
+use crate::outline::Outline;
+use crate::triangulate::convex_decomposition;
+use crate::triangulate::convex_decomposition_outline;
+use crate::triangulate::cross;
+use crate::triangulate::same_point;
+use crate::triangulate::signed_area;
+
+const EPSILON: f64 = 0.000_1;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A navigation mesh: convex polygons and the portals linking them.
+pub struct NavMesh {
+    pub polygons: Vec<Vec<Point>>,
+    pub portals: Vec<Vec<Portal>>,
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A portal is the shared stretch of edge leading from one polygon into the
+/// polygon at index to.  Left and right are seen by someone walking through it.
+pub struct Portal {
+    pub to: usize,
+    pub left: Point,
+    pub right: Point,
+}
+
+impl NavMesh {
+    /// Build a navmesh from walkable areas (with holes) minus obstacle polygons.
+    ///
+    /// Obstacles may overlap each other and the edges of the walkable areas.
+    pub fn new(areas: &[Outline], obstacles: &[Vec<Point>]) -> NavMesh {
+        let mut pieces: Vec<Vec<Point>> = Vec::new();
+        for area in areas.iter() {
+            pieces.extend(convex_decomposition_outline(area));
+        }
+
+        for obstacle in obstacles.iter() {
+            for cutter in convex_decomposition(obstacle) {
+                pieces = pieces
+                    .into_iter()
+                    .flat_map(|p| subtract_convex(p, &cutter))
+                    .collect();
+            }
+        }
+
+        let portals = link_polygons(&pieces);
+        NavMesh {
+            polygons: pieces,
+            portals,
+        }
+    }
+
+    /// Build a navmesh from the shapes of an object group, minus the shapes of
+    /// every object in the obstacle groups.
+    ///
+    /// Polygons, rectangles, ellipses and tile objects count; points, polylines
+    /// and text are ignored.
+    pub fn from_object_groups(walkable: &Layer, obstacles: &[&Layer]) -> NavMesh {
+        let areas: Vec<Outline> = layer_shapes(walkable)
+            .into_iter()
+            .map(|exterior| Outline {
+                exterior,
+                holes: Vec::new(),
+            })
+            .collect();
+        NavMesh::new(&areas, &obstacle_shapes(obstacles))
+    }
+
+    /// Build a navmesh from the tiles of a tile layer for which walkable returns
+    /// true, minus the shapes of every object in the obstacle groups.
+    ///
+    /// See tiled_json::trace_outlines() for how the walkable tiles are traced.
+    pub fn from_tiles<F>(map: &Map, layer: &Layer, walkable: F, obstacles: &[&Layer]) -> NavMesh
+    where
+        F: Fn(u32) -> bool,
+    {
+        let areas = trace_outlines(map, layer, walkable);
+        NavMesh::new(&areas, &obstacle_shapes(obstacles))
+    }
+
+    /// Borrow the convex polygons of the mesh.  Each winds clockwise on screen.
+    pub fn polygons(&self) -> &Vec<Vec<Point>> {
+        &self.polygons
+    }
+
+    /// Borrow the portals leaving each polygon, indexed like the polygons.
+    pub fn portals(&self) -> &Vec<Vec<Portal>> {
+        &self.portals
+    }
+
+    /// Find the index of the polygon containing point, if any.
+    pub fn polygon_at(&self, point: Point) -> Option<usize> {
+        self.polygons.iter().position(|poly| {
+            let n = poly.len();
+            (0..n).all(|i| cross(poly[i], poly[(i + 1) % n], point) >= -EPSILON)
+        })
+    }
+
+    /// Find the polygons a path from start to goal goes through, in order, with A*.
+    ///
+    /// None is returned when either point lies outside the mesh or no path exists.
+    pub fn find_corridor(&self, start: Point, goal: Point) -> Option<Vec<usize>> {
+        let from = self.polygon_at(start)?;
+        let to = self.polygon_at(goal)?;
+
+        // Polygons are entered through the middle of a portal; the first one is
+        // entered at the start point.
+        let mut entry = vec![start; self.polygons.len()];
+        let mut dist = vec![f64::INFINITY; self.polygons.len()];
+        let mut came_from = vec![usize::MAX; self.polygons.len()];
+        let mut open = BinaryHeap::new();
+        dist[from] = 0.0;
+        open.push(OpenPolygon {
+            priority: distance(start, goal),
+            index: from,
+        });
+
+        while let Option::Some(OpenPolygon { priority, index }) = open.pop() {
+            if index == to {
+                let mut corridor = vec![to];
+                let mut cur = to;
+                while came_from[cur] != usize::MAX {
+                    cur = came_from[cur];
+                    corridor.push(cur);
+                }
+                corridor.reverse();
+                return Option::Some(corridor);
+            }
+            if priority > dist[index] + distance(entry[index], goal) + EPSILON {
+                continue;
+            }
+            for portal in self.portals[index].iter() {
+                let mid = Point::new(
+                    (portal.left.x + portal.right.x) / 2.0,
+                    (portal.left.y + portal.right.y) / 2.0,
+                );
+                let d = dist[index] + distance(entry[index], mid);
+                if d < dist[portal.to] {
+                    dist[portal.to] = d;
+                    entry[portal.to] = mid;
+                    came_from[portal.to] = index;
+                    open.push(OpenPolygon {
+                        priority: d + distance(mid, goal),
+                        index: portal.to,
+                    });
+                }
+            }
+        }
+        Option::None
+    }
+
+    /// Find a smooth path from start to goal: the corridor found by
+    /// find_corridor(), straightened with the funnel algorithm.  The path starts
+    /// with start, ends with goal and only turns at corners of the corridor.
+    pub fn find_path(&self, start: Point, goal: Point) -> Option<Vec<Point>> {
+        let corridor = self.find_corridor(start, goal)?;
+        let mut portals = vec![(start, start)];
+        for pair in corridor.windows(2) {
+            let portal = self.portals[pair[0]].iter().find(|p| p.to == pair[1])?;
+            portals.push((portal.left, portal.right));
+        }
+        portals.push((goal, goal));
+        Option::Some(string_pull(&portals))
+    }
+}
+
+// The funnel algorithm over (left, right) portals, from the start point in the
+// first portal to the goal point in the last one.
+fn string_pull(portals: &[(Point, Point)]) -> Vec<Point> {
+    let mut path = vec![portals[0].0];
+    let mut apex = portals[0].0;
+    let (mut left, mut right) = portals[0];
+    let (mut left_i, mut right_i) = (0, 0);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let (l, r) = portals[i];
+
+        // Tighten the right side of the funnel.
+        if cross2(apex, right, r) <= 0.0 {
+            if same_point(apex, right) || cross2(apex, left, r) > 0.0 {
+                right = r;
+                right_i = i;
+            } else {
+                // The right side crossed the left one: the left corner is a turn.
+                path.push(left);
+                apex = left;
+                right = apex;
+                right_i = left_i;
+                i = left_i + 1;
+                continue;
+            }
+        }
+
+        // Tighten the left side of the funnel.
+        if cross2(apex, left, l) >= 0.0 {
+            if same_point(apex, left) || cross2(apex, right, l) < 0.0 {
+                left = l;
+                left_i = i;
+            } else {
+                path.push(right);
+                apex = right;
+                left = apex;
+                left_i = right_i;
+                i = right_i + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    let goal = portals[portals.len() - 1].0;
+    if !same_point(path[path.len() - 1], goal) {
+        path.push(goal);
+    }
+    path
+}
+
+// Cut the convex polygon cutter out of the convex polygon piece, leaving
+// convex pieces.  Both wind clockwise on screen.
+fn subtract_convex(piece: Vec<Point>, cutter: &[Point]) -> Vec<Vec<Point>> {
+    if !overlaps(&piece, cutter) {
+        return vec![piece];
+    }
+
+    let mut out = Vec::new();
+    let mut rest = piece;
+    let n = cutter.len();
+    for i in 0..n {
+        let (a, b) = (cutter[i], cutter[(i + 1) % n]);
+        let outside = clip_half_plane(&rest, a, b, false);
+        if signed_area(&outside) > EPSILON {
+            out.push(outside);
+        }
+        rest = clip_half_plane(&rest, a, b, true);
+        if signed_area(&rest) <= EPSILON {
+            return out;
+        }
+    }
+    out
+}
+
+// Keep the part of a convex polygon on the inner (right, for a clockwise
+// cutter) side of the line a->b, or on the outer side when inside is false.
+fn clip_half_plane(poly: &[Point], a: Point, b: Point, inside: bool) -> Vec<Point> {
+    let side = |p: Point| {
+        let c = cross(a, b, p);
+        if inside {
+            c
+        } else {
+            -c
+        }
+    };
+    let mut out = Vec::with_capacity(poly.len() + 1);
+    let n = poly.len();
+    for i in 0..n {
+        let (p, q) = (poly[i], poly[(i + 1) % n]);
+        let (sp, sq) = (side(p), side(q));
+        if sp >= 0.0 {
+            out.push(p);
+        }
+        if (sp > 0.0 && sq < 0.0) || (sp < 0.0 && sq > 0.0) {
+            let t = sp / (sp - sq);
+            out.push(Point::new(p.x + (q.x - p.x) * t, p.y + (q.y - p.y) * t));
+        }
+    }
+    crate::triangulate::cleanup_polygon(&out)
+}
+
+// Separating axis test for two convex polygons.  Touching does not count.
+fn overlaps(a: &[Point], b: &[Point]) -> bool {
+    let separated = |p: &[Point], q: &[Point]| {
+        let n = p.len();
+        (0..n).any(|i| {
+            let (s, e) = (p[i], p[(i + 1) % n]);
+            q.iter().all(|v| cross(s, e, *v) <= EPSILON)
+        })
+    };
+    !separated(a, b) && !separated(b, a)
+}
+
+// Find the portals between every pair of polygons sharing part of an edge.
+fn link_polygons(polygons: &[Vec<Point>]) -> Vec<Vec<Portal>> {
+    let bounds: Vec<(f64, f64, f64, f64)> = polygons.iter().map(|p| bounding_box(p)).collect();
+    let mut portals: Vec<Vec<Portal>> = vec![Vec::new(); polygons.len()];
+    for i in 0..polygons.len() {
+        for j in (i + 1)..polygons.len() {
+            let (a, b) = (bounds[i], bounds[j]);
+            if a.0 > b.2 + EPSILON || b.0 > a.2 + EPSILON || a.1 > b.3 + EPSILON || b.1 > a.3 + EPSILON {
+                continue;
+            }
+            if let Option::Some((left, right)) = shared_edge(&polygons[i], &polygons[j]) {
+                portals[i].push(Portal { to: j, left, right });
+                portals[j].push(Portal {
+                    to: i,
+                    left: right,
+                    right: left,
+                });
+            }
+        }
+    }
+    portals
+}
+
+// The longest stretch of edge shared by two clockwise polygons, as (left, right)
+// seen when walking from p into q.
+fn shared_edge(p: &[Point], q: &[Point]) -> Option<(Point, Point)> {
+    let mut best: Option<(Point, Point, f64)> = Option::None;
+    let (np, nq) = (p.len(), q.len());
+    for i in 0..np {
+        let (a, b) = (p[i], p[(i + 1) % np]);
+        let len2 = (b.x - a.x) * (b.x - a.x) + (b.y - a.y) * (b.y - a.y);
+        if len2 < EPSILON {
+            continue;
+        }
+        let len = len2.sqrt();
+        for j in 0..nq {
+            let (c, d) = (q[j], q[(j + 1) % nq]);
+            // Both ends of the other edge must lie on this edge's line...
+            if (cross(a, b, c) / len).abs() > EPSILON || (cross(a, b, d) / len).abs() > EPSILON {
+                continue;
+            }
+            // ...and run the opposite way.
+            let tc = ((c.x - a.x) * (b.x - a.x) + (c.y - a.y) * (b.y - a.y)) / len2;
+            let td = ((d.x - a.x) * (b.x - a.x) + (d.y - a.y) * (b.y - a.y)) / len2;
+            if tc <= td {
+                continue;
+            }
+            let t0 = td.max(0.0);
+            let t1 = tc.min(1.0);
+            let overlap = (t1 - t0) * len;
+            if overlap > EPSILON && best.is_none_or(|bst| overlap > bst.2) {
+                let at = |t: f64| Point::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+                best = Option::Some((at(t0), at(t1), overlap));
+            }
+        }
+    }
+    best.map(|(l, r, _)| (l, r))
+}
+
+fn layer_shapes(layer: &Layer) -> Vec<Vec<Point>> {
+    let objects = match layer.get_objects_vector() {
+        Option::Some(o) => o,
+        Option::None => return Vec::new(),
+    };
+    objects
+        .iter()
+        .filter_map(|o| o.outline())
+        .map(|shape| {
+            shape
+                .into_iter()
+                .map(|p| Point::new(p.x + layer.offsetx, p.y + layer.offsety))
+                .collect()
+        })
+        .collect()
+}
+
+fn obstacle_shapes(layers: &[&Layer]) -> Vec<Vec<Point>> {
+    layers.iter().flat_map(|l| layer_shapes(l)).collect()
+}
+
+fn bounding_box(points: &[Point]) -> (f64, f64, f64, f64) {
+    points.iter().fold(
+        (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        |b, p| (b.0.min(p.x), b.1.min(p.y), b.2.max(p.x), b.3.max(p.y)),
+    )
+}
+
+// Cross product of (b - o) and (c - o).  Positive when c lies clockwise of b,
+// that is to the right of b as seen from o.
+fn cross2(o: Point, b: Point, c: Point) -> f64 {
+    (b.x - o.x) * (c.y - o.y) - (b.y - o.y) * (c.x - o.x)
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    ((a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y)).sqrt()
+}
+
+// An entry of the open set, ordered so that the lowest priority pops first.
+struct OpenPolygon {
+    priority: f64,
+    index: usize,
+}
+
+impl PartialEq for OpenPolygon {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority
+    }
+}
+
+impl Eq for OpenPolygon {}
+
+impl PartialOrd for OpenPolygon {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Option::Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenPolygon {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .partial_cmp(&self.priority)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NavMesh;
+    use crate::object::Point;
+    use crate::outline::Outline;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Vec<Point> {
+        vec![
+            Point::new(x, y),
+            Point::new(x + width, y),
+            Point::new(x + width, y + height),
+            Point::new(x, y + height),
+        ]
+    }
+
+    // A 100x100 room with a wall hanging from its top edge down to y = 80.
+    fn room() -> NavMesh {
+        let area = Outline {
+            exterior: rect(0.0, 0.0, 100.0, 100.0),
+            holes: Vec::new(),
+        };
+        NavMesh::new(&[area], &[rect(40.0, -10.0, 20.0, 90.0)])
+    }
+
+    #[test]
+    fn path_turns_at_the_corners_of_the_wall() {
+        let mesh = room();
+        assert!(mesh.polygon_at(Point::new(50.0, 40.0)).is_none());
+        assert!(mesh.polygon_at(Point::new(50.0, 90.0)).is_some());
+
+        let path = mesh
+            .find_path(Point::new(10.0, 10.0), Point::new(90.0, 10.0))
+            .unwrap();
+        let expected = [(10.0, 10.0), (40.0, 80.0), (60.0, 80.0), (90.0, 10.0)];
+        assert_eq!(path.len(), expected.len());
+        for (p, e) in path.iter().zip(expected.iter()) {
+            assert!((p.x - e.0).abs() < 1e-6 && (p.y - e.1).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn straight_path_and_unreachable_points() {
+        let mesh = room();
+        let path = mesh
+            .find_path(Point::new(10.0, 10.0), Point::new(10.0, 90.0))
+            .unwrap();
+        assert_eq!(path.len(), 2);
+
+        assert!(mesh
+            .find_path(Point::new(10.0, 10.0), Point::new(50.0, 40.0))
+            .is_none());
+        assert!(mesh
+            .find_path(Point::new(-5.0, 10.0), Point::new(10.0, 90.0))
+            .is_none());
+    }
+}
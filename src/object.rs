@@ -27,6 +27,7 @@ use crate::color::Color;
 use crate::property::HasProperty;
 use crate::property::Property;
 use serde::Deserialize;
+use serde::Serialize;
 
 const ALIGN_LEFT: &str = "left";
 const ALIGN_RIGHT: &str = "right";
@@ -35,7 +36,10 @@ const ALIGN_CENTER: &str = "center";
 const ALIGN_TOP: &str = "top";
 const ALIGN_BOTTOM: &str = "bottom";
 
-#[derive(Deserialize)]
+/// The number of points used when approximating ellipses with polygons.
+pub const ELLIPSE_SEGMENTS: usize = 16;
+
+#[derive(Deserialize, Serialize, Clone)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// Means of describing nodes in objectgroup layers.
 pub struct Object {
@@ -43,7 +47,7 @@ pub struct Object {
     pub x: f64,
     pub y: f64,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub gid: Option<u32>, // only if represents tile.
 
     #[serde(default)]
@@ -70,13 +74,13 @@ pub struct Object {
     #[serde(default = "default_to_false")]
     pub point: bool,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub polygon: Option<Vec<Point>>,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub polyline: Option<Vec<Point>>,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub text: Option<Text>,
 
     #[serde(default)]
@@ -180,9 +184,67 @@ impl Object {
     pub fn text(&self) -> Option<&Text> {
         self.text.as_ref()
     }
+
+    /// Builds the closed outline of the area covered by this object, in the
+    /// same coordinate space as the object itself and with rotation applied.
+    ///
+    /// Rectangles and tile objects give four points, ellipses are approximated
+    /// with ELLIPSE_SEGMENTS points, and polygons are translated by the object
+    /// position.  Points, polylines and text cover no area and give None, as do
+    /// rectangles without a width or height.
+    pub fn outline(&self) -> Option<Vec<Point>> {
+        if self.point || self.polyline.is_some() || self.text.is_some() {
+            return Option::None;
+        }
+
+        let points: Vec<Point> = if let Option::Some(ref poly) = self.polygon {
+            poly.iter()
+                .map(|p| Point::new(self.x + p.x, self.y + p.y))
+                .collect()
+        } else if self.width <= 0.0 || self.height <= 0.0 {
+            return Option::None;
+        } else if self.ellipse {
+            let rx = self.width / 2.0;
+            let ry = self.height / 2.0;
+            (0..ELLIPSE_SEGMENTS)
+                .map(|i| {
+                    let a = (i as f64) * std::f64::consts::PI * 2.0 / (ELLIPSE_SEGMENTS as f64);
+                    Point::new(self.x + rx + rx * a.cos(), self.y + ry + ry * a.sin())
+                })
+                .collect()
+        } else {
+            // Tile objects are anchored at their bottom-left corner.
+            let top = if self.gid.is_some() {
+                self.y - self.height
+            } else {
+                self.y
+            };
+            vec![
+                Point::new(self.x, top),
+                Point::new(self.x + self.width, top),
+                Point::new(self.x + self.width, top + self.height),
+                Point::new(self.x, top + self.height),
+            ]
+        };
+
+        if self.rotation == 0.0 {
+            return Option::Some(points);
+        }
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        Option::Some(
+            points
+                .iter()
+                .map(|p| {
+                    let dx = p.x - self.x;
+                    let dy = p.y - self.y;
+                    Point::new(self.x + dx * cos - dy * sin, self.y + dx * sin + dy * cos)
+                })
+                .collect(),
+        )
+    }
 }
 
-#[derive(Deserialize, Copy, Clone)]
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// Points describe single points on maps and are generally used to describe 
 /// polygons and polylines.  They only have x and y components.
@@ -192,6 +254,11 @@ pub struct Point {
 }
 
 impl Point {
+    /// Create a new point from its x and y components.
+    pub fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
     /// Get the x value of the point.
     pub fn x(&self) -> f64 {
         self.x
@@ -203,7 +270,7 @@ impl Point {
     }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// Text is an oject that contains all kinds of characteristics of text that Tiled 
 /// is able to display, including the string itself.
@@ -319,8 +386,8 @@ impl Text {
     }
 }
 
-#[derive(Deserialize, Copy, Clone)]
-#[serde(from = "String")]
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq)]
+#[serde(from = "String", into = "String")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// This enum describes the horizontal alignment of text.  It has 4 variants:
 /// - Center 
@@ -349,8 +416,8 @@ impl std::fmt::Display for HAlign {
     }
 }
 
-#[derive(Deserialize, Copy, Clone)]
-#[serde(from = "String")]
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq)]
+#[serde(from = "String", into = "String")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// This enum describes the vertical alignment of text.  It has 3 variants:
 /// - Center 
@@ -376,6 +443,18 @@ impl std::fmt::Display for VAlign {
     }
 }
 
+impl From<HAlign> for String {
+    fn from(hal: HAlign) -> Self {
+        hal.to_string()
+    }
+}
+
+impl From<VAlign> for String {
+    fn from(val: VAlign) -> Self {
+        val.to_string()
+    }
+}
+
 impl From<String> for HAlign {
     fn from(hal: String) -> Self {
         match hal.as_str() {
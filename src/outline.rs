@@ -0,0 +1,354 @@
+//!
+//! Outline tracing turns solid areas of a tile layer into closed polygons.
+//!
+//! Lighting occluders and static colliders are best described by the boundary
+//! of a solid area instead of one box per tile.  Solid cells are chosen by a
+//! predicate over the gids of the layer (or by a boolean tile property such as
+//! `solid`), grouped into 4-connected regions and traced along their edges.
+//! Each region gives one Outline: an exterior boundary and any number of holes.
+//!
+//! Points are in pixels, using the tilewidth and tileheight of the map and the
+//! offset of the layer.  Exteriors wind clockwise on screen (y pointing down)
+//! and holes wind counter-clockwise.  Only the corners of each boundary are kept;
+//! points in the middle of straight runs are dropped.
+//!
+
+use std::collections::HashMap;
+
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::object::Point;
+use crate::property::HasProperty;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A closed polygon with holes, tracing the boundary of one solid region.
+pub struct Outline {
+    pub exterior: Vec<Point>,
+    pub holes: Vec<Vec<Point>>,
+}
+
+impl Outline {
+    /// Borrow the points of the outer boundary.
+    pub fn exterior(&self) -> &Vec<Point> {
+        &self.exterior
+    }
+
+    /// Borrow the holes of the region.  This vector is empty for solid regions.
+    pub fn holes(&self) -> &Vec<Vec<Point>> {
+        &self.holes
+    }
+}
+
+/// Trace the outlines of the cells in a tile layer for which solid returns true.
+///
+/// The predicate receives each gid as stored in the layer data, flags included.
+/// Empty cells (gid 0) are never solid.  An empty vector is returned if the layer
+/// is not a tile layer.
+pub fn trace_outlines<F>(map: &Map, layer: &Layer, solid: F) -> Vec<Outline>
+where
+    F: Fn(u32) -> bool,
+{
+    let data = match layer.get_data() {
+        Option::Some(d) => d,
+        Option::None => return Vec::new(),
+    };
+    let columns = layer.width as usize;
+    let rows = layer.height as usize;
+    if columns == 0 || data.len() < columns * rows {
+        return Vec::new();
+    }
+
+    let cells: Vec<bool> = data[..columns * rows]
+        .iter()
+        .map(|gid| crate::gid_without_flags(*gid) != 0 && solid(*gid))
+        .collect();
+
+    let scale_x = map.tilewidth as f64;
+    let scale_y = map.tileheight as f64;
+    trace_cells(&cells, columns, rows)
+        .into_iter()
+        .map(|(exterior, holes)| {
+            let to_pixels = |ring: Vec<(i64, i64)>| -> Vec<Point> {
+                ring.into_iter()
+                    .map(|(x, y)| {
+                        Point::new(
+                            x as f64 * scale_x + layer.offsetx,
+                            y as f64 * scale_y + layer.offsety,
+                        )
+                    })
+                    .collect()
+            };
+            Outline {
+                exterior: to_pixels(exterior),
+                holes: holes.into_iter().map(to_pixels).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Trace the outlines of the cells whose tile has the boolean property name set
+/// to true, such as `solid`.
+pub fn trace_outlines_by_property(map: &Map, layer: &Layer, name: &str) -> Vec<Outline> {
+    trace_outlines(map, layer, |gid| {
+        map.tile_by_gid(gid)
+            .and_then(|t| t.get_property(name))
+            .and_then(|p| p.get_bool())
+            .unwrap_or(false)
+    })
+}
+
+type Ring = Vec<(i64, i64)>;
+
+// A boundary edge: start corner, end corner and the index of its solid cell.
+type Edge = ((i64, i64), (i64, i64), usize);
+
+// Trace a grid of solid cells into (exterior, holes) rings in cell units.
+//
+// Every side of a solid cell that faces a non-solid cell becomes a directed edge
+// with the solid cell on its right.  Edges are chained into rings; where two
+// regions touch only at a corner, the ring keeps following the cell it came from
+// so that regions stay 4-connected.
+fn trace_cells(cells: &[bool], columns: usize, rows: usize) -> Vec<(Ring, Vec<Ring>)> {
+    let solid = |x: i64, y: i64| -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < columns
+            && (y as usize) < rows
+            && cells[y as usize * columns + x as usize]
+    };
+
+    let mut edges: Vec<Edge> = Vec::new();
+    for y in 0..rows as i64 {
+        for x in 0..columns as i64 {
+            if !solid(x, y) {
+                continue;
+            }
+            let cell = y as usize * columns + x as usize;
+            if !solid(x, y - 1) {
+                edges.push(((x, y), (x + 1, y), cell));
+            }
+            if !solid(x + 1, y) {
+                edges.push(((x + 1, y), (x + 1, y + 1), cell));
+            }
+            if !solid(x, y + 1) {
+                edges.push(((x + 1, y + 1), (x, y + 1), cell));
+            }
+            if !solid(x - 1, y) {
+                edges.push(((x, y + 1), (x, y), cell));
+            }
+        }
+    }
+
+    let mut outgoing: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (i, e) in edges.iter().enumerate() {
+        outgoing.entry(e.0).or_default().push(i);
+    }
+
+    let regions = label_regions(cells, columns, rows);
+    let mut used = vec![false; edges.len()];
+    let mut exteriors: HashMap<usize, Ring> = HashMap::new();
+    let mut holes: HashMap<usize, Vec<Ring>> = HashMap::new();
+    let mut order: Vec<usize> = Vec::new();
+
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        let mut ring: Ring = Vec::new();
+        let mut current = first;
+        loop {
+            used[current] = true;
+            let (start, end, cell) = edges[current];
+            ring.push(start);
+            let next = outgoing.get(&end).and_then(|c| {
+                let free = c.iter().copied().filter(|n| !used[*n]);
+                let mut fallback = Option::None;
+                for n in free {
+                    if edges[n].2 == cell {
+                        return Option::Some(n);
+                    }
+                    fallback = fallback.or(Option::Some(n));
+                }
+                fallback
+            });
+            match next {
+                Option::Some(n) => current = n,
+                Option::None => break,
+            }
+        }
+
+        let ring = simplify_ring(ring);
+        let region = regions[edges[first].2];
+        if signed_area(&ring) > 0 {
+            if !exteriors.contains_key(&region) {
+                order.push(region);
+            }
+            exteriors.insert(region, ring);
+        } else {
+            holes.entry(region).or_default().push(ring);
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|r| {
+            let ext = exteriors.remove(&r).unwrap_or_default();
+            (ext, holes.remove(&r).unwrap_or_default())
+        })
+        .collect()
+}
+
+// Label 4-connected regions of solid cells.  Non-solid cells get usize::MAX.
+fn label_regions(cells: &[bool], columns: usize, rows: usize) -> Vec<usize> {
+    let mut labels = vec![usize::MAX; cells.len()];
+    let mut next = 0;
+    let mut stack = Vec::new();
+    for start in 0..cells.len() {
+        if !cells[start] || labels[start] != usize::MAX {
+            continue;
+        }
+        labels[start] = next;
+        stack.push(start);
+        while let Option::Some(i) = stack.pop() {
+            let (x, y) = (i % columns, i / columns);
+            let mut visit = |n: usize| {
+                if cells[n] && labels[n] == usize::MAX {
+                    labels[n] = next;
+                    stack.push(n);
+                }
+            };
+            if x > 0 {
+                visit(i - 1);
+            }
+            if x + 1 < columns {
+                visit(i + 1);
+            }
+            if y > 0 {
+                visit(i - columns);
+            }
+            if y + 1 < rows {
+                visit(i + columns);
+            }
+        }
+        next += 1;
+    }
+    labels
+}
+
+// Drop points that lie on a straight line between their neighbours.
+fn simplify_ring(ring: Ring) -> Ring {
+    let n = ring.len();
+    if n < 3 {
+        return ring;
+    }
+    let mut out = Vec::with_capacity(n);
+    for i in 0..n {
+        let prev = ring[(i + n - 1) % n];
+        let cur = ring[i];
+        let next = ring[(i + 1) % n];
+        let cross = (cur.0 - prev.0) * (next.1 - cur.1) - (cur.1 - prev.1) * (next.0 - cur.0);
+        if cross != 0 {
+            out.push(cur);
+        }
+    }
+    out
+}
+
+// Twice the signed area of a ring; positive when clockwise with y pointing down.
+fn signed_area(ring: &[(i64, i64)]) -> i64 {
+    let n = ring.len();
+    let mut sum = 0;
+    for i in 0..n {
+        let a = ring[i];
+        let b = ring[(i + 1) % n];
+        sum += a.0 * b.1 - b.0 * a.1;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::signed_area;
+    use super::trace_cells;
+    use super::trace_outlines;
+    use crate::map::Map;
+
+    // Parse a grid drawn with # for solid cells and . for the others.
+    fn grid(rows: &[&str]) -> (Vec<bool>, usize, usize) {
+        let cells = rows
+            .iter()
+            .flat_map(|r| r.chars().map(|c| c == '#'))
+            .collect();
+        (cells, rows[0].len(), rows.len())
+    }
+
+    fn sorted(ring: &[(i64, i64)]) -> Vec<(i64, i64)> {
+        let mut ring = ring.to_vec();
+        ring.sort();
+        ring
+    }
+
+    #[test]
+    fn enclosed_hole() {
+        let (cells, columns, rows) = grid(&["###", "#.#", "###"]);
+        let outlines = trace_cells(&cells, columns, rows);
+        assert_eq!(outlines.len(), 1);
+        let (exterior, holes) = &outlines[0];
+        assert_eq!(exterior, &vec![(0, 0), (3, 0), (3, 3), (0, 3)]);
+        assert_eq!(holes.len(), 1);
+        assert_eq!(sorted(&holes[0]), vec![(1, 1), (1, 2), (2, 1), (2, 2)]);
+        assert!(signed_area(&holes[0]) < 0);
+    }
+
+    #[test]
+    fn regions_touching_at_a_corner_stay_apart() {
+        let (cells, columns, rows) = grid(&["#.", ".#"]);
+        let outlines = trace_cells(&cells, columns, rows);
+        assert_eq!(outlines.len(), 2);
+        assert_eq!(outlines[0].0, vec![(0, 0), (1, 0), (1, 1), (0, 1)]);
+        assert_eq!(outlines[1].0, vec![(1, 1), (2, 1), (2, 2), (1, 2)]);
+        assert!(outlines.iter().all(|(_, holes)| holes.is_empty()));
+    }
+
+    #[test]
+    fn gap_on_the_map_edge_is_not_a_hole() {
+        let (cells, columns, rows) = grid(&["###", "#.#"]);
+        let outlines = trace_cells(&cells, columns, rows);
+        assert_eq!(outlines.len(), 1);
+        assert_eq!(
+            outlines[0].0,
+            vec![
+                (0, 0),
+                (3, 0),
+                (3, 2),
+                (2, 2),
+                (2, 1),
+                (1, 1),
+                (1, 2),
+                (0, 2)
+            ]
+        );
+        assert!(outlines[0].1.is_empty());
+    }
+
+    #[test]
+    fn outlines_are_in_pixels_with_the_layer_offset() {
+        let map: Map = serde_json::from_str(
+            r#"{
+                "orientation": "orthogonal", "width": 3, "height": 1,
+                "tilewidth": 16, "tileheight": 8, "nextobjectid": 1, "nextlayerid": 2,
+                "layers": [{ "type": "tilelayer", "id": 1, "name": "walls", "offsetx": 4,
+                             "width": 3, "height": 1, "data": [1, 2, 1] }]
+            }"#,
+        )
+        .unwrap();
+        let outlines = trace_outlines(&map, &map.layers[0], |gid| gid == 1);
+        assert_eq!(outlines.len(), 2);
+        let corners: Vec<(f64, f64)> = outlines[1].exterior.iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(
+            corners,
+            vec![(36.0, 0.0), (52.0, 0.0), (52.0, 8.0), (36.0, 8.0)]
+        );
+    }
+}
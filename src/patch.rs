@@ -0,0 +1,531 @@
+//!
+//! Patches over maps, for save games and overlays.
+//!
+//! A MapPatch records what changed on a map, keyed by layer id and object id,
+//! so a save game only needs to store what the player did: tiles mined or
+//! built, objects picked up, spawned, moved or edited, and properties changed.
+//! It serializes with serde, so it can be written next to the save with
+//! serde_json or any other format:
+//!
+//! ```ignore
+//! let patch = MapPatch::between(&level, &played);
+//! let text = serde_json::to_string(&patch)?;
+//!
+//! let mut level = tiled_json::load_map("level.json")?;
+//! let patch: MapPatch = serde_json::from_str(&text)?;
+//! patch.apply(&mut level)?;
+//! ```
+//!
+//! Patches are built by hand, or from a MapDiff with MapPatch::from_diff().
+//! Only changes to the content of a map can be patched.  Changes to its size,
+//! its layers or its tilesets, and properties of tilesets and tiles, are left
+//! out of patches made from a diff.  When the map was resized, so are the
+//! changed cells outside of the old map, which the map a patch is applied to
+//! does not have.
+//!
+//! MapPatch::apply() applies the changes in a fixed order: objects removed,
+//! objects added, objects updated, tiles, properties removed, properties set.
+//! Either all of them are applied or, when one fails, none is.
+//!
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+
+use crate::command::Command;
+use crate::command::PropertyTarget;
+use crate::diff::diff;
+use crate::diff::Change;
+use crate::diff::DiffTarget;
+use crate::diff::MapDiff;
+use crate::diff::ObjectField;
+use crate::map::Map;
+use crate::object::Object;
+use crate::object::Point;
+use crate::object::Text;
+use crate::property::Property;
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A set of changes to apply onto a map.  See the module documentation.
+pub struct MapPatch {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiles: Vec<TilePatch>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_objects: Vec<u32>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_objects: Vec<AddedObject>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub updated_objects: Vec<ObjectUpdate>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_properties: Vec<RemovedProperty>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<PropertyPatch>,
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Set cell (x, y) of a tile layer to gid, flags included.
+pub struct TilePatch {
+    pub layer: u32,
+    pub x: u32,
+    pub y: u32,
+    pub gid: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Add an object on top of an object group.  An object id of 0 is replaced by
+/// the next free one.
+pub struct AddedObject {
+    pub layer: u32,
+    pub object: Object,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Change fields of an object; fields left as None are kept.  The position and
+/// the size only change when both of their values are given, and a gid of 0
+/// turns a tile object into a plain one.  Polygons, polylines and text are
+/// removed by Some(None), written as null in JSON.
+pub struct ObjectUpdate {
+    pub id: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub otype: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub polygon: Option<Option<Vec<Point>>>,
+
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub polyline: Option<Option<Vec<Point>>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ellipse: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub point: Option<bool>,
+
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub text: Option<Option<Text>>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Set a property of the map, a layer or an object.
+pub struct PropertyPatch {
+    pub target: PropertyTarget,
+    pub property: Property,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Remove a property of the map, a layer or an object by name.
+pub struct RemovedProperty {
+    pub target: PropertyTarget,
+    pub name: String,
+}
+
+impl MapPatch {
+    /// Create an empty patch.
+    pub fn new() -> MapPatch {
+        MapPatch::default()
+    }
+
+    /// Build the patch turning old into new.
+    pub fn between(old: &Map, new: &Map) -> MapPatch {
+        MapPatch::from_diff(&diff(old, new))
+    }
+
+    /// Build a patch from the changes of a diff that patches can hold.  See
+    /// the module documentation.
+    pub fn from_diff(diff: &MapDiff) -> MapPatch {
+        let mut patch = MapPatch::new();
+        let mut updates: BTreeMap<u32, ObjectUpdate> = BTreeMap::new();
+        let bounds = diff.changes.iter().find_map(|c| match c {
+            Change::MapResized { old, .. } => Option::Some(*old),
+            _ => Option::None,
+        });
+        let inside = |x: u32, y: u32| bounds.is_none_or(|(w, h)| x < w as u32 && y < h as u32);
+        for change in diff.changes.iter() {
+            match change {
+                Change::TileChanged {
+                    layer, x, y, new, ..
+                } if inside(*x, *y) => patch.tiles.push(TilePatch {
+                    layer: *layer,
+                    x: *x,
+                    y: *y,
+                    gid: *new,
+                }),
+                Change::ObjectRemoved { id, .. } => patch.removed_objects.push(*id),
+                Change::ObjectAdded { layer, object } => patch.added_objects.push(AddedObject {
+                    layer: *layer,
+                    object: object.clone(),
+                }),
+                Change::ObjectMoved { id, new, .. } => {
+                    let update = updates.entry(*id).or_insert_with(|| ObjectUpdate::new(*id));
+                    update.x = Option::Some(new.0);
+                    update.y = Option::Some(new.1);
+                }
+                Change::ObjectChanged { id, new, .. } => updates
+                    .entry(*id)
+                    .or_insert_with(|| ObjectUpdate::new(*id))
+                    .set_field(new),
+                Change::PropertyChanged {
+                    target, name, new, ..
+                } => {
+                    let target = match target {
+                        DiffTarget::Map => PropertyTarget::Map,
+                        DiffTarget::Layer(id) => PropertyTarget::Layer(*id),
+                        DiffTarget::Object(id) => PropertyTarget::Object(*id),
+                        DiffTarget::Tileset(_) | DiffTarget::Tile(_, _) => continue,
+                    };
+                    match new {
+                        Option::Some(value) => patch.properties.push(PropertyPatch {
+                            target,
+                            property: Property {
+                                name: name.clone(),
+                                value: value.clone(),
+                            },
+                        }),
+                        Option::None => patch.removed_properties.push(RemovedProperty {
+                            target,
+                            name: name.clone(),
+                        }),
+                    }
+                }
+                _ => {}
+            }
+        }
+        patch.updated_objects = updates.into_values().collect();
+        patch
+    }
+
+    /// Whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+            && self.removed_objects.is_empty()
+            && self.added_objects.is_empty()
+            && self.updated_objects.is_empty()
+            && self.removed_properties.is_empty()
+            && self.properties.is_empty()
+    }
+
+    /// The patch as commands, in the order they are applied.  Feeding them to
+    /// a History between History::begin() and History::commit() applies the
+    /// patch as one undoable transaction.
+    pub fn commands(&self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        for id in self.removed_objects.iter() {
+            commands.push(Command::RemoveObject { id: *id });
+        }
+        for added in self.added_objects.iter() {
+            commands.push(Command::AddObject {
+                layer: added.layer,
+                index: usize::MAX,
+                object: added.object.clone(),
+            });
+        }
+        for update in self.updated_objects.iter() {
+            if let (Option::Some(x), Option::Some(y)) = (update.x, update.y) {
+                commands.push(Command::MoveObject {
+                    id: update.id,
+                    x,
+                    y,
+                });
+            }
+            for field in update.fields().into_iter() {
+                commands.push(Command::SetObjectField {
+                    id: update.id,
+                    field,
+                });
+            }
+        }
+        let mut tiles: BTreeMap<u32, Vec<(u32, u32, u32)>> = BTreeMap::new();
+        for tile in self.tiles.iter() {
+            tiles
+                .entry(tile.layer)
+                .or_default()
+                .push((tile.x, tile.y, tile.gid));
+        }
+        for (layer, cells) in tiles.into_iter() {
+            commands.push(Command::SetTiles { layer, cells });
+        }
+        for removed in self.removed_properties.iter() {
+            commands.push(Command::RemoveProperty {
+                target: removed.target,
+                name: removed.name.clone(),
+            });
+        }
+        for set in self.properties.iter() {
+            commands.push(Command::SetProperty {
+                target: set.target,
+                property: set.property.clone(),
+            });
+        }
+        commands
+    }
+
+    /// Apply the patch onto a map.  When an error is returned, the map is left
+    /// as it was.
+    pub fn apply(&self, map: &mut Map) -> Result<(), String> {
+        let mut done: Vec<Command> = Vec::new();
+        for command in self.commands().into_iter() {
+            match command.apply(map) {
+                Ok(inverse) => done.push(inverse),
+                Err(e) => {
+                    for inverse in done.into_iter().rev() {
+                        let _ = inverse.apply(map);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ObjectUpdate {
+    /// Create an update of the object with id that changes nothing.
+    pub fn new(id: u32) -> ObjectUpdate {
+        ObjectUpdate {
+            id,
+            ..ObjectUpdate::default()
+        }
+    }
+
+    // Record the new value of a field.
+    fn set_field(&mut self, field: &ObjectField) {
+        match field {
+            ObjectField::Name(s) => self.name = Option::Some(s.clone()),
+            ObjectField::Type(s) => self.otype = Option::Some(s.clone()),
+            ObjectField::Size(w, h) => {
+                self.width = Option::Some(*w);
+                self.height = Option::Some(*h);
+            }
+            ObjectField::Rotation(r) => self.rotation = Option::Some(*r),
+            ObjectField::Visible(v) => self.visible = Option::Some(*v),
+            ObjectField::Gid(g) => self.gid = Option::Some(g.unwrap_or(0)),
+            ObjectField::Polygon(p) => self.polygon = Option::Some(p.clone()),
+            ObjectField::Polyline(p) => self.polyline = Option::Some(p.clone()),
+            ObjectField::Ellipse(e) => self.ellipse = Option::Some(*e),
+            ObjectField::Point(p) => self.point = Option::Some(*p),
+            ObjectField::Text(t) => self.text = Option::Some(t.clone()),
+        }
+    }
+
+    // The fields changed, other than the position.
+    fn fields(&self) -> Vec<ObjectField> {
+        let mut fields = Vec::new();
+        if let Option::Some(ref s) = self.name {
+            fields.push(ObjectField::Name(s.clone()));
+        }
+        if let Option::Some(ref s) = self.otype {
+            fields.push(ObjectField::Type(s.clone()));
+        }
+        if let (Option::Some(w), Option::Some(h)) = (self.width, self.height) {
+            fields.push(ObjectField::Size(w, h));
+        }
+        if let Option::Some(r) = self.rotation {
+            fields.push(ObjectField::Rotation(r));
+        }
+        if let Option::Some(v) = self.visible {
+            fields.push(ObjectField::Visible(v));
+        }
+        if let Option::Some(g) = self.gid {
+            fields.push(ObjectField::Gid(if g == 0 {
+                Option::None
+            } else {
+                Option::Some(g)
+            }));
+        }
+        if let Option::Some(ref p) = self.polygon {
+            fields.push(ObjectField::Polygon(p.clone()));
+        }
+        if let Option::Some(ref p) = self.polyline {
+            fields.push(ObjectField::Polyline(p.clone()));
+        }
+        if let Option::Some(e) = self.ellipse {
+            fields.push(ObjectField::Ellipse(e));
+        }
+        if let Option::Some(p) = self.point {
+            fields.push(ObjectField::Point(p));
+        }
+        if let Option::Some(ref t) = self.text {
+            fields.push(ObjectField::Text(t.clone()));
+        }
+        fields
+    }
+}
+
+// Deserialize a field that is present, even as null, into Some, so that a
+// missing field (None) and null (Some(None)) can be told apart.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Option::Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MapPatch;
+    use crate::builder::MapBuilder;
+    use crate::diff::diff;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+    use crate::object::Object;
+    use crate::object::Point;
+    use crate::object::Text;
+    use crate::property::Property;
+    use crate::property::PropertyValue;
+    use crate::tileset::Tileset;
+
+    // A 4x3 map with a tile layer (id 1), an object group (id 2) holding a
+    // rectangle (id 1), a polygon (id 2) and a point (id 3), and a property.
+    fn level() -> Map {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 4, 3, 16, 16);
+        builder.add_tileset(Tileset::new("base", "base.png", 64, 32, 16, 16));
+        builder.property(Property {
+            name: "title".to_string(),
+            value: PropertyValue::StringV("Caves".to_string()),
+        });
+        let ground = builder.add_layer(Layer::new_tile_layer("ground")).unwrap();
+        builder.layer_mut(ground).unwrap().fill_rect(0, 0, 4, 3, 1);
+        let things = builder
+            .add_layer(Layer::new_object_group("things"))
+            .unwrap();
+        builder.add_object(things, Object::new_rect(0.0, 0.0, 16.0, 16.0));
+        let triangle = vec![
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 16.0, y: 0.0 },
+            Point { x: 0.0, y: 16.0 },
+        ];
+        builder.add_object(things, Object::new_polygon(16.0, 16.0, triangle));
+        builder.add_object(things, Object::new_point(40.0, 8.0));
+        builder.build()
+    }
+
+    fn objects(map: &mut Map) -> &mut Vec<Object> {
+        map.layer_by_id_mut(2)
+            .and_then(|l| l.get_objects_vector_mut())
+            .unwrap()
+    }
+
+    #[test]
+    fn diff_patch_apply_round_trip() {
+        let old = level();
+        let mut new = old.clone();
+        new.layer_by_id_mut(1).unwrap().set_gid(1, 1, 0);
+        new.layer_by_id_mut(1)
+            .unwrap()
+            .set_gid(2, 0, 3 | crate::VERT_FLIP_FLAG);
+        {
+            let objects = objects(&mut new);
+            objects[0].x = 32.0;
+            objects[0].name = "door".to_string();
+            objects[0].ellipse = true;
+            objects[1].polygon = Option::None;
+            objects[2].text = serde_json::from_str::<Text>(r#"{ "text": "Exit" }"#).ok();
+            objects[2].point = false;
+            objects.push(Object::new_tile(5, 48.0, 48.0, 16.0, 16.0));
+            objects.last_mut().unwrap().id = 4;
+        }
+        new.properties[0].value = PropertyValue::StringV("Deep caves".to_string());
+        new.properties.push(Property {
+            name: "dark".to_string(),
+            value: PropertyValue::Bool(true),
+        });
+
+        let patch = MapPatch::between(&old, &new);
+        let text = serde_json::to_string(&patch).unwrap();
+        let patch: MapPatch = serde_json::from_str(&text).unwrap();
+
+        let mut patched = old.clone();
+        patch.apply(&mut patched).unwrap();
+        let left = diff(&patched, &new);
+        assert!(left.is_empty(), "{}", left);
+
+        // Removing an object and its property both come back too.
+        let mut fewer = old.clone();
+        objects(&mut fewer).remove(1);
+        fewer.properties.clear();
+        let mut patched = old.clone();
+        MapPatch::between(&old, &fewer).apply(&mut patched).unwrap();
+        assert!(diff(&patched, &fewer).is_empty());
+    }
+
+    #[test]
+    fn patch_between_resized_maps_applies() {
+        let old = level();
+        let mut new = old.clone();
+        new.resize(6, 3, 0, 0, false);
+        new.layer_by_id_mut(1).unwrap().set_gid(0, 0, 2);
+        new.layer_by_id_mut(1).unwrap().set_gid(5, 2, 2);
+
+        let patch = MapPatch::between(&old, &new);
+        assert_eq!(patch.tiles.len(), 1);
+        let mut patched = old.clone();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(
+            patched.layer_by_id(1).unwrap().get_gid(0, 0),
+            Option::Some(2)
+        );
+    }
+
+    #[test]
+    fn failing_patch_changes_nothing() {
+        let old = level();
+        let mut patch = MapPatch::between(&old, &old);
+        patch.removed_objects.push(1);
+        patch.removed_objects.push(99);
+        let mut patched = old.clone();
+        assert!(patch.apply(&mut patched).is_err());
+        assert!(diff(&old, &patched).is_empty());
+    }
+}
@@ -34,6 +34,7 @@
 
 use crate::color::Color;
 use serde::Deserialize;
+use serde::Serialize;
 
 const TYPE_FILE: &str = "file";
 const TYPE_STRING: &str = "string";
@@ -42,8 +43,8 @@ const TYPE_FLOAT: &str = "float";
 const TYPE_BOOL: &str = "bool";
 const TYPE_COLOR: &str = "color";
 
-#[derive(Deserialize, Clone)]
-#[serde(from = "PropertyLoader")]
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(from = "PropertyLoader", into = "PropertyLoader")]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// The structure defining all properties and how to use them.
 pub struct Property {
@@ -51,7 +52,7 @@ pub struct Property {
     pub value: PropertyValue,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 #[serde(untagged)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// This is the power behind the Property struct.  Each variant describes a 
@@ -71,6 +72,18 @@ pub enum PropertyValue {
     File(String),
 }
 
+impl std::fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyValue::StringV(s) | PropertyValue::File(s) => write!(f, "{:?}", s),
+            PropertyValue::Int(i) => write!(f, "{}", i),
+            PropertyValue::Float(x) => write!(f, "{}", x),
+            PropertyValue::Bool(b) => write!(f, "{}", b),
+            PropertyValue::Color(c) => write!(f, "{}", c),
+        }
+    }
+}
+
 impl Property {
     /// Get the name of the property.  Returns a simple string.
     pub fn name(&self) -> &String {
@@ -181,7 +194,7 @@ pub trait HasProperty {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct PropertyLoader {
     name: String,
     #[serde(rename = "type")]
@@ -189,6 +202,16 @@ struct PropertyLoader {
     value: PropertyValue, // based on type
 }
 
+impl From<Property> for PropertyLoader {
+    fn from(p: Property) -> Self {
+        PropertyLoader {
+            ptype: p.type_as_string().to_string(),
+            name: p.name,
+            value: p.value,
+        }
+    }
+}
+
 impl From<PropertyLoader> for Property {
     fn from(pl: PropertyLoader) -> Self {
         let v = match pl.value {
@@ -0,0 +1,328 @@
+//!
+//! Flood fill and connected-region labeling over the cells of a tile layer.
+//!
+//! Designers often mark rooms simply by painting floor tiles.  Regions finds
+//! those rooms: every group of connected cells becomes a Region with an id, its
+//! list of cells, its bounding box and the ids of the regions it touches.
+//!
+//! Which cells belong together is decided per gid.  Regions::label() takes a
+//! predicate and groups every connected cell for which it returns true.
+//! Regions::label_by() takes a key instead: cells with no key are left out and
+//! neighbouring cells only connect when their keys are equal, so rooms painted
+//! with different floors (or doors painted with a door tile) become separate,
+//! adjacent regions.
+//!
+//! Cells connect through tiled_json::Neighborhood, following the orientation
+//! of the map: 4-way or 8-way on orthogonal and isometric maps, 6-way on
+//! hexagonal maps.
+//!
+
+use std::collections::BTreeSet;
+
+use crate::grid::Neighborhood;
+use crate::layer::Layer;
+use crate::map::Map;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The connected regions of a tile layer.  labels is indexed like tile layer
+/// data (y * width + x) and holds the id of the region of each cell.
+pub struct Regions {
+    pub width: u32,
+    pub height: u32,
+    pub labels: Vec<Option<usize>>,
+    pub regions: Vec<Region>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// One connected region.  x, y, width and height describe its bounding box in
+/// cells, and neighbors lists the ids of the regions touching it.
+pub struct Region {
+    pub id: usize,
+    pub cells: Vec<(u32, u32)>,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub neighbors: Vec<usize>,
+}
+
+impl Region {
+    /// The id of the region, which is also its index in Regions::regions().
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Borrow the cells of the region, row by row.
+    pub fn cells(&self) -> &Vec<(u32, u32)> {
+        &self.cells
+    }
+
+    /// Number of cells in the region.
+    pub fn size(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Bounding box of the region in cells: (x, y, width, height).
+    pub fn bounds(&self) -> (u32, u32, u32, u32) {
+        (self.x, self.y, self.width, self.height)
+    }
+
+    /// Borrow the ids of the regions touching this one.
+    pub fn neighbors(&self) -> &Vec<usize> {
+        &self.neighbors
+    }
+}
+
+impl Regions {
+    /// Label the connected regions of cells for which connects returns true.
+    ///
+    /// connects receives each gid as stored in the layer data (flags included,
+    /// 0 for empty cells).  diagonal selects 8-way connectivity on orthogonal and
+    /// isometric maps.  Since neighbouring cells always join, regions found this
+    /// way never touch each other.
+    pub fn label<F>(map: &Map, layer: &Layer, diagonal: bool, connects: F) -> Regions
+    where
+        F: Fn(u32) -> bool,
+    {
+        Regions::label_by(map, layer, diagonal, |gid| {
+            if connects(gid) {
+                Option::Some(())
+            } else {
+                Option::None
+            }
+        })
+    }
+
+    /// Label connected regions of cells sharing the same key.
+    ///
+    /// key receives each gid as stored in the layer data.  Cells without a key
+    /// belong to no region; neighbouring cells with equal keys join the same
+    /// region, and regions with different keys touching each other are listed
+    /// as neighbors.
+    pub fn label_by<K, F>(map: &Map, layer: &Layer, diagonal: bool, key: F) -> Regions
+    where
+        K: PartialEq,
+        F: Fn(u32) -> Option<K>,
+    {
+        let width = map.width as u32;
+        let height = map.height as u32;
+        let size = (width * height) as usize;
+        let neighborhood = Neighborhood::from_map(map, diagonal);
+
+        let keys: Vec<Option<K>> = match layer.get_data() {
+            Option::Some(data) => (0..size)
+                .map(|i| data.get(i).and_then(|gid| key(*gid)))
+                .collect(),
+            Option::None => (0..size).map(|_| Option::None).collect(),
+        };
+
+        // Regions never share cells, so one visited set serves every fill.
+        let mut seen = vec![false; size];
+        let mut labels: Vec<Option<usize>> = vec![Option::None; size];
+        let mut regions: Vec<Region> = Vec::new();
+        for start in 0..size {
+            if keys[start].is_none() || seen[start] {
+                continue;
+            }
+            let id = regions.len();
+            let mut cells = fill(width, height, neighborhood, start, &mut seen, |a, b| {
+                keys[b].is_some() && keys[a] == keys[b]
+            });
+            cells.sort_unstable();
+            for c in cells.iter() {
+                labels[*c] = Option::Some(id);
+            }
+
+            let coords: Vec<(u32, u32)> = cells
+                .iter()
+                .map(|c| (*c as u32 % width, *c as u32 / width))
+                .collect();
+            let min_x = coords.iter().map(|c| c.0).min().unwrap_or(0);
+            let max_x = coords.iter().map(|c| c.0).max().unwrap_or(0);
+            let min_y = coords.iter().map(|c| c.1).min().unwrap_or(0);
+            let max_y = coords.iter().map(|c| c.1).max().unwrap_or(0);
+            regions.push(Region {
+                id,
+                cells: coords,
+                x: min_x,
+                y: min_y,
+                width: max_x - min_x + 1,
+                height: max_y - min_y + 1,
+                neighbors: Vec::new(),
+            });
+        }
+
+        for region in regions.iter_mut() {
+            let mut touching = BTreeSet::new();
+            for (x, y) in region.cells.iter() {
+                for (nx, ny) in neighborhood.neighbors(*x as i32, *y as i32) {
+                    if nx < 0 || ny < 0 || nx as u32 >= width || ny as u32 >= height {
+                        continue;
+                    }
+                    if let Option::Some(other) = labels[(ny as u32 * width + nx as u32) as usize] {
+                        if other != region.id {
+                            touching.insert(other);
+                        }
+                    }
+                }
+            }
+            region.neighbors = touching.into_iter().collect();
+        }
+
+        Regions {
+            width,
+            height,
+            labels,
+            regions,
+        }
+    }
+
+    /// Borrow every region, indexed by id.
+    pub fn regions(&self) -> &Vec<Region> {
+        &self.regions
+    }
+
+    /// Get a region by id.
+    pub fn region(&self, id: usize) -> Option<&Region> {
+        self.regions.get(id)
+    }
+
+    /// The id of the region holding the cell, if any.
+    pub fn label_at(&self, x: u32, y: u32) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        self.labels[(y * self.width + x) as usize]
+    }
+
+    /// The region holding the cell, if any.
+    pub fn region_at(&self, x: u32, y: u32) -> Option<&Region> {
+        self.label_at(x, y).and_then(|id| self.regions.get(id))
+    }
+}
+
+/// Flood fill a tile layer from start, returning every cell connected to it for
+/// which connects returns true, row by row.
+///
+/// connects receives each gid as stored in the layer data.  The vector is empty
+/// when start is outside the map or does not connect itself.  diagonal selects
+/// 8-way connectivity on orthogonal and isometric maps.
+pub fn flood_fill<F>(
+    map: &Map,
+    layer: &Layer,
+    start: (u32, u32),
+    diagonal: bool,
+    connects: F,
+) -> Vec<(u32, u32)>
+where
+    F: Fn(u32) -> bool,
+{
+    let width = map.width as u32;
+    let height = map.height as u32;
+    let data = match layer.get_data() {
+        Option::Some(d) => d,
+        Option::None => return Vec::new(),
+    };
+    if start.0 >= width || start.1 >= height {
+        return Vec::new();
+    }
+    let inside = |i: usize| data.get(i).is_some_and(|gid| connects(*gid));
+    let first = (start.1 * width + start.0) as usize;
+    if !inside(first) {
+        return Vec::new();
+    }
+
+    let neighborhood = Neighborhood::from_map(map, diagonal);
+    let mut seen = vec![false; (width * height) as usize];
+    let mut cells = fill(width, height, neighborhood, first, &mut seen, |_, b| {
+        inside(b)
+    });
+    cells.sort_unstable();
+    cells
+        .into_iter()
+        .map(|c| (c as u32 % width, c as u32 / width))
+        .collect()
+}
+
+// Collect the indices of every cell reachable from start, stepping from a to b
+// only when joins(a, b) is true.  Cells marked in seen are skipped, and the
+// cells collected are marked.
+fn fill<J>(
+    width: u32,
+    height: u32,
+    neighborhood: Neighborhood,
+    start: usize,
+    seen: &mut [bool],
+    joins: J,
+) -> Vec<usize>
+where
+    J: Fn(usize, usize) -> bool,
+{
+    let mut stack = vec![start];
+    let mut cells = Vec::new();
+    seen[start] = true;
+    while let Option::Some(i) = stack.pop() {
+        cells.push(i);
+        let (x, y) = ((i as u32 % width) as i32, (i as u32 / width) as i32);
+        for (nx, ny) in neighborhood.neighbors(x, y) {
+            if nx < 0 || ny < 0 || nx as u32 >= width || ny as u32 >= height {
+                continue;
+            }
+            let n = (ny as u32 * width + nx as u32) as usize;
+            if !seen[n] && joins(i, n) {
+                seen[n] = true;
+                stack.push(n);
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regions;
+    use crate::builder::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+
+    // A size by size orthogonal map painted as a checkerboard of gids 1 and 2.
+    fn checkerboard(size: u16) -> Map {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, size, size, 16, 16);
+        let id = builder.add_layer(Layer::new_tile_layer("floor")).unwrap();
+        let data = builder.layer_mut(id).unwrap().get_data_mut().unwrap();
+        for (i, gid) in data.iter_mut().enumerate() {
+            let (x, y) = (i % size as usize, i / size as usize);
+            *gid = 1 + ((x + y) % 2) as u32;
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn label_by_checkerboard_without_diagonals() {
+        let map = checkerboard(256);
+        let regions = Regions::label_by(&map, &map.layers[0], false, Option::Some);
+
+        assert_eq!(regions.regions().len(), 256 * 256);
+        let corner = regions.region_at(0, 0).unwrap();
+        assert_eq!(corner.cells(), &vec![(0, 0)]);
+        assert_eq!(corner.neighbors.len(), 2);
+        let inner = regions.region_at(10, 10).unwrap();
+        assert_eq!(inner.neighbors.len(), 4);
+        assert!(inner.neighbors.contains(&regions.label_at(11, 10).unwrap()));
+    }
+
+    #[test]
+    fn label_by_checkerboard_with_diagonals() {
+        let map = checkerboard(8);
+        let regions = Regions::label_by(&map, &map.layers[0], true, Option::Some);
+
+        assert_eq!(regions.regions().len(), 2);
+        let black = regions.region_at(0, 0).unwrap();
+        assert_eq!(black.cells().len(), 32);
+        assert_eq!((black.width, black.height), (8, 8));
+        assert_eq!(black.neighbors, vec![regions.label_at(1, 0).unwrap()]);
+    }
+}
@@ -0,0 +1,349 @@
+//!
+//! Renumbering gids when tilesets change.
+//!
+//! A gid is the firstgid of a tileset plus the local id of a tile in it, so
+//! reordering, replacing or removing a tileset changes the gids of every tile
+//! after it.  The methods here change the tilesets of a map and rewrite every
+//! gid to match, in tile layers and tile objects, nested groups included, while
+//! keeping flip flags:
+//! - Map::move_tileset() reorders tilesets.
+//! - Map::replace_tileset() swaps a tileset for another, moving its tiles to new
+//!   local ids through a mapping table.
+//! - Map::remove_tileset() and Map::remove_unused_tilesets() drop tilesets.
+//! - Map::compact_tilesets() only closes the gaps between gid ranges.
+//!
+//! Each of them leaves the tilesets in their order with compact gid ranges: the
+//! first starts at gid 1 and each next one right after the last tile of the
+//! previous one.  Cells whose tile no longer exists are cleared, and tile
+//! objects whose tile no longer exists are removed.
+//!
+//! Map::map_gids() is the building block for other rewrites of gids.
+//!
+
+use std::collections::HashMap;
+
+use crate::layer::Layer;
+use crate::layer::LayerDataContainer;
+use crate::map::Map;
+use crate::tileset::Tileset;
+
+impl Map {
+    /// Apply f to every gid of the map other than 0, in tile layers and tile
+    /// objects, nested ones included.
+    pub fn map_gids<F>(&mut self, f: F)
+    where
+        F: Fn(u32) -> u32,
+    {
+        rewrite_gids(&mut self.layers, &|gid| Option::Some(f(gid)));
+    }
+
+    /// Move the tileset at index from to index to.
+    pub fn move_tileset(&mut self, from: usize, to: usize) -> Result<(), String> {
+        let count = self.tilesets.len();
+        if from >= count || to >= count {
+            return Err(format!(
+                "Cannot move tileset {} to {}: the map has {} tilesets.",
+                from, to, count
+            ));
+        }
+        let mut order: Vec<Entry> = self.entries();
+        let moved = order.remove(from);
+        order.insert(to, moved);
+        self.rebuild(order, |_, local| Option::Some(local));
+        Ok(())
+    }
+
+    /// Replace the tileset at index with tileset.  The tile with local id l in
+    /// the old tileset becomes the tile mapping[l] of the new one, or keeps its
+    /// local id when l is not in mapping.  Tiles beyond the new tileset are
+    /// cleared.
+    pub fn replace_tileset(
+        &mut self,
+        index: usize,
+        tileset: Tileset,
+        mapping: &HashMap<u32, u32>,
+    ) -> Result<(), String> {
+        if index >= self.tilesets.len() {
+            return Err(format!("The map has no tileset {}.", index));
+        }
+        let mut order = self.entries();
+        order[index].tileset = tileset;
+        self.rebuild(order, |i, local| {
+            if i == index {
+                Option::Some(mapping.get(&local).copied().unwrap_or(local))
+            } else {
+                Option::Some(local)
+            }
+        });
+        Ok(())
+    }
+
+    /// Remove the tileset at index, returning it.  Its tiles are cleared from
+    /// the map.
+    pub fn remove_tileset(&mut self, index: usize) -> Option<Tileset> {
+        if index >= self.tilesets.len() {
+            return Option::None;
+        }
+        let mut order = self.entries();
+        let removed = order.remove(index).tileset;
+        self.rebuild(order, |_, local| Option::Some(local));
+        Option::Some(removed)
+    }
+
+    /// Remove the tilesets none of whose tiles are used by the map, returning
+    /// them.
+    pub fn remove_unused_tilesets(&mut self) -> Vec<Tileset> {
+        let mut used = vec![false; self.tilesets.len()];
+        collect_gids(&self.layers, &mut |gid| {
+            if let Option::Some((i, _)) = locate(&self.tilesets, gid) {
+                used[i] = true;
+            }
+        });
+        let (kept, removed): (Vec<Entry>, Vec<Entry>) =
+            self.entries().into_iter().partition(|e| used[e.index]);
+        self.rebuild(kept, |_, local| Option::Some(local));
+        removed.into_iter().map(|e| e.tileset).collect()
+    }
+
+    /// Give the tilesets compact gid ranges, keeping their order.
+    pub fn compact_tilesets(&mut self) {
+        let order = self.entries();
+        self.rebuild(order, |_, local| Option::Some(local));
+    }
+
+    // The tilesets of the map along with their current index.
+    fn entries(&self) -> Vec<Entry> {
+        self.tilesets
+            .iter()
+            .enumerate()
+            .map(|(index, ts)| Entry {
+                index,
+                tileset: ts.clone(),
+            })
+            .collect()
+    }
+
+    // Make order the tilesets of the map with compact firstgids.  Each tile is
+    // found by the index of its old tileset and its local id, and local gives
+    // its new local id in the same tileset, or None to clear it.
+    fn rebuild<F>(&mut self, mut order: Vec<Entry>, local: F)
+    where
+        F: Fn(usize, u32) -> Option<u32>,
+    {
+        let mut next = 1;
+        let mut placed: HashMap<usize, (u32, u32)> = HashMap::new();
+        for entry in order.iter_mut() {
+            entry.tileset.firstgid = next;
+            placed.insert(entry.index, (next, entry.tileset.gid_span()));
+            next += entry.tileset.gid_span();
+        }
+
+        let old = std::mem::take(&mut self.tilesets);
+        rewrite_gids(&mut self.layers, &|gid| {
+            let (index, id) = locate(&old, gid)?;
+            let (firstgid, span) = placed.get(&index)?;
+            let id = local(index, id).filter(|id| id < span)?;
+            Option::Some((gid & !crate::gid_without_flags(gid)) | (firstgid + id))
+        });
+        self.tilesets = order.into_iter().map(|e| e.tileset).collect();
+    }
+}
+
+// A tileset and the index it had before a change.
+struct Entry {
+    index: usize,
+    tileset: Tileset,
+}
+
+// The index of the tileset holding a gid and the local id of its tile.
+fn locate(tilesets: &[Tileset], gid: u32) -> Option<(usize, u32)> {
+    let raw = crate::gid_without_flags(gid);
+    tilesets
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, ts)| ts.firstgid <= raw)
+        .filter(|(_, ts)| raw - ts.firstgid < ts.gid_span())
+        .map(|(i, ts)| (i, raw - ts.firstgid))
+}
+
+// Replace every gid other than 0 with f(gid); cells for which f gives None are
+// cleared and tile objects removed.
+fn rewrite_gids<F>(layers: &mut [Layer], f: &F)
+where
+    F: Fn(u32) -> Option<u32>,
+{
+    for layer in layers.iter_mut() {
+        match layer.layerdata {
+            LayerDataContainer::TileLayer { ref mut data } => {
+                for gid in data
+                    .iter_mut()
+                    .filter(|g| crate::gid_without_flags(**g) != 0)
+                {
+                    *gid = f(*gid).unwrap_or(0);
+                }
+            }
+            LayerDataContainer::ObjectGroup {
+                ref mut objects, ..
+            } => {
+                objects.retain_mut(|object| {
+                    match object.gid.filter(|g| crate::gid_without_flags(*g) != 0) {
+                        Option::Some(gid) => match f(gid) {
+                            Option::Some(new) => {
+                                object.gid = Option::Some(new);
+                                true
+                            }
+                            Option::None => false,
+                        },
+                        Option::None => true,
+                    }
+                });
+            }
+            LayerDataContainer::Group { ref mut layers } => rewrite_gids(layers, f),
+            LayerDataContainer::ImageLayer { .. } => {}
+        }
+    }
+}
+
+// Call f with every gid other than 0.
+fn collect_gids<F>(layers: &[Layer], f: &mut F)
+where
+    F: FnMut(u32),
+{
+    for layer in layers.iter() {
+        if let Option::Some(data) = layer.get_data() {
+            for gid in data.iter().filter(|g| crate::gid_without_flags(**g) != 0) {
+                f(*gid);
+            }
+        }
+        if let Option::Some(objects) = layer.get_objects_vector() {
+            for gid in objects.iter().filter_map(|o| o.gid) {
+                if crate::gid_without_flags(gid) != 0 {
+                    f(gid);
+                }
+            }
+        }
+        if let Option::Some(children) = layer.get_layers() {
+            collect_gids(children, f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::builder::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+    use crate::object::Object;
+    use crate::tileset::Tileset;
+
+    // A 4x1 map with tilesets a, b and c of two tiles each, a tile layer whose
+    // cells hold gids, and an object group with a tile object of gid object.
+    fn map_with(gids: [u32; 4], object: u32) -> Map {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 4, 1, 16, 16);
+        for name in ["a", "b", "c"].iter() {
+            builder.add_tileset(Tileset::new(name, "tiles.png", 32, 16, 16, 16));
+        }
+        let layer = builder.add_layer(Layer::new_tile_layer("ground")).unwrap();
+        for (x, gid) in gids.iter().enumerate() {
+            builder.layer_mut(layer).unwrap().set_gid(x as u32, 0, *gid);
+        }
+        let group = builder
+            .add_layer(Layer::new_object_group("things"))
+            .unwrap();
+        builder.add_object(group, Object::new_tile(object, 0.0, 16.0, 16.0, 16.0));
+        builder.build()
+    }
+
+    fn gids(map: &Map) -> Vec<u32> {
+        map.layer_by_name("ground")
+            .unwrap()
+            .get_data()
+            .unwrap()
+            .clone()
+    }
+
+    fn object_gids(map: &Map) -> Vec<Option<u32>> {
+        let things = map.layer_by_name("things").unwrap();
+        things
+            .get_objects_vector()
+            .unwrap()
+            .iter()
+            .map(|o| o.gid)
+            .collect()
+    }
+
+    #[test]
+    fn compact_closes_gaps_and_keeps_flags() {
+        let flipped = crate::VERT_FLIP_FLAG | crate::DIAG_FLIP_FLAG;
+        let mut map = map_with([2, flipped | 4, 6, 0], 5);
+        // Open gaps before b and c, moving their gids along.
+        map.tilesets[1].firstgid = 10;
+        map.tilesets[2].firstgid = 20;
+        map.map_gids(|gid| match crate::gid_without_flags(gid) {
+            3..=4 => gid + 7,
+            5..=6 => gid + 15,
+            _ => gid,
+        });
+        assert_eq!(gids(&map), vec![2, flipped | 11, 21, 0]);
+
+        map.compact_tilesets();
+        let firstgids: Vec<u32> = map.tilesets.iter().map(|ts| ts.firstgid).collect();
+        assert_eq!(firstgids, vec![1, 3, 5]);
+        assert_eq!(gids(&map), vec![2, flipped | 4, 6, 0]);
+        assert_eq!(object_gids(&map), vec![Option::Some(5)]);
+    }
+
+    #[test]
+    fn remove_tileset_clears_its_tiles() {
+        let mut map = map_with([1, 3, crate::HORZ_FLIP_FLAG | 6, 0], 4);
+        let removed = map.remove_tileset(1).unwrap();
+        assert_eq!(removed.name, "b");
+
+        let names: Vec<&str> = map.tilesets.iter().map(|ts| ts.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "c"]);
+        assert_eq!(map.tilesets[1].firstgid, 3);
+        assert_eq!(gids(&map), vec![1, 0, crate::HORZ_FLIP_FLAG | 4, 0]);
+        assert!(object_gids(&map).is_empty());
+    }
+
+    #[test]
+    fn move_and_remove_unused_tilesets() {
+        let mut map = map_with([1, 5, 0, 0], 6);
+        map.move_tileset(2, 0).unwrap();
+        assert_eq!(map.tilesets[0].name, "c");
+        assert_eq!(gids(&map), vec![3, 1, 0, 0]);
+        assert_eq!(object_gids(&map), vec![Option::Some(2)]);
+
+        let removed = map.remove_unused_tilesets();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].name, "b");
+        assert_eq!(gids(&map), vec![3, 1, 0, 0]);
+        assert!(map.move_tileset(0, 2).is_err());
+    }
+
+    #[test]
+    fn replace_tileset_maps_local_ids() {
+        let flipped = crate::HORZ_FLIP_FLAG;
+        let mut map = map_with([1, 3, flipped | 4, 6], 4);
+        // d has a single tile: b's tile 1 moves onto it, and tile 0 is sent
+        // past its end.
+        let mut mapping = HashMap::new();
+        mapping.insert(0, 1);
+        mapping.insert(1, 0);
+        let d = Tileset::new("d", "d.png", 16, 16, 16, 16);
+        map.replace_tileset(1, d, &mapping).unwrap();
+
+        let firstgids: Vec<u32> = map.tilesets.iter().map(|ts| ts.firstgid).collect();
+        assert_eq!(firstgids, vec![1, 3, 4]);
+        assert_eq!(map.tilesets[1].name, "d");
+        assert_eq!(gids(&map), vec![1, 0, flipped | 3, 5]);
+        assert_eq!(object_gids(&map), vec![Option::Some(3)]);
+
+        let e = Tileset::new("e", "e.png", 16, 16, 16, 16);
+        assert!(map.replace_tileset(3, e, &mapping).is_err());
+    }
+}
@@ -0,0 +1,733 @@
+//!
+//! A small software renderer drawing a whole map into an RGBA image.
+//!
+//! This module is only available with the `render` feature.  It is meant for
+//! thumbnails, level-select previews and golden-image tests, not for games:
+//! everything is drawn on the CPU, pixel by pixel, with nearest-neighbour
+//! sampling.
+//!
+//! ```render_map(map: &Map, file: &str)``` loads the images of every tileset
+//! and image layer, relative to the directory of the map file, and composites
+//! every visible layer on top of the map background color:
+//! - Tile layers, in the render order of the map, with flipped tiles.
+//! - Image layers.
+//! - Object groups, in the draw order of the group: tile objects are drawn
+//!   with their tile, rectangles, ellipses and polygons are filled and
+//!   outlined, polylines and points are outlined.  Text is not drawn.
+//!
+//! Layer opacity, visibility, offsets and tint colors are honored, including
+//! those of groups, as are the transparent colors of tilesets and image layers
+//! and the repetition of image layers.  Parallax factors are not: the map is
+//! drawn as seen from its parallax origin.  Every MapOrientation is supported.
+//!
+//! Errors follow load_map(): a missing or undecodable image gives a
+//! std::io::Error, as does a map too large to fit in memory as one image.
+//!
+
+use std::collections::HashMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::color::Color;
+use crate::draworder::objects_in_draw_order;
+use crate::layer::Layer;
+use crate::layer::LayerDataContainer;
+use crate::layer::LayerTransform;
+use crate::map::Map;
+use crate::map::MapOrientation;
+use crate::object::Object;
+use crate::object::Point;
+
+// The color shapes are drawn with, Tiled's default object color.
+const SHAPE_COLOR: [u8; 4] = [160, 160, 164, 255];
+// The opacity of shape fills.
+const SHAPE_FILL_OPACITY: f64 = 0.25;
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// An RGBA image: width * height pixels of four bytes (red, green, blue and
+/// alpha), row by row from the top-left corner.
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// Create a transparent canvas.  An error is returned when the canvas has
+    /// more bytes than can be addressed.
+    pub fn new(width: u32, height: u32) -> Result<Canvas, Error> {
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("A canvas of {}x{} pixels is too large.", width, height),
+                )
+            })?;
+        Ok(Canvas {
+            width,
+            height,
+            pixels: vec![0; size],
+        })
+    }
+
+    /// Width of the canvas in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the canvas in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Borrow the raw RGBA bytes.
+    pub fn pixels(&self) -> &Vec<u8> {
+        &self.pixels
+    }
+
+    /// Get the color of a pixel, or None outside of the canvas.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        let i = self.index(x, y);
+        Option::Some(Color {
+            r: self.pixels[i],
+            g: self.pixels[i + 1],
+            b: self.pixels[i + 2],
+            a: self.pixels[i + 3],
+        })
+    }
+
+    /// Blend an RGBA color over a pixel with the given opacity.  Pixels outside
+    /// of the canvas are ignored.
+    pub fn blend_pixel(&mut self, x: i64, y: i64, rgba: [u8; 4], opacity: f64) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let a = rgba[3] as f64 / 255.0 * opacity;
+        if a <= 0.0 {
+            return;
+        }
+        let i = self.index(x as u32, y as u32);
+        let dst_a = self.pixels[i + 3] as f64 / 255.0;
+        let out_a = a + dst_a * (1.0 - a);
+        for (dst, src) in self.pixels[i..i + 3].iter_mut().zip(rgba.iter()) {
+            let blended = (*src as f64 * a + *dst as f64 * dst_a * (1.0 - a)) / out_a;
+            *dst = blended.round() as u8;
+        }
+        self.pixels[i + 3] = (out_a * 255.0).round() as u8;
+    }
+
+    // The index of the first byte of a pixel inside the canvas.
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    /// Write the canvas to a PNG file.
+    pub fn save_png(&self, file: &str) -> Result<(), Error> {
+        image::save_buffer(
+            file,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| Error::other(e.to_string()))
+    }
+}
+
+/// Render a map loaded from file, finding its images relative to the directory
+/// of that file.
+/// ```ignore
+/// let map = tiled_json::load_map("map1.json").unwrap();
+/// let canvas = tiled_json::render_map(&map, "map1.json").unwrap();
+/// canvas.save_png("map1.png").unwrap();
+/// ```
+pub fn render_map(map: &Map, file: &str) -> Result<Canvas, Error> {
+    let dir = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+    Renderer::new(map, dir)?.render()
+}
+
+/// Renderer holds a map together with its decoded images, so the same map can
+/// be rendered several times without loading them again.
+pub struct Renderer<'a> {
+    map: &'a Map,
+    images: HashMap<(String, String), Bitmap>,
+}
+
+// A rectangle of a bitmap: x, y, width and height in pixels.
+type SourceRect = (u32, u32, u32, u32);
+
+// A decoded image.
+struct Bitmap {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl<'a> Renderer<'a> {
+    /// Load every image used by the map, relative to the directory dir.
+    pub fn new<P: AsRef<Path>>(map: &'a Map, dir: P) -> Result<Renderer<'a>, Error> {
+        let dir = dir.as_ref();
+        let mut renderer = Renderer {
+            map,
+            images: HashMap::new(),
+        };
+        for ts in map.tilesets.iter() {
+            if !ts.image.is_empty() {
+                renderer.load(dir, &ts.image, ts.transparentcolor)?;
+            }
+            for tile in ts.tiles.iter() {
+                if let Option::Some(ref image) = tile.image {
+                    renderer.load(dir, image, ts.transparentcolor)?;
+                }
+            }
+        }
+        renderer.load_layers(dir, &map.layers)?;
+        Ok(renderer)
+    }
+
+    /// The average color of the image of a tile, weighted by opacity, or None
+    /// when the tile has no image.  Flags on the gid are ignored.
+    pub fn average_color(&self, gid: u32) -> Option<Color> {
+        let (bmp, src) = self.tile_source(gid)?;
+        let mut sum = [0u64; 4];
+        let mut count = 0u64;
+        for y in src.1..(src.1 + src.3).min(bmp.height) {
+            for x in src.0..(src.0 + src.2).min(bmp.width) {
+                let i = (y as usize * bmp.width as usize + x as usize) * 4;
+                let a = bmp.pixels[i + 3] as u64;
+                for (total, px) in sum.iter_mut().zip(bmp.pixels[i..i + 3].iter()) {
+                    *total += *px as u64 * a;
+                }
+                sum[3] += a;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return Option::None;
+        }
+        let channel = |c: usize| sum[c].checked_div(sum[3]).unwrap_or(0) as u8;
+        Option::Some(Color {
+            r: channel(0),
+            g: channel(1),
+            b: channel(2),
+            a: (sum[3] / count) as u8,
+        })
+    }
+
+    /// Draw the map into a new canvas the size of the map.  See Canvas::new()
+    /// for errors.
+    pub fn render(&self) -> Result<Canvas, Error> {
+        let (width, height) = self.map.pixel_size();
+        let mut canvas = Canvas::new(width, height)?;
+        if let Option::Some(bg) = self.map.backgroundcolor {
+            for px in canvas.pixels.chunks_mut(4) {
+                px.copy_from_slice(&[bg.r, bg.g, bg.b, bg.a]);
+            }
+        }
+        self.draw_layers(&mut canvas, &self.map.layers, LayerTransform::default());
+        Ok(canvas)
+    }
+
+    fn load_layers(&mut self, dir: &Path, layers: &[Layer]) -> Result<(), Error> {
+        for layer in layers.iter() {
+            match layer.layerdata {
+                LayerDataContainer::ImageLayer {
+                    ref image,
+                    transparentcolor,
+                } if !image.is_empty() => self.load(dir, image, transparentcolor)?,
+                LayerDataContainer::Group { ref layers } => self.load_layers(dir, layers)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, dir: &Path, image: &str, transparent: Option<Color>) -> Result<(), Error> {
+        let key = image_key(image, transparent);
+        if self.images.contains_key(&key) {
+            return Ok(());
+        }
+        let path: PathBuf = dir.join(image);
+        let decoded = image::open(&path)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?
+            .to_rgba8();
+        let (width, height) = decoded.dimensions();
+        let mut pixels = decoded.into_raw();
+        if let Option::Some(tc) = transparent {
+            for px in pixels.chunks_mut(4) {
+                if px[0] == tc.r && px[1] == tc.g && px[2] == tc.b {
+                    px[3] = 0;
+                }
+            }
+        }
+        self.images.insert(
+            key,
+            Bitmap {
+                width,
+                height,
+                pixels,
+            },
+        );
+        Ok(())
+    }
+
+    fn draw_layers(&self, canvas: &mut Canvas, layers: &[Layer], parent: LayerTransform) {
+        for layer in layers.iter() {
+            let state = parent.combine(layer);
+            if !state.visible {
+                continue;
+            }
+            match layer.layerdata {
+                LayerDataContainer::TileLayer { ref data } => self.draw_tiles(canvas, data, &state),
+                LayerDataContainer::ObjectGroup { .. } => {
+                    let objects = objects_in_draw_order(self.map, layer);
+                    self.draw_objects(canvas, &objects, &state)
+                }
+                LayerDataContainer::ImageLayer {
+                    ref image,
+                    transparentcolor,
+                } => {
+                    if let Option::Some(bmp) = self.images.get(&image_key(image, transparentcolor))
+                    {
+                        self.draw_image_layer(canvas, bmp, layer, &state);
+                    }
+                }
+                LayerDataContainer::Group { ref layers } => self.draw_layers(canvas, layers, state),
+            }
+        }
+    }
+
+    fn draw_image_layer(
+        &self,
+        canvas: &mut Canvas,
+        bmp: &Bitmap,
+        layer: &Layer,
+        state: &LayerTransform,
+    ) {
+        let (w, h) = (bmp.width as f64, bmp.height as f64);
+        // Repeated images start at the copy touching the left or top edge of the
+        // canvas and cover it entirely.
+        let (first_x, count_x) = if layer.repeatx {
+            let first = state.offsetx - (state.offsetx / w).ceil() * w;
+            (first, ((canvas.width as f64 - first) / w).ceil() as u32)
+        } else {
+            (state.offsetx, 1)
+        };
+        let (first_y, count_y) = if layer.repeaty {
+            let first = state.offsety - (state.offsety / h).ceil() * h;
+            (first, ((canvas.height as f64 - first) / h).ceil() as u32)
+        } else {
+            (state.offsety, 1)
+        };
+        let src = (0, 0, bmp.width, bmp.height);
+        for j in 0..count_y {
+            for i in 0..count_x {
+                let dest = (first_x + i as f64 * w, first_y + j as f64 * h, w, h);
+                blit(canvas, bmp, src, dest, 0, state, Option::None);
+            }
+        }
+    }
+
+    fn draw_tiles(&self, canvas: &mut Canvas, data: &[u32], state: &LayerTransform) {
+        let width = self.map.width as u32;
+        let cell_height = self.map.tileheight as f64;
+        for (x, y) in self.map.cells_in_render_order() {
+            let gid = match data.get((y * width + x) as usize) {
+                Option::Some(g) if crate::gid_without_flags(*g) != 0 => *g,
+                _ => continue,
+            };
+            let (bmp, src) = match self.tile_source(gid) {
+                Option::Some(s) => s,
+                Option::None => continue,
+            };
+            let (px, py) = self.map.tile_to_pixel(x, y);
+            let (off_x, off_y) = self.tile_offset(gid);
+            // A diagonal flip swaps the width and height of the tile; it still
+            // rests on the bottom of the cell.
+            let (w, h) = if crate::gid_flipped_hvd(gid).2 {
+                (src.3 as f64, src.2 as f64)
+            } else {
+                (src.2 as f64, src.3 as f64)
+            };
+            let dest = (
+                px + state.offsetx + off_x,
+                py + cell_height - h + state.offsety + off_y,
+                w,
+                h,
+            );
+            blit(canvas, bmp, src, dest, gid, state, Option::None);
+        }
+    }
+
+    fn draw_objects(&self, canvas: &mut Canvas, objects: &[&Object], state: &LayerTransform) {
+        for obj in objects.iter() {
+            if !obj.visible {
+                continue;
+            }
+            if let Option::Some(gid) = obj.gid {
+                let (bmp, src) = match self.tile_source(gid) {
+                    Option::Some(s) => s,
+                    Option::None => continue,
+                };
+                let (off_x, off_y) = self.tile_offset(gid);
+                let anchor = project(self.map, Point::new(obj.x, obj.y));
+                let (w, h) = (obj.width, obj.height);
+                // Tile objects hang from their bottom-left corner, or from their
+                // bottom-centre on isometric maps.
+                let left = match self.map.orientation {
+                    MapOrientation::Isometric => anchor.x - w / 2.0,
+                    _ => anchor.x,
+                };
+                let dest = (
+                    left + state.offsetx + off_x,
+                    anchor.y - h + state.offsety + off_y,
+                    w,
+                    h,
+                );
+                let pivot = (
+                    anchor.x + state.offsetx + off_x,
+                    anchor.y + state.offsety + off_y,
+                );
+                let rotation = if obj.rotation != 0.0 {
+                    Option::Some((obj.rotation, pivot))
+                } else {
+                    Option::None
+                };
+                blit(canvas, bmp, src, dest, gid, state, rotation);
+                continue;
+            }
+
+            let color = tint(SHAPE_COLOR, state.tintcolor);
+            let shift = |p: Point| {
+                let p = project(self.map, p);
+                Point::new(p.x + state.offsetx, p.y + state.offsety)
+            };
+            if let Option::Some(outline) = obj.outline() {
+                let points: Vec<Point> = outline.into_iter().map(shift).collect();
+                fill_polygon(canvas, &points, color, state.opacity * SHAPE_FILL_OPACITY);
+                stroke(canvas, &points, true, color, state.opacity);
+            } else if let Option::Some(ref line) = obj.polyline {
+                let points: Vec<Point> = line
+                    .iter()
+                    .map(|p| shift(Point::new(obj.x + p.x, obj.y + p.y)))
+                    .collect();
+                stroke(canvas, &points, false, color, state.opacity);
+            } else if obj.point {
+                let p = shift(Point::new(obj.x, obj.y));
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        canvas.blend_pixel(p.x as i64 + dx, p.y as i64 + dy, color, state.opacity);
+                    }
+                }
+            }
+        }
+    }
+
+    // The image and source rectangle (x, y, width, height) of the tile of a gid.
+    fn tile_source(&self, gid: u32) -> Option<(&Bitmap, SourceRect)> {
+        let ts = self.map.tileset_by_gid(gid)?;
+        if let Option::Some(image) = ts.tile_by_gid(gid).and_then(|t| t.image.as_ref()) {
+            let bmp = self.images.get(&image_key(image, ts.transparentcolor))?;
+            return Option::Some((bmp, (0, 0, bmp.width, bmp.height)));
+        }
+        if ts.columns == 0 {
+            return Option::None;
+        }
+        let bmp = self
+            .images
+            .get(&image_key(&ts.image, ts.transparentcolor))?;
+        let (x, y) = ts.coord_by_gid(gid);
+        Option::Some((
+            bmp,
+            (
+                x as u32,
+                y as u32,
+                ts.tilewidth as u32,
+                ts.tileheight as u32,
+            ),
+        ))
+    }
+
+    fn tile_offset(&self, gid: u32) -> (f64, f64) {
+        match self.map.tileset_by_gid(gid).and_then(|ts| ts.tileoffset) {
+            Option::Some(o) => (o.x as f64, o.y as f64),
+            Option::None => (0.0, 0.0),
+        }
+    }
+}
+
+fn project(map: &Map, p: Point) -> Point {
+    let (x, y) = map.object_to_pixel(p.x, p.y);
+    Point::new(x, y)
+}
+
+fn image_key(image: &str, transparent: Option<Color>) -> (String, String) {
+    (
+        image.to_string(),
+        transparent.map(|c| c.to_string()).unwrap_or_default(),
+    )
+}
+
+// Copy the src rectangle of bmp into the dest rectangle of the canvas, scaled
+// to fit, flipped according to the flags of gid and optionally rotated by an
+// angle in degrees around a pivot.
+fn blit(
+    canvas: &mut Canvas,
+    bmp: &Bitmap,
+    src: SourceRect,
+    dest: (f64, f64, f64, f64),
+    gid: u32,
+    transform: &LayerTransform,
+    rotation: Option<(f64, (f64, f64))>,
+) {
+    let (dx, dy, dw, dh) = dest;
+    if dw <= 0.0 || dh <= 0.0 || src.2 == 0 || src.3 == 0 {
+        return;
+    }
+    let (flip_h, flip_v, flip_d) = crate::gid_flipped_hvd(gid);
+
+    // Bounding box of the destination on the canvas.
+    let corners = [(dx, dy), (dx + dw, dy), (dx + dw, dy + dh), (dx, dy + dh)];
+    let (sin, cos, pivot) = match rotation {
+        Option::Some((deg, pivot)) => {
+            let (s, c) = deg.to_radians().sin_cos();
+            (s, c, pivot)
+        }
+        Option::None => (0.0, 1.0, (0.0, 0.0)),
+    };
+    let rotate = |x: f64, y: f64, s: f64| {
+        let (rx, ry) = (x - pivot.0, y - pivot.1);
+        (pivot.0 + rx * cos - ry * s, pivot.1 + rx * s + ry * cos)
+    };
+    let placed: Vec<(f64, f64)> = corners.iter().map(|c| rotate(c.0, c.1, sin)).collect();
+    let min_x = placed
+        .iter()
+        .fold(f64::INFINITY, |m, c| m.min(c.0))
+        .floor()
+        .max(0.0) as i64;
+    let min_y = placed
+        .iter()
+        .fold(f64::INFINITY, |m, c| m.min(c.1))
+        .floor()
+        .max(0.0) as i64;
+    let max_x = placed
+        .iter()
+        .fold(f64::NEG_INFINITY, |m, c| m.max(c.0))
+        .ceil() as i64;
+    let max_y = placed
+        .iter()
+        .fold(f64::NEG_INFINITY, |m, c| m.max(c.1))
+        .ceil() as i64;
+    let max_x = max_x.min(canvas.width as i64);
+    let max_y = max_y.min(canvas.height as i64);
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            // Undo the rotation, then find where we are in the destination.
+            let (ux, uy) = rotate(x as f64 + 0.5, y as f64 + 0.5, -sin);
+            let mut u = (ux - dx) / dw;
+            let mut v = (uy - dy) / dh;
+            if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                continue;
+            }
+            // Tiled flips diagonally, then horizontally, then vertically; undo
+            // those in reverse.
+            if flip_v {
+                v = 1.0 - v;
+            }
+            if flip_h {
+                u = 1.0 - u;
+            }
+            if flip_d {
+                std::mem::swap(&mut u, &mut v);
+            }
+            let sx = src.0 + ((u * src.2 as f64) as u32).min(src.2 - 1);
+            let sy = src.1 + ((v * src.3 as f64) as u32).min(src.3 - 1);
+            if sx >= bmp.width || sy >= bmp.height {
+                continue;
+            }
+            let i = (sy as usize * bmp.width as usize + sx as usize) * 4;
+            let px = [
+                bmp.pixels[i],
+                bmp.pixels[i + 1],
+                bmp.pixels[i + 2],
+                bmp.pixels[i + 3],
+            ];
+            canvas.blend_pixel(x, y, tint(px, transform.tintcolor), transform.opacity);
+        }
+    }
+}
+
+// Multiply a color by the tint color of a layer.
+fn tint(rgba: [u8; 4], tint: Option<Color>) -> [u8; 4] {
+    match tint {
+        Option::Some(t) => [
+            (rgba[0] as u32 * t.r as u32 / 255) as u8,
+            (rgba[1] as u32 * t.g as u32 / 255) as u8,
+            (rgba[2] as u32 * t.b as u32 / 255) as u8,
+            (rgba[3] as u32 * t.a as u32 / 255) as u8,
+        ],
+        Option::None => rgba,
+    }
+}
+
+// Fill a polygon with the even-odd rule, sampling pixel centres.
+fn fill_polygon(canvas: &mut Canvas, points: &[Point], rgba: [u8; 4], opacity: f64) {
+    if points.len() < 3 {
+        return;
+    }
+    let min_y = points
+        .iter()
+        .fold(f64::INFINITY, |m, p| m.min(p.y))
+        .floor()
+        .max(0.0) as i64;
+    let max_y = points
+        .iter()
+        .fold(f64::NEG_INFINITY, |m, p| m.max(p.y))
+        .ceil() as i64;
+    let max_y = max_y.min(canvas.height as i64);
+    let n = points.len();
+    for y in min_y..max_y {
+        let cy = y as f64 + 0.5;
+        let mut xs: Vec<f64> = Vec::new();
+        for i in 0..n {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            if (a.y <= cy && b.y > cy) || (b.y <= cy && a.y > cy) {
+                xs.push(a.x + (cy - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        for pair in xs.chunks(2) {
+            if pair.len() < 2 {
+                break;
+            }
+            // Spans are clamped to the canvas, as shapes may reach far outside.
+            let start = ((pair[0] - 0.5).ceil() as i64).max(0);
+            let end = ((pair[1] - 0.5).floor() as i64).min(canvas.width as i64 - 1);
+            for x in start..=end {
+                canvas.blend_pixel(x, y, rgba, opacity);
+            }
+        }
+    }
+}
+
+// Draw one-pixel lines between consecutive points, back to the first one when
+// closed is true.
+fn stroke(canvas: &mut Canvas, points: &[Point], closed: bool, rgba: [u8; 4], opacity: f64) {
+    let n = points.len();
+    let segments = if closed { n } else { n.saturating_sub(1) };
+    for i in 0..segments {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        let steps = (b.x - a.x).abs().max((b.y - a.y).abs()).ceil().max(1.0) as i64;
+        // Skip the last pixel of each segment so joints are not blended twice.
+        for s in 0..steps {
+            let t = s as f64 / steps as f64;
+            let x = (a.x + (b.x - a.x) * t).floor() as i64;
+            let y = (a.y + (b.y - a.y) * t).floor() as i64;
+            canvas.blend_pixel(x, y, rgba, opacity);
+        }
+    }
+    if !closed && n > 0 {
+        let last = points[n - 1];
+        canvas.blend_pixel(last.x.floor() as i64, last.y.floor() as i64, rgba, opacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fill_polygon;
+    use super::Canvas;
+    use super::Renderer;
+    use crate::map::Map;
+    use crate::object::Point;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    fn pixel(canvas: &Canvas, x: u32, y: u32) -> [u8; 4] {
+        let c = canvas.get_pixel(x, y).unwrap();
+        [c.r, c.g, c.b, c.a]
+    }
+
+    fn row(canvas: &Canvas, y: u32, from: u32, to: u32) -> Vec<[u8; 4]> {
+        (from..to).map(|x| pixel(canvas, x, y)).collect()
+    }
+
+    #[test]
+    fn flipped_tiles_land_on_the_right_pixels() {
+        // squares.png is one 2x2 tile: red and green over blue and white.
+        // bar.png is one 1x2 tile: red over blue.
+        let dir = std::env::temp_dir().join(format!("render-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let squares = [RED, GREEN, BLUE, WHITE].concat();
+        let bar = [RED, BLUE].concat();
+        let rgba = image::ExtendedColorType::Rgba8;
+        image::save_buffer(dir.join("squares.png"), &squares, 2, 2, rgba).unwrap();
+        image::save_buffer(dir.join("bar.png"), &bar, 1, 2, rgba).unwrap();
+
+        let (h, v, d) = (
+            crate::HORZ_FLIP_FLAG,
+            crate::VERT_FLIP_FLAG,
+            crate::DIAG_FLIP_FLAG,
+        );
+        let json = r#"{
+            "orientation": "orthogonal", "renderorder": "right-down", "width": 5, "height": 1,
+            "tilewidth": 2, "tileheight": 2, "nextobjectid": 1, "nextlayerid": 2,
+            "tilesets": [
+                { "firstgid": 1, "name": "squares", "image": "squares.png",
+                  "imagewidth": 2, "imageheight": 2, "tilewidth": 2, "tileheight": 2,
+                  "tilecount": 1, "columns": 1 },
+                { "firstgid": 2, "name": "bar", "image": "bar.png",
+                  "imagewidth": 1, "imageheight": 2, "tilewidth": 1, "tileheight": 2,
+                  "tilecount": 1, "columns": 1 }
+            ],
+            "layers": [{ "type": "tilelayer", "id": 1, "name": "tiles",
+                         "width": 5, "height": 1, "data": DATA }]
+        }"#;
+        let data = format!("{:?}", vec![1, 1 | h, 1 | v, 1 | d, 2 | d]);
+        let map: Map = serde_json::from_str(&json.replace("DATA", &data)).unwrap();
+        let renderer = Renderer::new(&map, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let canvas = renderer.unwrap().render().unwrap();
+
+        assert_eq!((canvas.width(), canvas.height()), (10, 2));
+        // Plain, flipped horizontally, flipped vertically, then transposed.
+        assert_eq!(
+            row(&canvas, 0, 0, 8),
+            vec![RED, GREEN, GREEN, RED, BLUE, WHITE, RED, BLUE]
+        );
+        assert_eq!(
+            row(&canvas, 1, 0, 8),
+            vec![BLUE, WHITE, WHITE, BLUE, RED, GREEN, GREEN, WHITE]
+        );
+        // The transposed bar lies 2x1 on the bottom of its cell.
+        assert_eq!(row(&canvas, 0, 8, 10), vec![[0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(row(&canvas, 1, 8, 10), vec![RED, BLUE]);
+    }
+
+    #[test]
+    fn huge_canvases_and_shapes_are_refused_or_clipped() {
+        assert!(Canvas::new(u32::MAX, u32::MAX).is_err());
+
+        let mut canvas = Canvas::new(3, 2).unwrap();
+        let far = 1.0e12;
+        let square = [
+            Point::new(-far, -far),
+            Point::new(far, -far),
+            Point::new(far, far),
+            Point::new(-far, far),
+        ];
+        fill_polygon(&mut canvas, &square, RED, 1.0);
+        assert!(canvas.pixels().chunks(4).all(|px| px == RED));
+    }
+}
@@ -0,0 +1,305 @@
+//!
+//! Resizing, offsetting and cropping maps.
+//!
+//! These mirror Tiled's "Resize Map" and "Offset Map" actions:
+//! - Map::resize() changes the size of the map and shifts its contents by an
+//!   offset in cells, optionally removing the objects left outside.
+//! - Map::offset() shifts the contents of the map within its current size,
+//!   optionally wrapping around the edges.
+//! - Map::crop() keeps a rectangle of cells, and Map::sub_map() copies one out
+//!   as a standalone map.
+//!
+//! Every tile layer, nested ones included, is shifted and resized; tiles moved
+//! outside of the map are dropped and new cells are empty.  Every object of
+//! every object group is moved by the same offset in object coordinates.  Image
+//! layers are left alone, as are the objects of tiles in tilesets.
+//!
+//! Only objects can be kept outside of the map.  Tiles cannot: a tile layer
+//! holds exactly width * height cells, and the chunks of infinite maps, which
+//! could hold more, are not supported by this library.  Keep a copy of the
+//! map, or use Map::sub_map() first, to hold on to the tiles a resize drops.
+//!
+//! On staggered and hexagonal maps, an odd offset along the stagger axis
+//! changes which rows or columns are shifted, so the map looks sheared; keep
+//! those offsets even to preserve its look.
+//!
+
+use crate::layer::Layer;
+use crate::layer::LayerDataContainer;
+use crate::map::Map;
+use crate::map::MapOrientation;
+use crate::map::StaggerAxis;
+use crate::object::Object;
+use crate::object::Point;
+
+impl Map {
+    /// Resize the map to width by height cells, moving its contents by
+    /// (offset_x, offset_y) cells.  Tiles outside of the resized map are always
+    /// dropped; see the module documentation.  With remove_objects, objects
+    /// entirely outside of it are removed, otherwise they are kept.
+    pub fn resize(
+        &mut self,
+        width: u16,
+        height: u16,
+        offset_x: i32,
+        offset_y: i32,
+        remove_objects: bool,
+    ) {
+        let (dx, dy) = self.cell_offset_to_object(offset_x, offset_y);
+        self.width = width;
+        self.height = height;
+        let bounds = self.object_bounds();
+        let shift = Shift {
+            width: width as u32,
+            height: height as u32,
+            offset_x,
+            offset_y,
+            dx,
+            dy,
+            wrap: false,
+            remove: if remove_objects {
+                Option::Some(bounds)
+            } else {
+                Option::None
+            },
+        };
+        shift.apply(&mut self.layers);
+    }
+
+    /// Move the contents of the map by (x, y) cells, keeping its size.  Without
+    /// wrap, tiles moved outside of the map are dropped and objects are moved
+    /// anyway; with wrap, both come back in on the opposite side.
+    pub fn offset(&mut self, x: i32, y: i32, wrap: bool) {
+        let (dx, dy) = self.cell_offset_to_object(x, y);
+        let shift = Shift {
+            width: self.width as u32,
+            height: self.height as u32,
+            offset_x: x,
+            offset_y: y,
+            dx,
+            dy,
+            wrap,
+            remove: Option::None,
+        };
+        shift.apply(&mut self.layers);
+        if wrap {
+            let (w, h) = self.object_bounds();
+            wrap_objects(&mut self.layers, w, h);
+        }
+    }
+
+    /// Keep the width by height cells whose top-left cell is (x, y), removing
+    /// the objects outside of them.
+    pub fn crop(&mut self, x: u16, y: u16, width: u16, height: u16) {
+        self.resize(width, height, -(x as i32), -(y as i32), true);
+    }
+
+    /// Copy the width by height cells whose top-left cell is (x, y), with the
+    /// objects over them, into a standalone map.  Tilesets, properties and
+    /// layer ids are kept.
+    pub fn sub_map(&self, x: u16, y: u16, width: u16, height: u16) -> Map {
+        let mut map = self.clone();
+        map.crop(x, y, width, height);
+        map
+    }
+
+    // The move in object coordinates of a shift by (x, y) cells.  Objects on
+    // isometric maps are laid out on a grid of tileheight squares.
+    fn cell_offset_to_object(&self, x: i32, y: i32) -> (f64, f64) {
+        let (x, y) = (x as f64, y as f64);
+        let (tw, th) = (self.tilewidth as f64, self.tileheight as f64);
+        match self.orientation {
+            MapOrientation::Orthogonal => (x * tw, y * th),
+            MapOrientation::Isometric => (x * th, y * th),
+            MapOrientation::Staggered | MapOrientation::Hexagonal => {
+                let (column_width, row_height, _, _) = self.stagger_metrics();
+                match self.staggeraxis.unwrap_or(StaggerAxis::StaggerY) {
+                    StaggerAxis::StaggerY => (x * tw, y * row_height),
+                    StaggerAxis::StaggerX => (x * column_width, y * th),
+                }
+            }
+        }
+    }
+
+    // The size of the map in object coordinates.
+    fn object_bounds(&self) -> (f64, f64) {
+        match self.orientation {
+            MapOrientation::Isometric => {
+                let th = self.tileheight as f64;
+                (self.width as f64 * th, self.height as f64 * th)
+            }
+            _ => {
+                let (w, h) = self.pixel_size();
+                (w as f64, h as f64)
+            }
+        }
+    }
+}
+
+// How to move the contents of layers.
+struct Shift {
+    width: u32,
+    height: u32,
+    offset_x: i32,
+    offset_y: i32,
+    dx: f64,
+    dy: f64,
+    wrap: bool,
+    remove: Option<(f64, f64)>,
+}
+
+impl Shift {
+    fn apply(&self, layers: &mut [Layer]) {
+        for layer in layers.iter_mut() {
+            let (old_width, old_height) = (layer.width, layer.height);
+            match layer.layerdata {
+                LayerDataContainer::TileLayer { ref mut data } => {
+                    *data = self.shift_data(data, old_width, old_height);
+                    layer.width = self.width;
+                    layer.height = self.height;
+                }
+                LayerDataContainer::ObjectGroup {
+                    ref mut objects, ..
+                } => {
+                    for object in objects.iter_mut() {
+                        object.x += self.dx;
+                        object.y += self.dy;
+                    }
+                    if let Option::Some((w, h)) = self.remove {
+                        objects.retain(|o| overlaps(o, w, h));
+                    }
+                }
+                LayerDataContainer::Group { ref mut layers } => self.apply(layers),
+                LayerDataContainer::ImageLayer { .. } => {}
+            }
+        }
+    }
+
+    fn shift_data(&self, data: &[u32], width: u32, height: u32) -> Vec<u32> {
+        let mut out = vec![0; (self.width * self.height) as usize];
+        if self.width == 0 || self.height == 0 {
+            return out;
+        }
+        for y in 0..height {
+            for x in 0..width {
+                let gid = match data.get((y * width + x) as usize) {
+                    Option::Some(g) => *g,
+                    Option::None => continue,
+                };
+                let mut nx = x as i64 + self.offset_x as i64;
+                let mut ny = y as i64 + self.offset_y as i64;
+                if self.wrap {
+                    nx = nx.rem_euclid(self.width as i64);
+                    ny = ny.rem_euclid(self.height as i64);
+                }
+                if nx < 0 || ny < 0 || nx >= self.width as i64 || ny >= self.height as i64 {
+                    continue;
+                }
+                out[(ny * self.width as i64 + nx) as usize] = gid;
+            }
+        }
+        out
+    }
+}
+
+// Whether the bounding box of an object overlaps the map, width by height in
+// object coordinates.  Boxes only touching an edge of the map do not.
+fn overlaps(obj: &Object, width: f64, height: f64) -> bool {
+    let points = obj
+        .outline()
+        .or_else(|| {
+            obj.polyline.as_ref().map(|line| {
+                line.iter()
+                    .map(|p| Point::new(obj.x + p.x, obj.y + p.y))
+                    .collect()
+            })
+        })
+        .unwrap_or_else(|| vec![Point::new(obj.x, obj.y)]);
+    let min_x = points.iter().fold(f64::INFINITY, |m, p| m.min(p.x));
+    let max_x = points.iter().fold(f64::NEG_INFINITY, |m, p| m.max(p.x));
+    let min_y = points.iter().fold(f64::INFINITY, |m, p| m.min(p.y));
+    let max_y = points.iter().fold(f64::NEG_INFINITY, |m, p| m.max(p.y));
+    let hit = |min: f64, max: f64, size: f64| {
+        if min == max {
+            min >= 0.0 && min < size
+        } else {
+            max > 0.0 && min < size
+        }
+    };
+    hit(min_x, max_x, width) && hit(min_y, max_y, height)
+}
+
+// Bring the positions of objects back inside width by height.
+fn wrap_objects(layers: &mut [Layer], width: f64, height: f64) {
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    for layer in layers.iter_mut() {
+        if let Option::Some(objects) = layer.get_objects_vector_mut() {
+            for object in objects.iter_mut() {
+                object.x = object.x.rem_euclid(width);
+                object.y = object.y.rem_euclid(height);
+            }
+        }
+        if let Option::Some(layers) = layer.get_layers_mut() {
+            wrap_objects(layers, width, height);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+    use crate::object::Object;
+
+    // A 4x4 map whose tile layer (id 1) has cell (x, y) set to gid 1 + x + 4y,
+    // with an object group (id 2) holding a rectangle over cell (3, 3).
+    fn numbered() -> Map {
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 4, 4, 16, 16);
+        let ground = builder.add_layer(Layer::new_tile_layer("ground")).unwrap();
+        let data = builder.layer_mut(ground).unwrap().get_data_mut().unwrap();
+        for (i, gid) in data.iter_mut().enumerate() {
+            *gid = 1 + i as u32;
+        }
+        let things = builder
+            .add_layer(Layer::new_object_group("things"))
+            .unwrap();
+        builder.add_object(things, Object::new_rect(48.0, 48.0, 16.0, 16.0));
+        builder.build()
+    }
+
+    #[test]
+    fn resize_drops_tiles_and_optionally_objects() {
+        let mut kept = numbered();
+        kept.resize(3, 2, -1, 0, false);
+        let ground = kept.layer_by_id(1).unwrap();
+        assert_eq!(ground.get_data().unwrap(), &vec![2, 3, 4, 6, 7, 8]);
+        let objects = kept.layer_by_id(2).unwrap().get_objects_vector().unwrap();
+        assert_eq!((objects.len(), objects[0].x), (1, 32.0));
+
+        let mut removed = numbered();
+        removed.resize(3, 2, -1, 0, true);
+        let objects = removed
+            .layer_by_id(2)
+            .unwrap()
+            .get_objects_vector()
+            .unwrap();
+        assert!(objects.is_empty());
+    }
+
+    #[test]
+    fn sub_map_copies_a_rectangle() {
+        let map = numbered();
+        let sub = map.sub_map(2, 2, 2, 2);
+        assert_eq!((sub.width, sub.height), (2, 2));
+        assert_eq!(
+            sub.layer_by_id(1).unwrap().get_data().unwrap(),
+            &vec![11, 12, 15, 16]
+        );
+        let objects = sub.layer_by_id(2).unwrap().get_objects_vector().unwrap();
+        assert_eq!((objects[0].x, objects[0].y), (16.0, 16.0));
+    }
+}
@@ -0,0 +1,563 @@
+//!
+//! Checking custom properties against a schema.
+//!
+//! Games read custom properties by name, so a designer typing heatlh instead
+//! of health breaks an enemy without any error.  A Schema declares, for each
+//! object type, layer class and tile type, the properties it carries: their
+//! name, their kind, and optionally a range of numbers or a list of allowed
+//! strings.  Map::validate_properties() checks a map against it and reports
+//! Diagnostic values naming the object, layer or tile and the property:
+//! - declared properties that are missing, unless they are optional,
+//! - properties of the wrong kind, out of range or not among the allowed
+//!   strings,
+//! - properties that are not declared, with the closest declared name when
+//!   one is close, unless the class allows other properties.
+//!
+//! Only types and classes named in the schema are checked.  Tile objects with
+//! no type take the type of their tile and inherit its properties, as in Tiled.
+//!
+//! Schemas are built in code, or loaded from JSON with Schema::load():
+//!
+//! ```ignore
+//! {
+//!   "objects": {
+//!     "enemy": {
+//!       "properties": [
+//!         { "name": "health", "type": "int", "min": 1, "max": 100 },
+//!         { "name": "faction", "type": "string", "values": ["red", "blue"] },
+//!         { "name": "boss", "type": "bool", "optional": true }
+//!       ]
+//!     }
+//!   },
+//!   "layers": {},
+//!   "tiles": {}
+//! }
+//! ```
+//!
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::map::Map;
+use crate::property::Property;
+use crate::property::PropertyValue;
+use crate::validate::Diagnostic;
+use crate::validate::Location;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The kind of a property, one for each variant of PropertyValue.  They are
+/// named as in Tiled: string, int, float, bool, color and file.
+pub enum PropertyKind {
+    String,
+    Int,
+    Float,
+    Bool,
+    Color,
+    File,
+}
+
+impl PropertyKind {
+    /// The kind of a property value.
+    pub fn of(value: &PropertyValue) -> PropertyKind {
+        match value {
+            PropertyValue::StringV(_) => PropertyKind::String,
+            PropertyValue::Int(_) => PropertyKind::Int,
+            PropertyValue::Float(_) => PropertyKind::Float,
+            PropertyValue::Bool(_) => PropertyKind::Bool,
+            PropertyValue::Color(_) => PropertyKind::Color,
+            PropertyValue::File(_) => PropertyKind::File,
+        }
+    }
+}
+
+impl std::fmt::Display for PropertyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PropertyKind::String => "string",
+            PropertyKind::Int => "int",
+            PropertyKind::Float => "float",
+            PropertyKind::Bool => "bool",
+            PropertyKind::Color => "color",
+            PropertyKind::File => "file",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A declared property.  min and max bound int and float properties; values,
+/// when not empty, lists the strings a string property may hold.
+pub struct PropertyRule {
+    pub name: String,
+
+    #[serde(rename = "type")]
+    pub kind: PropertyKind,
+
+    #[serde(default)]
+    pub optional: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The properties of an object type, a layer class or a tile type.  Unless
+/// allow_other is set, properties not declared are reported.
+pub struct ClassSchema {
+    #[serde(default)]
+    pub properties: Vec<PropertyRule>,
+
+    #[serde(default)]
+    pub allow_other: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// The declared properties of a game, by object type, layer class and tile
+/// type.  See the module documentation.
+pub struct Schema {
+    #[serde(default)]
+    pub objects: BTreeMap<String, ClassSchema>,
+
+    #[serde(default)]
+    pub layers: BTreeMap<String, ClassSchema>,
+
+    #[serde(default)]
+    pub tiles: BTreeMap<String, ClassSchema>,
+}
+
+impl PropertyRule {
+    /// Declare a required property.
+    pub fn new(name: &str, kind: PropertyKind) -> PropertyRule {
+        PropertyRule {
+            name: name.to_string(),
+            kind,
+            optional: false,
+            min: Option::None,
+            max: Option::None,
+            values: Vec::new(),
+        }
+    }
+
+    /// Make the property optional.
+    pub fn optional(mut self) -> PropertyRule {
+        self.optional = true;
+        self
+    }
+
+    /// Bound the value of a number property, inclusively.
+    pub fn range(mut self, min: f64, max: f64) -> PropertyRule {
+        self.min = Option::Some(min);
+        self.max = Option::Some(max);
+        self
+    }
+
+    /// Restrict a string property to values.
+    pub fn values(mut self, values: &[&str]) -> PropertyRule {
+        self.values = values.iter().map(|v| v.to_string()).collect();
+        self
+    }
+
+    // What is wrong with a value of the property, if anything.
+    fn check(&self, value: &PropertyValue) -> Option<String> {
+        let kind = PropertyKind::of(value);
+        if kind != self.kind {
+            return Option::Some(format!("is of type {}, expected {}", kind, self.kind));
+        }
+        let number = match value {
+            PropertyValue::Int(i) => Option::Some(*i as f64),
+            PropertyValue::Float(x) => Option::Some(*x),
+            _ => Option::None,
+        };
+        if let Option::Some(n) = number {
+            let below = self.min.is_some_and(|min| n < min);
+            let above = self.max.is_some_and(|max| n > max);
+            if below || above {
+                return Option::Some(format!("{} is out of range {}", value, self.range_text()));
+            }
+        }
+        if let PropertyValue::StringV(ref s) = value {
+            if !self.values.is_empty() && !self.values.contains(s) {
+                return Option::Some(format!(
+                    "{} is not one of {}",
+                    value,
+                    self.values
+                        .iter()
+                        .map(|v| format!("{:?}", v))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ));
+            }
+        }
+        Option::None
+    }
+
+    fn range_text(&self) -> String {
+        let bound = |b: Option<f64>| b.map(|b| b.to_string()).unwrap_or_default();
+        format!("{}..={}", bound(self.min), bound(self.max))
+    }
+}
+
+impl ClassSchema {
+    /// Create a class with no properties.
+    pub fn new() -> ClassSchema {
+        ClassSchema::default()
+    }
+
+    /// Declare a property of the class.
+    pub fn property(mut self, rule: PropertyRule) -> ClassSchema {
+        self.properties.push(rule);
+        self
+    }
+
+    /// Allow properties that are not declared.
+    pub fn allow_other(mut self) -> ClassSchema {
+        self.allow_other = true;
+        self
+    }
+
+    // Check a set of properties, reporting problems at location.
+    fn check(&self, found: &mut Vec<Diagnostic>, location: &Location, properties: &[&Property]) {
+        for rule in self.properties.iter() {
+            match properties.iter().find(|p| p.name == rule.name) {
+                Option::Some(property) => {
+                    if let Option::Some(problem) = rule.check(&property.value) {
+                        found.push(error(location, &rule.name, problem));
+                    }
+                }
+                Option::None if !rule.optional => {
+                    found.push(error(
+                        location,
+                        &rule.name,
+                        format!("is missing, expected type {}", rule.kind),
+                    ));
+                }
+                Option::None => {}
+            }
+        }
+        if self.allow_other {
+            return;
+        }
+        for property in properties.iter() {
+            if self.properties.iter().any(|r| r.name == property.name) {
+                continue;
+            }
+            let closest = self
+                .properties
+                .iter()
+                .map(|r| (distance(&r.name, &property.name), &r.name))
+                .filter(|(d, _)| *d <= 2)
+                .min();
+            let message = match closest {
+                Option::Some((_, name)) => format!("not declared, did you mean {:?}?", name),
+                Option::None => "not declared".to_string(),
+            };
+            let mut diagnostic = Diagnostic::warning(location.clone(), message);
+            diagnostic.property = Option::Some(property.name.clone());
+            found.push(diagnostic);
+        }
+    }
+}
+
+impl Schema {
+    /// Create an empty schema.
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Load a schema from a JSON file.
+    pub fn load(file: &str) -> Result<Schema, std::io::Error> {
+        let file = File::open(file)?;
+
+        let mut buf_reader = BufReader::new(file);
+        let mut contents = String::new();
+        buf_reader.read_to_string(&mut contents)?;
+
+        let schema: Schema = serde_json::from_str(&contents)?;
+        Ok(schema)
+    }
+
+    /// Declare the properties of objects of type name.
+    pub fn object(&mut self, name: &str, class: ClassSchema) -> &mut Schema {
+        self.objects.insert(name.to_string(), class);
+        self
+    }
+
+    /// Declare the properties of layers of class name.
+    pub fn layer(&mut self, name: &str, class: ClassSchema) -> &mut Schema {
+        self.layers.insert(name.to_string(), class);
+        self
+    }
+
+    /// Declare the properties of tiles of type name.
+    pub fn tile(&mut self, name: &str, class: ClassSchema) -> &mut Schema {
+        self.tiles.insert(name.to_string(), class);
+        self
+    }
+}
+
+impl Map {
+    /// Check the properties of the objects, layers and tiles of the map against
+    /// schema.  See the module documentation.
+    pub fn validate_properties(&self, schema: &Schema) -> Vec<Diagnostic> {
+        let mut found = Vec::new();
+        for ts in self.tilesets.iter() {
+            for tile in ts.tiles.iter() {
+                let class = tile.ttype.as_ref().and_then(|t| schema.tiles.get(t));
+                if let Option::Some(class) = class {
+                    let location = Location::Tile {
+                        tileset: ts.name.clone(),
+                        id: tile.id,
+                    };
+                    let properties: Vec<&Property> = tile.properties.iter().collect();
+                    class.check(&mut found, &location, &properties);
+                }
+            }
+        }
+
+        for layer in crate::validate::all_layers(&self.layers) {
+            if let Option::Some(class) = schema.layers.get(&layer.class) {
+                let location = Location::Layer {
+                    id: layer.id,
+                    name: layer.name.clone(),
+                };
+                let properties: Vec<&Property> = layer.properties.iter().collect();
+                class.check(&mut found, &location, &properties);
+            }
+
+            for obj in layer.get_objects_vector().into_iter().flatten() {
+                let tile = obj.gid.and_then(|gid| self.tile_by_gid(gid));
+                let otype = match tile.and_then(|t| t.ttype.as_ref()) {
+                    Option::Some(ttype) if obj.otype.is_empty() => ttype,
+                    _ => &obj.otype,
+                };
+                if let Option::Some(class) = schema.objects.get(otype) {
+                    let location = Location::Object {
+                        id: obj.id,
+                        layer: layer.name.clone(),
+                    };
+                    let mut properties: Vec<&Property> = obj.properties.iter().collect();
+                    for inherited in tile.into_iter().flat_map(|t| t.properties.iter()) {
+                        if !obj.properties.iter().any(|p| p.name == inherited.name) {
+                            properties.push(inherited);
+                        }
+                    }
+                    class.check(&mut found, &location, &properties);
+                }
+            }
+        }
+        found
+    }
+}
+
+fn error(location: &Location, name: &str, message: String) -> Diagnostic {
+    let mut diagnostic = Diagnostic::error(location.clone(), message);
+    diagnostic.property = Option::Some(name.to_string());
+    diagnostic
+}
+
+// The edit distance between two names, to suggest the declared name closest
+// to a typo.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClassSchema;
+    use super::PropertyKind;
+    use super::PropertyRule;
+    use super::Schema;
+    use crate::map::Map;
+    use crate::validate::Location;
+    use crate::validate::Severity;
+
+    fn schema() -> Schema {
+        let mut schema = Schema::new();
+        schema.object(
+            "enemy",
+            ClassSchema::new()
+                .property(PropertyRule::new("health", PropertyKind::Int).range(1.0, 100.0))
+                .property(
+                    PropertyRule::new("faction", PropertyKind::String).values(&["red", "blue"]),
+                )
+                .property(PropertyRule::new("boss", PropertyKind::Bool).optional()),
+        );
+        schema.layer(
+            "spawns",
+            ClassSchema::new()
+                .property(PropertyRule::new("rate", PropertyKind::Float).range(0.0, 1.0))
+                .allow_other(),
+        );
+        schema
+    }
+
+    // A map whose tile 0 is an enemy, with an object group "spawns" holding
+    // the objects given in JSON.
+    fn map_with(objects: &str) -> Map {
+        let json = r#"{
+            "orientation": "orthogonal", "width": 2, "height": 2,
+            "tilewidth": 16, "tileheight": 16, "nextobjectid": 10, "nextlayerid": 2,
+            "tilesets": [{
+                "firstgid": 1, "name": "tiles", "image": "tiles.png",
+                "imagewidth": 32, "imageheight": 16, "tilewidth": 16, "tileheight": 16,
+                "tilecount": 2, "columns": 2,
+                "tiles": [{ "id": 0, "type": "enemy", "properties": [
+                    { "name": "health", "type": "int", "value": 50 },
+                    { "name": "faction", "type": "string", "value": "blue" }
+                ] }]
+            }],
+            "layers": [{ "type": "objectgroup", "id": 1, "name": "spawns", "class": "spawns",
+                         "properties": [
+                            { "name": "rate", "type": "float", "value": 0.5 },
+                            { "name": "note", "type": "string", "value": "anything" }
+                         ],
+                         "objects": OBJECTS }]
+        }"#;
+        serde_json::from_str(&json.replace("OBJECTS", objects)).unwrap()
+    }
+
+    // The severity, property and message of each diagnostic.
+    fn found(map: &Map, schema: &Schema) -> Vec<(Severity, String, String)> {
+        map.validate_properties(schema)
+            .into_iter()
+            .map(|d| (d.severity, d.property.unwrap_or_default(), d.message))
+            .collect()
+    }
+
+    #[test]
+    fn typos_are_missing_properties_with_a_suggestion() {
+        let map = map_with(
+            r#"[{ "id": 1, "type": "enemy", "x": 0, "y": 0, "properties": [
+                { "name": "heatlh", "type": "int", "value": 5 },
+                { "name": "faction", "type": "string", "value": "red" }
+            ] }]"#,
+        );
+        assert_eq!(
+            found(&map, &schema()),
+            vec![
+                (
+                    Severity::Error,
+                    "health".to_string(),
+                    "is missing, expected type int".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "heatlh".to_string(),
+                    "not declared, did you mean \"health\"?".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_values_and_kinds_are_checked() {
+        let map = map_with(
+            r#"[{ "id": 1, "type": "enemy", "x": 0, "y": 0, "properties": [
+                { "name": "health", "type": "int", "value": 0 },
+                { "name": "faction", "type": "string", "value": "green" },
+                { "name": "boss", "type": "string", "value": "yes" },
+                { "name": "loot", "type": "int", "value": 3 }
+            ] }]"#,
+        );
+        assert_eq!(
+            found(&map, &schema()),
+            vec![
+                (
+                    Severity::Error,
+                    "health".to_string(),
+                    "0 is out of range 1..=100".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "faction".to_string(),
+                    "\"green\" is not one of \"red\", \"blue\"".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "boss".to_string(),
+                    "is of type string, expected bool".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "loot".to_string(),
+                    "not declared".to_string()
+                ),
+            ]
+        );
+
+        // Objects of other types, and the "note" of the layer, are not checked.
+        let map = map_with(r#"[{ "id": 1, "type": "chest", "x": 0, "y": 0 }]"#);
+        assert!(found(&map, &schema()).is_empty());
+    }
+
+    #[test]
+    fn tile_objects_inherit_the_type_and_properties_of_their_tile() {
+        let map = map_with(
+            r#"[
+                { "id": 1, "gid": 1, "x": 0, "y": 16, "width": 16, "height": 16 },
+                { "id": 2, "gid": 1, "x": 0, "y": 16, "width": 16, "height": 16,
+                  "properties": [{ "name": "faction", "type": "string", "value": "green" }] },
+                { "id": 3, "gid": 2, "x": 0, "y": 16, "width": 16, "height": 16 }
+            ]"#,
+        );
+        let diagnostics = map.validate_properties(&schema());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].location,
+            Location::Object {
+                id: 2,
+                layer: "spawns".to_string()
+            }
+        );
+        assert_eq!(diagnostics[0].property, Option::Some("faction".to_string()));
+    }
+
+    #[test]
+    fn schemas_load_from_json() {
+        let path = std::env::temp_dir().join(format!("schema-{}.json", std::process::id()));
+        let written = serde_json::to_string(&schema()).unwrap();
+        std::fs::write(&path, &written).unwrap();
+        let loaded = Schema::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), written);
+
+        let rule = &loaded.objects["enemy"].properties[0];
+        assert_eq!(
+            (rule.min, rule.max),
+            (Option::Some(1.0), Option::Some(100.0))
+        );
+        assert!(loaded.layers["spawns"].allow_other);
+        assert!(Schema::load("no/such/schema.json").is_err());
+    }
+}
@@ -36,7 +36,7 @@ use serde::Deserialize;
 const ORIENT_ORTHO: &str = "orthogonal";
 const ORIENT_ISO: &str = "isometric";
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// The primary means of capturing image data.
 pub struct Tileset {
@@ -205,6 +205,16 @@ impl Tileset {
         self.tilecount
     }
 
+    /// Give you the number of gids this tileset takes, from first_gid() on.
+    /// This is tile_count(), except in image collections whose tile ids go
+    /// beyond it, once some tiles were removed in Tiled.
+    pub fn gid_span(&self) -> u32 {
+        self.tiles
+            .iter()
+            .map(|t| t.id as u32 + 1)
+            .fold(self.tilecount, u32::max)
+    }
+
     /// Columns refers to the width of the tileset in tile units.
     pub fn columns(&self) -> u16 {
         self.columns
@@ -277,7 +287,7 @@ impl HasProperty for Tileset {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 /// Tile contains data relevant to overrides of the tileset.
 /// This is for containing data specific to certain tiles within the tileset, such
@@ -329,14 +339,14 @@ impl Tile {
     /// The tuple is a boolean (whether we get an animation or not) and a u16, which will be the local
     /// id of the tile in the parent tileset.
     pub fn get_anim(&self, msecs: u32) -> (bool, u16) {
-        if !self.animation.is_empty() {
-            let total = self.get_anim_total() as u32;
-            let mut msecs = (msecs % total) as u16;
+        let total = self.get_anim_total();
+        if total > 0 {
+            let mut msecs = msecs % total;
             for f in self.animation.iter() {
-                if msecs < f.duration {
+                if msecs < f.duration as u32 {
                     return (true, f.tileid);
                 }
-                msecs -= f.duration;
+                msecs -= f.duration as u32;
             }
         }
         (false, 0)
@@ -382,12 +392,8 @@ impl Tile {
         self.objectgroup.as_ref()
     }
 
-    fn get_anim_total(&self) -> u16 {
-        let mut total: u16 = 0;
-        for f in self.animation.iter() {
-            total += f.duration;
-        }
-        total
+    fn get_anim_total(&self) -> u32 {
+        self.animation.iter().map(|f| f.duration as u32).sum()
     }
 }
 
@@ -0,0 +1,448 @@
+//!
+//! Triangulation and convex decomposition of polygons.
+//!
+//! Polygon objects drawn in Tiled are often concave and may contain repeated or
+//! collinear points, which most physics engines and renderers cannot consume.
+//! This module cleans such point lists up, normalizes their winding and cuts
+//! them into triangles (by ear clipping) or into a small number of convex
+//! polygons (by merging those triangles back together, Hertel-Mehlhorn style).
+//!
+//! Everything works on slices of tiled_json::Point.  Objects are handled through
+//! Object::outline(), so rectangles and ellipses work as well as polygons, and
+//! traced Outlines have their holes bridged into the exterior before clipping.
+//!
+//! Winding is described as it appears on screen, with y pointing down: a
+//! clockwise polygon has a positive signed area.  All output is clockwise.
+//!
+
+use crate::object::Object;
+use crate::object::Point;
+use crate::outline::Outline;
+
+const EPSILON: f64 = 0.000_000_1;
+
+/// A triangle, as three points in clockwise order.
+pub type Triangle = [Point; 3];
+
+/// Twice the signed area of a polygon.  It is positive when the points go
+/// clockwise on screen (y pointing down) and negative otherwise.
+pub fn signed_area(points: &[Point]) -> f64 {
+    let n = points.len();
+    let mut sum = 0.0;
+    for i in 0..n {
+        let a = points[i];
+        let b = points[(i + 1) % n];
+        sum += a.x * b.y - b.x * a.y;
+    }
+    sum
+}
+
+/// Does the polygon go clockwise on screen (y pointing down)?
+pub fn is_clockwise(points: &[Point]) -> bool {
+    signed_area(points) > 0.0
+}
+
+/// Reverse the polygon if needed so that it winds clockwise on screen, or
+/// counter-clockwise when clockwise is false.
+pub fn normalize_winding(points: &mut [Point], clockwise: bool) {
+    if points.len() > 2 && is_clockwise(points) != clockwise {
+        points.reverse();
+    }
+}
+
+/// Remove degenerate points from a polygon: repeated points (including a last
+/// point repeating the first) and points lying on the straight line between
+/// their neighbours.  Fewer than three points are left for degenerate polygons.
+pub fn cleanup_polygon(points: &[Point]) -> Vec<Point> {
+    let mut out: Vec<Point> = Vec::with_capacity(points.len());
+    for p in points.iter() {
+        match out.last() {
+            Option::Some(l) if same_point(*l, *p) => {}
+            _ => out.push(*p),
+        }
+    }
+    while out.len() > 1 && same_point(out[0], out[out.len() - 1]) {
+        out.pop();
+    }
+
+    let mut changed = true;
+    while changed && out.len() >= 3 {
+        changed = false;
+        let n = out.len();
+        for i in 0..n {
+            let prev = out[(i + n - 1) % n];
+            let next = out[(i + 1) % n];
+            if cross(prev, out[i], next).abs() < EPSILON {
+                out.remove(i);
+                changed = true;
+                break;
+            }
+        }
+    }
+    out
+}
+
+/// Cut a simple polygon into triangles by ear clipping.
+///
+/// The polygon is cleaned up first, so it may contain repeated or collinear
+/// points and may wind either way.  An empty vector is returned when fewer than
+/// three points remain.
+pub fn triangulate(points: &[Point]) -> Vec<Triangle> {
+    let mut poly = cleanup_polygon(points);
+    normalize_winding(&mut poly, true);
+    ear_clip(poly)
+}
+
+/// Triangulate the area covered by an object: a polygon, rectangle, ellipse or
+/// tile object.  Objects without an area yield an empty vector.
+pub fn triangulate_object(object: &Object) -> Vec<Triangle> {
+    match object.outline() {
+        Option::Some(points) => triangulate(&points),
+        Option::None => Vec::new(),
+    }
+}
+
+/// Triangulate a traced outline, holes included.
+pub fn triangulate_outline(outline: &Outline) -> Vec<Triangle> {
+    ear_clip(bridge_holes(outline))
+}
+
+/// Split a simple polygon into convex polygons.
+///
+/// The polygon is triangulated, then neighbouring pieces are merged for as long
+/// as the result stays convex.  This gives at most four times the minimum number
+/// of pieces and is usually much closer to it.
+pub fn convex_decomposition(points: &[Point]) -> Vec<Vec<Point>> {
+    merge_convex(triangulate(points))
+}
+
+/// Split the area covered by an object into convex polygons.
+pub fn convex_decomposition_object(object: &Object) -> Vec<Vec<Point>> {
+    merge_convex(triangulate_object(object))
+}
+
+/// Split a traced outline, holes included, into convex polygons.
+pub fn convex_decomposition_outline(outline: &Outline) -> Vec<Vec<Point>> {
+    merge_convex(triangulate_outline(outline))
+}
+
+// Clip ears off a clockwise polygon without degenerate points.
+fn ear_clip(mut poly: Vec<Point>) -> Vec<Triangle> {
+    let mut triangles = Vec::new();
+    if poly.len() < 3 {
+        return triangles;
+    }
+
+    while poly.len() > 3 {
+        let n = poly.len();
+        let mut ear = Option::None;
+        for i in 0..n {
+            let a = poly[(i + n - 1) % n];
+            let b = poly[i];
+            let c = poly[(i + 1) % n];
+            if cross(a, b, c) <= EPSILON {
+                continue;
+            }
+            let blocked = poly.iter().any(|p| {
+                !same_point(*p, a)
+                    && !same_point(*p, b)
+                    && !same_point(*p, c)
+                    && in_triangle(*p, a, b, c)
+            });
+            if !blocked {
+                ear = Option::Some(i);
+                break;
+            }
+        }
+
+        // A polygon that is not quite simple may have no clean ear left; clip the
+        // most convex corner rather than looping forever.
+        let i = ear.unwrap_or_else(|| {
+            (0..n)
+                .max_by(|x, y| {
+                    let cx = cross(poly[(x + n - 1) % n], poly[*x], poly[(x + 1) % n]);
+                    let cy = cross(poly[(y + n - 1) % n], poly[*y], poly[(y + 1) % n]);
+                    cx.partial_cmp(&cy).unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap_or(0)
+        });
+        let a = poly[(i + n - 1) % n];
+        let c = poly[(i + 1) % n];
+        triangles.extend(clockwise_triangle(a, poly[i], c));
+        poly.remove(i);
+    }
+
+    triangles.extend(clockwise_triangle(poly[0], poly[1], poly[2]));
+    triangles
+}
+
+// The triangle abc in clockwise order, or None when it is degenerate.  Corners
+// clipped by the fallback of ear_clip() may turn the other way.
+fn clockwise_triangle(a: Point, b: Point, c: Point) -> Option<Triangle> {
+    let turn = cross(a, b, c);
+    if turn > EPSILON {
+        Option::Some([a, b, c])
+    } else if turn < -EPSILON {
+        Option::Some([c, b, a])
+    } else {
+        Option::None
+    }
+}
+
+// Join every hole of an outline to the exterior with a pair of coincident
+// edges, giving one clockwise polygon that ear clipping can handle.
+fn bridge_holes(outline: &Outline) -> Vec<Point> {
+    let mut poly = cleanup_polygon(&outline.exterior);
+    normalize_winding(&mut poly, true);
+
+    let mut holes: Vec<Vec<Point>> = outline
+        .holes
+        .iter()
+        .map(|h| {
+            let mut h = cleanup_polygon(h);
+            normalize_winding(&mut h, false);
+            h
+        })
+        .filter(|h| h.len() >= 3)
+        .collect();
+
+    // Bridge the holes furthest to the right first, so that earlier bridges
+    // never cut the way to later holes.
+    holes.sort_by(|a, b| {
+        max_x(b)
+            .partial_cmp(&max_x(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    for (h, hole) in holes.iter().enumerate() {
+        let (mi, m) = hole
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.x.partial_cmp(&b.1.x).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or((0, hole[0]));
+
+        let mut candidates: Vec<usize> = (0..poly.len()).collect();
+        candidates.sort_by(|a, b| {
+            dist2(poly[*a], m)
+                .partial_cmp(&dist2(poly[*b], m))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let visible = candidates.into_iter().find(|v| {
+            let p = poly[*v];
+            !crosses_ring(m, p, &poly) && holes[h..].iter().all(|o| !crosses_ring(m, p, o))
+        });
+        let v = match visible {
+            Option::Some(v) => v,
+            Option::None => continue,
+        };
+
+        let mut bridged = Vec::with_capacity(poly.len() + hole.len() + 2);
+        bridged.extend_from_slice(&poly[..=v]);
+        for k in 0..=hole.len() {
+            bridged.push(hole[(mi + k) % hole.len()]);
+        }
+        bridged.push(poly[v]);
+        bridged.extend_from_slice(&poly[v + 1..]);
+        poly = bridged;
+    }
+    poly
+}
+
+// Merge neighbouring convex pieces for as long as the result stays convex.
+fn merge_convex(triangles: Vec<Triangle>) -> Vec<Vec<Point>> {
+    let mut pieces: Vec<Vec<Point>> = triangles.iter().map(|t| t.to_vec()).collect();
+    let mut merged = true;
+    while merged {
+        merged = false;
+        'search: for i in 0..pieces.len() {
+            for j in (i + 1)..pieces.len() {
+                if let Option::Some(joined) = join_pieces(&pieces[i], &pieces[j]) {
+                    if is_convex(&joined) {
+                        pieces[i] = joined;
+                        pieces.swap_remove(j);
+                        merged = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+    pieces
+}
+
+// Join two clockwise pieces along an edge they share in opposite directions.
+fn join_pieces(a: &[Point], b: &[Point]) -> Option<Vec<Point>> {
+    let (na, nb) = (a.len(), b.len());
+    for i in 0..na {
+        let (a0, a1) = (a[i], a[(i + 1) % na]);
+        for j in 0..nb {
+            if same_point(b[j], a1) && same_point(b[(j + 1) % nb], a0) {
+                // Walk a up to the shared edge, then go around b back to it.
+                let mut out = Vec::with_capacity(na + nb - 2);
+                for k in 0..na {
+                    out.push(a[(i + 1 + k) % na]);
+                }
+                for k in 2..nb {
+                    out.push(b[(j + k) % nb]);
+                }
+                return Option::Some(cleanup_polygon(&out));
+            }
+        }
+    }
+    Option::None
+}
+
+fn is_convex(poly: &[Point]) -> bool {
+    let n = poly.len();
+    n >= 3 && (0..n).all(|i| cross(poly[(i + n - 1) % n], poly[i], poly[(i + 1) % n]) > -EPSILON)
+}
+
+// Does the segment from p to q properly cross an edge of the ring?  Edges
+// touching either end of the segment do not count.
+fn crosses_ring(p: Point, q: Point, ring: &[Point]) -> bool {
+    let n = ring.len();
+    (0..n).any(|i| {
+        let a = ring[i];
+        let b = ring[(i + 1) % n];
+        if same_point(a, p) || same_point(a, q) || same_point(b, p) || same_point(b, q) {
+            return false;
+        }
+        let d1 = cross(p, q, a);
+        let d2 = cross(p, q, b);
+        let d3 = cross(a, b, p);
+        let d4 = cross(a, b, q);
+        (d1 > EPSILON && d2 < -EPSILON || d1 < -EPSILON && d2 > EPSILON)
+            && (d3 > EPSILON && d4 < -EPSILON || d3 < -EPSILON && d4 > EPSILON)
+    })
+}
+
+// Cross product of (b - a) and (c - b).  Positive for a clockwise turn on screen.
+pub(crate) fn cross(a: Point, b: Point, c: Point) -> f64 {
+    (b.x - a.x) * (c.y - b.y) - (b.y - a.y) * (c.x - b.x)
+}
+
+fn in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    cross(a, b, p) >= -EPSILON && cross(b, c, p) >= -EPSILON && cross(c, a, p) >= -EPSILON
+}
+
+pub(crate) fn same_point(a: Point, b: Point) -> bool {
+    (a.x - b.x).abs() < EPSILON && (a.y - b.y).abs() < EPSILON
+}
+
+fn dist2(a: Point, b: Point) -> f64 {
+    (a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y)
+}
+
+fn max_x(points: &[Point]) -> f64 {
+    points.iter().fold(f64::MIN, |m, p| m.max(p.x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cleanup_polygon;
+    use super::convex_decomposition;
+    use super::convex_decomposition_outline;
+    use super::cross;
+    use super::is_clockwise;
+    use super::is_convex;
+    use super::signed_area;
+    use super::triangulate;
+    use super::triangulate_outline;
+    use super::Triangle;
+    use crate::object::Point;
+    use crate::outline::Outline;
+
+    fn points(coords: &[(f64, f64)]) -> Vec<Point> {
+        coords.iter().map(|c| Point::new(c.0, c.1)).collect()
+    }
+
+    // The area covered by triangles, which must all wind clockwise.
+    fn area(triangles: &[Triangle]) -> f64 {
+        assert!(triangles.iter().all(|t| is_clockwise(t)));
+        triangles.iter().map(|t| signed_area(t) / 2.0).sum()
+    }
+
+    #[test]
+    fn repeated_and_collinear_points_are_dropped() {
+        // A counter-clockwise square with points halfway along two sides and
+        // the first point repeated at the end.
+        let square = points(&[
+            (0.0, 0.0),
+            (0.0, 2.0),
+            (0.0, 4.0),
+            (4.0, 4.0),
+            (4.0, 4.0),
+            (4.0, 0.0),
+            (2.0, 0.0),
+            (0.0, 0.0),
+        ]);
+        assert_eq!(cleanup_polygon(&square).len(), 4);
+        let triangles = triangulate(&square);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(area(&triangles), 16.0);
+
+        let line = points(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]);
+        assert!(triangulate(&line).is_empty());
+    }
+
+    #[test]
+    fn concave_polygon_merges_into_two_convex_pieces() {
+        // An L: a 3x1 bar along the top and a 1x2 leg down the left side.
+        let l = points(&[
+            (0.0, 0.0),
+            (3.0, 0.0),
+            (3.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 3.0),
+            (0.0, 3.0),
+        ]);
+        let triangles = triangulate(&l);
+        assert_eq!(triangles.len(), 4);
+        assert_eq!(area(&triangles), 5.0);
+
+        let pieces = convex_decomposition(&l);
+        assert_eq!(pieces.len(), 2);
+        assert!(pieces.iter().all(|p| is_convex(p) && is_clockwise(p)));
+        let total: f64 = pieces.iter().map(|p| signed_area(p) / 2.0).sum();
+        assert_eq!(total, 5.0);
+    }
+
+    #[test]
+    fn holes_are_left_out() {
+        let outline = Outline {
+            exterior: points(&[(0.0, 0.0), (3.0, 0.0), (3.0, 3.0), (0.0, 3.0)]),
+            holes: vec![points(&[(1.0, 1.0), (1.0, 2.0), (2.0, 2.0), (2.0, 1.0)])],
+        };
+        let triangles = triangulate_outline(&outline);
+        assert_eq!(area(&triangles), 8.0);
+        // No triangle covers the middle of the hole.
+        let centre = Point::new(1.5, 1.5);
+        assert!(triangles
+            .iter()
+            .all(|t| { (0..3).any(|i| cross(t[i], t[(i + 1) % 3], centre) < 0.0) }));
+
+        let pieces = convex_decomposition_outline(&outline);
+        assert!(pieces.len() >= 4);
+        assert!(pieces.iter().all(|p| is_convex(p)));
+        let total: f64 = pieces.iter().map(|p| signed_area(p) / 2.0).sum();
+        assert_eq!(total, 8.0);
+    }
+
+    #[test]
+    fn self_crossing_polygons_still_give_clockwise_triangles() {
+        // A figure eight: the right loop goes clockwise, the left one does not,
+        // so no corner of the left loop is a clean ear.
+        let eight = points(&[
+            (0.0, 0.0),
+            (4.0, 4.0),
+            (8.0, 0.0),
+            (8.0, 4.0),
+            (4.0, 0.0),
+            (0.0, 4.0),
+        ]);
+        let triangles = triangulate(&eight);
+        assert!(!triangles.is_empty());
+        assert!(triangles.iter().all(|t| is_clockwise(t)));
+    }
+}
@@ -0,0 +1,449 @@
+//!
+//! Checking maps for mistakes.
+//!
+//! A broken map usually loads fine and only shows up later as glitches at run
+//! time.  Map::validate() looks for the usual mistakes and lists them as
+//! Diagnostic values, which print as one line each:
+//! - gids of tile layers and tile objects with no tileset, or beyond the tiles
+//!   of their tileset,
+//! - tile layers whose data does not hold width * height cells,
+//! - layer and object ids used twice, or not below nextlayerid and
+//!   nextobjectid,
+//! - animation frames showing tiles their tileset does not have,
+//! - tilesets whose gid ranges overlap,
+//! - tilesets with an image but no columns, for which
+//!   Tileset::coord_by_gid() would divide by zero.
+//!
+//! Missing tileset and image files are mistakes too, but the paths in a map are
+//! relative to the map file, which a Map does not know.  Map::validate() leaves
+//! them out; Map::validate_files() checks only them, given the directory of
+//! the map file, and Map::validate_in() runs both.
+//!
+//! Invalid gids are reported once per layer and gid, with the number of cells
+//! using them and the first of those cells.
+//!
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::layer::Layer;
+use crate::map::Map;
+use crate::tileset::Tileset;
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// How bad a problem is.  Errors break loading or drawing the map; warnings
+/// only break editing it further.
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// Where a problem is.  Layers are given by id, when they have one, and name;
+/// objects by id and the name of their layer.
+pub enum Location {
+    Map,
+    Tileset(String),
+    Tile { tileset: String, id: u16 },
+    Layer { id: Option<u32>, name: String },
+    Object { id: u32, layer: String },
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Map => write!(f, "map"),
+            Location::Tileset(name) => write!(f, "tileset {:?}", name),
+            Location::Tile { tileset, id } => write!(f, "tile {} of tileset {:?}", id, tileset),
+            Location::Layer {
+                id: Option::Some(id),
+                name,
+            } => write!(f, "layer {} {:?}", id, name),
+            Location::Layer {
+                id: Option::None,
+                name,
+            } => write!(f, "layer {:?}", name),
+            Location::Object { id, layer } => write!(f, "object {} in layer {:?}", id, layer),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+/// A problem found in a map.  property names the property at fault, for
+/// problems found by Map::validate_properties().
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Location,
+    pub property: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(location: Location, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            location,
+            property: Option::None,
+            message,
+        }
+    }
+
+    pub(crate) fn warning(location: Location, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            location,
+            property: Option::None,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.location)?;
+        if let Option::Some(ref name) = self.property {
+            write!(f, ", property {:?}", name)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Map {
+    /// Check the map for mistakes.  See the module documentation.
+    ///
+    /// Files are not checked, since image paths are relative to the map file;
+    /// use Map::validate_in() to check them as well.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut found = Vec::new();
+        self.validate_tilesets(&mut found);
+
+        let mut layer_ids = HashSet::new();
+        let mut object_ids = HashSet::new();
+        for layer in all_layers(&self.layers) {
+            let location = Location::Layer {
+                id: layer.id,
+                name: layer.name.clone(),
+            };
+            if let Option::Some(id) = layer.id {
+                if !layer_ids.insert(id) {
+                    found.push(Diagnostic::error(
+                        location.clone(),
+                        format!("layer id {} is used more than once", id),
+                    ));
+                }
+                if self.nextlayerid > 0 && id >= self.nextlayerid as u32 {
+                    found.push(Diagnostic::warning(
+                        location.clone(),
+                        format!("id {} is not below nextlayerid {}", id, self.nextlayerid),
+                    ));
+                }
+            }
+            if let Option::Some(data) = layer.get_data() {
+                self.validate_data(&mut found, layer, data, location);
+            }
+            for obj in layer.get_objects_vector().into_iter().flatten() {
+                let location = Location::Object {
+                    id: obj.id,
+                    layer: layer.name.clone(),
+                };
+                if !object_ids.insert(obj.id) {
+                    found.push(Diagnostic::error(
+                        location.clone(),
+                        format!("object id {} is used more than once", obj.id),
+                    ));
+                }
+                if obj.id >= self.nextobjectid {
+                    found.push(Diagnostic::warning(
+                        location.clone(),
+                        format!(
+                            "id {} is not below nextobjectid {}",
+                            obj.id, self.nextobjectid
+                        ),
+                    ));
+                }
+                if let Option::Some(problem) = obj.gid.and_then(|gid| self.gid_problem(gid)) {
+                    found.push(Diagnostic::error(location, problem));
+                }
+            }
+        }
+        found
+    }
+
+    /// Check the map for mistakes, files included.  dir is the directory the
+    /// map was loaded from.  This is Map::validate() followed by
+    /// Map::validate_files().
+    pub fn validate_in(&self, dir: &Path) -> Vec<Diagnostic> {
+        let mut found = self.validate();
+        found.extend(self.validate_files(dir));
+        found
+    }
+
+    /// Check that the images of the map exist: those of tilesets, of tiles in
+    /// image collections and of image layers.  dir is the directory the map
+    /// was loaded from, which image paths are relative to.
+    pub fn validate_files(&self, dir: &Path) -> Vec<Diagnostic> {
+        let mut found = Vec::new();
+        let mut check = |location: Location, image: &str| {
+            if !image.is_empty() && !dir.join(image).is_file() {
+                found.push(Diagnostic::error(
+                    location,
+                    format!("image {:?} does not exist", image),
+                ));
+            }
+        };
+        for ts in self.tilesets.iter() {
+            check(Location::Tileset(ts.name.clone()), &ts.image);
+            for tile in ts.tiles.iter() {
+                if let Option::Some(ref image) = tile.image {
+                    let location = Location::Tile {
+                        tileset: ts.name.clone(),
+                        id: tile.id,
+                    };
+                    check(location, image);
+                }
+            }
+        }
+        for layer in all_layers(&self.layers) {
+            if let Option::Some(image) = layer.get_image() {
+                let location = Location::Layer {
+                    id: layer.id,
+                    name: layer.name.clone(),
+                };
+                check(location, image);
+            }
+        }
+        found
+    }
+
+    fn validate_tilesets(&self, found: &mut Vec<Diagnostic>) {
+        let mut ranges: Vec<&Tileset> = self.tilesets.iter().collect();
+        ranges.sort_by_key(|ts| ts.firstgid);
+        for pair in ranges.windows(2) {
+            let end = pair[0].firstgid + pair[0].gid_span();
+            if end > pair[1].firstgid {
+                found.push(Diagnostic::error(
+                    Location::Tileset(pair[1].name.clone()),
+                    format!(
+                        "gids {}..{} overlap those of tileset {:?}, {}..{}",
+                        pair[1].firstgid,
+                        pair[1].firstgid + pair[1].gid_span(),
+                        pair[0].name,
+                        pair[0].firstgid,
+                        end
+                    ),
+                ));
+            }
+        }
+
+        for ts in self.tilesets.iter() {
+            let location = Location::Tileset(ts.name.clone());
+            if ts.firstgid == 0 {
+                found.push(Diagnostic::error(
+                    location.clone(),
+                    "firstgid is 0, which marks empty cells".to_string(),
+                ));
+            }
+            if !ts.image.is_empty() && ts.columns == 0 {
+                found.push(Diagnostic::error(
+                    location,
+                    "the tileset has an image but 0 columns".to_string(),
+                ));
+            }
+            for tile in ts.tiles.iter() {
+                for frame in tile.animation.iter() {
+                    if !has_tile(ts, frame.tileid as u32) {
+                        found.push(Diagnostic::error(
+                            Location::Tile {
+                                tileset: ts.name.clone(),
+                                id: tile.id,
+                            },
+                            format!("animation frame shows missing tile {}", frame.tileid),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    fn validate_data(
+        &self,
+        found: &mut Vec<Diagnostic>,
+        layer: &Layer,
+        data: &[u32],
+        location: Location,
+    ) {
+        let cells = layer.width as usize * layer.height as usize;
+        if data.len() != cells {
+            found.push(Diagnostic::error(
+                location.clone(),
+                format!(
+                    "data holds {} cells instead of {}x{} = {}",
+                    data.len(),
+                    layer.width,
+                    layer.height,
+                    cells
+                ),
+            ));
+        }
+
+        // gid -> (problem, first cell, count)
+        let mut bad: BTreeMap<u32, (String, usize, usize)> = BTreeMap::new();
+        for (i, gid) in data.iter().enumerate() {
+            if let Option::Some(entry) = bad.get_mut(gid) {
+                entry.2 += 1;
+            } else if let Option::Some(problem) = self.gid_problem(*gid) {
+                bad.insert(*gid, (problem, i, 1));
+            }
+        }
+        let width = (layer.width as usize).max(1);
+        for (_, (problem, first, count)) in bad.into_iter() {
+            let (x, y) = (first % width, first / width);
+            let cells = if count == 1 {
+                format!("1 cell, at ({}, {})", x, y)
+            } else {
+                format!("{} cells, the first at ({}, {})", count, x, y)
+            };
+            found.push(Diagnostic::error(
+                location.clone(),
+                format!("{} ({})", problem, cells),
+            ));
+        }
+    }
+
+    // What is wrong with a gid, if anything.  Gid 0 is an empty cell.
+    fn gid_problem(&self, gid: u32) -> Option<String> {
+        let raw = crate::gid_without_flags(gid);
+        if raw == 0 {
+            return Option::None;
+        }
+        let ts = match self.tileset_by_gid(raw) {
+            Option::Some(ts) => ts,
+            Option::None => return Option::Some(format!("gid {} has no tileset", raw)),
+        };
+        let end = ts.firstgid + ts.gid_span();
+        if raw >= end {
+            Option::Some(format!(
+                "gid {} is beyond tileset {:?}, which holds gids {}..{}",
+                raw, ts.name, ts.firstgid, end
+            ))
+        } else if !has_tile(ts, raw - ts.firstgid) {
+            Option::Some(format!(
+                "gid {} is tile {} of tileset {:?}, which has no such tile",
+                raw,
+                raw - ts.firstgid,
+                ts.name
+            ))
+        } else {
+            Option::None
+        }
+    }
+}
+
+// Whether a tileset has a tile with a local id: a tile of its image, or one of
+// the tiles listed by an image collection, whose ids may leave gaps.
+fn has_tile(ts: &Tileset, id: u32) -> bool {
+    if ts.image.is_empty() {
+        ts.tiles.iter().any(|t| t.id as u32 == id)
+    } else {
+        id < ts.tilecount
+    }
+}
+
+// Every layer, groups before their layers.
+pub(crate) fn all_layers(layers: &[Layer]) -> Vec<&Layer> {
+    let mut found = Vec::new();
+    for layer in layers.iter() {
+        found.push(layer);
+        if let Option::Some(children) = layer.get_layers() {
+            found.extend(all_layers(children));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::builder::MapBuilder;
+    use crate::layer::Layer;
+    use crate::map::Map;
+    use crate::map::MapOrientation;
+    use crate::tileset::Tile;
+    use crate::tileset::Tileset;
+
+    // A map with an image collection holding tiles 0 and 5, gids 1..7, and a
+    // tile layer whose first cells hold gids.
+    fn collection_map(gids: &[u32]) -> Map {
+        let mut collection = Tileset::new("props", "", 0, 0, 16, 16);
+        collection.tilecount = 2;
+        for id in [0, 5].iter() {
+            collection.tiles.push(Tile {
+                id: *id,
+                image: Option::Some(format!("tile{}.png", id)),
+                imageheight: 16,
+                imagewidth: 16,
+                ttype: Option::None,
+                objectgroup: Option::None,
+                animation: Vec::new(),
+                properties: Vec::new(),
+            });
+        }
+        let mut builder = MapBuilder::new(MapOrientation::Orthogonal, 4, 1, 16, 16);
+        builder.add_tileset(collection);
+        let id = builder.add_layer(Layer::new_tile_layer("ground")).unwrap();
+        for (x, gid) in gids.iter().enumerate() {
+            builder.layer_mut(id).unwrap().set_gid(x as u32, 0, *gid);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn sparse_collection_gids() {
+        assert!(collection_map(&[1, 6]).validate().is_empty());
+
+        let found = collection_map(&[3, 7, 3]).validate();
+        let messages: Vec<String> = found.iter().map(|d| d.message.clone()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "gid 3 is tile 2 of tileset \"props\", which has no such tile (2 cells, the first at (0, 0))",
+                "gid 7 is beyond tileset \"props\", which holds gids 1..7 (1 cell, at (1, 0))",
+            ]
+        );
+
+        // Tile 1 is below the tile count of the collection, but no tile has
+        // that id.
+        let found = collection_map(&[2]).validate();
+        let messages: Vec<String> = found.iter().map(|d| d.message.clone()).collect();
+        assert_eq!(
+            messages,
+            vec!["gid 2 is tile 1 of tileset \"props\", which has no such tile (1 cell, at (0, 0))"]
+        );
+    }
+
+    #[test]
+    fn validate_in_checks_files() {
+        let map = collection_map(&[1]);
+        assert!(map.validate().is_empty());
+        let found = map.validate_in(Path::new("/nonexistent"));
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[0].to_string(),
+            "error: tile 0 of tileset \"props\": image \"tile0.png\" does not exist"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::process::Command;
+use std::process::Output;
+
+fn tiled_json(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tiled-json"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// The lines printed between the heading of a section and the next one.
+fn section(stdout: &str, heading: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .skip_while(|l| *l != heading)
+        .skip(1)
+        .take_while(|l| l.starts_with("  "))
+        .map(|l| l.to_string())
+        .collect()
+}
+
+#[test]
+fn info_lists_gid_ranges_and_the_layer_tree() {
+    let output = tiled_json(&["info", "tests/fixtures/info.json"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.starts_with(
+        "tests/fixtures/info.json\n  orthogonal map, 3x2 tiles of 16x16 pixels, 48x32 pixels\n"
+    ));
+    assert_eq!(
+        section(&stdout, "tilesets:"),
+        vec![
+            "  \"terrain\": gids 1..5, 4 tiles of 16x16, image terrain.png",
+            "  \"props\": gids 5..8, 3 tiles of 32x32, image collection",
+        ]
+    );
+    assert_eq!(
+        section(&stdout, "layers:"),
+        vec![
+            "  tilelayer 1 \"ground\": 3x2, 4 of 6 cells set",
+            "  group 2 \"decor\": 2 layers",
+            "    objectgroup 3 \"things\": 2 objects",
+            "    imagelayer 4 \"sky\": image sky.png, hidden",
+        ]
+    );
+    assert_eq!(
+        section(&stdout, "objects:"),
+        vec!["  (no type): 1", "  chest: 1"]
+    );
+    assert_eq!(
+        section(&stdout, "properties:"),
+        vec![
+            "  map: music (file) = \"town.ogg\"",
+            "  object 2: locked (bool) = true",
+        ]
+    );
+}
+
+#[test]
+fn info_fails_on_missing_files_and_bad_usage() {
+    let output = tiled_json(&["info", "tests/fixtures/info.json", "no/such/map.json"]);
+    assert_eq!(output.status.code(), Option::Some(1));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("\"terrain\": gids 1..5"));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("no/such/map.json: "));
+
+    assert_eq!(tiled_json(&[]).status.code(), Option::Some(2));
+    assert_eq!(tiled_json(&["info"]).status.code(), Option::Some(2));
+}